@@ -0,0 +1,16 @@
+pragma circom 2.1.0;
+
+// compile with `circom --prime goldilocks`
+template Main() {
+    signal input z;
+    signal output result;
+    signal _w2;
+    _w2 <== z * 32;
+    signal y;
+    y <== _w2 - 1;
+    signal _w6;
+    _w6 <== y + 16;
+    result <== _w6;
+}
+
+component main {public [z]} = Main();
@@ -0,0 +1,14 @@
+pragma circom 2.1.0;
+
+// compile with `circom --prime goldilocks`
+template Main() {
+    signal input p;
+    signal output result;
+    signal c;
+    c <== p + 1;
+    signal _w3;
+    _w3 <== p * c;
+    result <== _w3;
+}
+
+component main {public [p]} = Main();
@@ -0,0 +1,25 @@
+pragma circom 2.1.0;
+
+// compile with `circom --prime goldilocks`
+template Main() {
+    signal input x;
+    signal input y;
+    signal output result;
+    signal _w2;
+    _w2 <== y * x;
+    signal v;
+    v <== _w2 + 2;
+    signal _w5;
+    _w5 <== v * v;
+    signal w;
+    w <== _w5 + 1;
+    signal _w8;
+    _w8 <== -w;
+    signal _w9;
+    _w9 <== _w8 + _w5;
+    signal _w10;
+    _w10 <== _w9 + 1;
+    result <== _w10;
+}
+
+component main {public [x, y]} = Main();
@@ -0,0 +1,12 @@
+pragma circom 2.1.0;
+
+// compile with `circom --prime goldilocks`
+template Main() {
+    signal input z;
+    signal output result;
+    signal _w2;
+    _w2 <== z + 25;
+    result <== _w2;
+}
+
+component main {public [z]} = Main();
@@ -0,0 +1,39 @@
+pragma circom 2.1.0;
+
+// compile with `circom --prime goldilocks`
+template Main() {
+    signal input x;
+    signal input y;
+    signal output result;
+    signal _w3;
+    _w3 <== x * x;
+    signal _w4;
+    _w4 <== x * 2;
+    signal _w5;
+    _w5 <== _w3 + _w4;
+    signal _w7;
+    _w7 <== _w5 + 1;
+    signal f;
+    f <== _w7 - y;
+    signal _w10_inv;
+    _w10_inv <-- f != 0 ? 1 / f : 0;
+    signal _w10;
+    _w10 <== 1 - f * _w10_inv;
+    f * _w10 === 0;
+    signal _w12;
+    _w12 <== x + 7;
+    signal g;
+    g <== _w12 - y;
+    signal _w14_inv;
+    _w14_inv <-- g != 0 ? 1 / g : 0;
+    signal _w14;
+    _w14 <== 1 - g * _w14_inv;
+    g * _w14 === 0;
+    signal _w15;
+    _w15 <== _w10 * _w14;
+    signal _w17;
+    _w17 <== _w15 * 42;
+    result <== _w17;
+}
+
+component main {public [x, y]} = Main();
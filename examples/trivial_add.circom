@@ -0,0 +1,14 @@
+pragma circom 2.1.0;
+
+// compile with `circom --prime goldilocks`
+template Main() {
+    signal input z;
+    signal output result;
+    signal _w2;
+    _w2 <== z * 2;
+    signal _w4;
+    _w4 <== _w2 + 1;
+    result <== _w4;
+}
+
+component main {public [z]} = Main();
@@ -28,6 +28,13 @@ impl Default for Span {
     }
 }
 
+impl Span {
+    // the line the span starts on, counting from 1 (0 for the default span)
+    pub fn line(&self) -> u32 {
+        self.start.line
+    }
+}
+
 impl Display for Span {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         if self.start.line == self.end.line {
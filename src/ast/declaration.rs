@@ -1,7 +1,8 @@
 use crate::ast::annotation::{HasSourceLoc, Span};
+use crate::ast::error::ASTError;
 use crate::ast::expression::{Expr, Ident};
 use crate::ast::typechecker::{Ty, TypeContext};
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
@@ -33,6 +34,12 @@ impl<A> Binder<A> {
             Binder::TypedBinder { ann, .. } => ann,
         }
     }
+    pub fn ty(&self) -> Option<Ty> {
+        match self {
+            Binder::VarBinder { .. } => None,
+            Binder::TypedBinder { _type, .. } => Some(_type.clone()),
+        }
+    }
 }
 
 impl<A: Default> Binder<A> {
@@ -80,6 +87,10 @@ impl<A: Clone> Clone for Binder<A> {
 pub enum Declaration<A> {
     VarAssignment { binder: Binder<A>, expr: Expr<A> },
     PublicVar { binder: Binder<A> },
+    // an input known only to the prover, it is not exposed by the proof
+    PrivateVar { binder: Binder<A> },
+    // an assignment whose value is exposed by the proof
+    Output { binder: Binder<A>, expr: Expr<A> },
 }
 
 impl<A> Clone for Declaration<A>
@@ -97,6 +108,13 @@ where
             Declaration::PublicVar { binder } => Declaration::PublicVar {
                 binder: binder.clone(),
             },
+            Declaration::PrivateVar { binder } => Declaration::PrivateVar {
+                binder: binder.clone(),
+            },
+            Declaration::Output { binder, expr } => Declaration::Output {
+                binder: binder.clone(),
+                expr: expr.clone(),
+            },
         }
     }
 }
@@ -106,6 +124,8 @@ impl<A> Declaration<A> {
         match self {
             Declaration::VarAssignment { binder, .. } => binder,
             Declaration::PublicVar { binder, .. } => binder,
+            Declaration::PrivateVar { binder, .. } => binder,
+            Declaration::Output { binder, .. } => binder,
         }
     }
 }
@@ -129,21 +149,28 @@ impl<A: Clone> Declaration<A> {
             Declaration::PublicVar { binder } => Declaration::PublicVar {
                 binder: binder.clear_annotations(),
             },
+            Declaration::PrivateVar { binder } => Declaration::PrivateVar {
+                binder: binder.clear_annotations(),
+            },
+            Declaration::Output { binder, expr } => Declaration::Output {
+                binder: binder.clear_annotations(),
+                expr: expr.clear_annotations(),
+            },
         }
     }
 }
 
 impl<A: Clone + PartialEq> Declaration<A> {
     // get all the free variables in the expression bound in this declaration
-    // (none for public variables)
+    // (none for public and private variables)
     pub fn get_dependencies(&self) -> Vec<(Ident, A)> {
         match self {
-            Declaration::VarAssignment { expr, .. } => {
+            Declaration::VarAssignment { expr, .. } | Declaration::Output { expr, .. } => {
                 let mut vars = expr.variables();
                 vars.dedup();
                 vars
             }
-            Declaration::PublicVar { .. } => vec![],
+            Declaration::PublicVar { .. } | Declaration::PrivateVar { .. } => vec![],
         }
     }
 }
@@ -151,18 +178,30 @@ impl<A: Clone + PartialEq> Declaration<A> {
 impl<A: Clone + HasSourceLoc> Declaration<A> {
     pub fn typecheck(&self, context: &mut TypeContext) -> Result<()> {
         match self {
-            Declaration::VarAssignment { binder, expr } => {
+            Declaration::VarAssignment { binder, expr } | Declaration::Output { binder, expr } => {
                 let expr_ty = expr.typecheck(context)?;
+                if let Binder::TypedBinder { ann, _type, .. } = binder {
+                    if *_type != expr_ty {
+                        return Err(anyhow!(ASTError::TypeMismatch(
+                            ann.source_loc(),
+                            _type.clone(),
+                            expr.source_loc(),
+                            expr_ty,
+                        )));
+                    }
+                }
                 context.context.insert(binder.var().clone(), expr_ty);
                 Ok(())
             }
-            Declaration::PublicVar { binder } => match binder {
-                Binder::TypedBinder { var, _type, .. } => {
-                    context.context.insert(var.clone(), _type.clone());
-                    Ok(())
+            Declaration::PublicVar { binder } | Declaration::PrivateVar { binder } => {
+                match binder {
+                    Binder::TypedBinder { var, _type, .. } => {
+                        context.context.insert(var.clone(), _type.clone());
+                        Ok(())
+                    }
+                    _ => Ok(()),
                 }
-                _ => Ok(()),
-            },
+            }
         }
     }
 }
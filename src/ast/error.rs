@@ -1,5 +1,5 @@
 use crate::ast::annotation::Span;
-use crate::ast::expression::Ident;
+use crate::ast::expression::{Builtin, Ident};
 use crate::ast::typechecker::Ty;
 use err_derive::Error;
 
@@ -18,4 +18,35 @@ pub enum ASTError {
         _3
     )]
     TypeMismatch(Span, Ty, Span, Ty),
+    #[error(
+        display = "Type Error at {}. Expected an unsigned integer type, found {}",
+        _0,
+        _1
+    )]
+    ExpectedUnsignedInteger(Span, Ty),
+    #[error(display = "Type Error at {}. Cannot cast {} to {}", _0, _1, _2)]
+    InvalidCast(Span, Ty, Ty),
+    #[error(
+        display = "Type Error at {}. {} expects {} but was called with {}",
+        _0,
+        _1,
+        _2,
+        _3
+    )]
+    ArityMismatch(Span, Builtin, String, usize),
+    #[error(display = "Type Error at {}. Expected an integer constant", _0)]
+    ExpectedConstant(Span),
+    #[error(display = "Type Error at {}. Invalid bit width {}", _0, _1)]
+    InvalidBitWidth(Span, i128),
+    #[error(display = "Type Error at {}. Expected an array, found {}", _0, _1)]
+    ExpectedArray(Span, Ty),
+    #[error(
+        display = "Type Error at {}. Index {} is out of bounds for an array of length {}",
+        _0,
+        _1,
+        _2
+    )]
+    IndexOutOfBounds(Span, usize, usize),
+    #[error(display = "Program has no outputs, declare one with `out` or end with an expression")]
+    MissingOutput,
 }
@@ -1,4 +1,4 @@
-use crate::ast::annotation::HasSourceLoc;
+use crate::ast::annotation::{HasSourceLoc, Span};
 use crate::ast::error::ASTError;
 use anyhow::{anyhow, Result};
 use derive_more::Display;
@@ -7,7 +7,7 @@ use std::fmt::Display;
 
 use super::typechecker::{Ty, TypeContext};
 
-#[derive(Debug, PartialEq, Serialize, Deserialize, Copy, Clone)]
+#[derive(Debug, PartialEq, Eq, Hash, Serialize, Deserialize, Copy, Clone)]
 pub enum Opcode {
     Add,
     Sub,
@@ -16,13 +16,95 @@ pub enum Opcode {
     And,
     Or,
     Eq,
+    WrappingAdd,
+    WrappingSub,
+    WrappingMul,
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Hash, Serialize, Deserialize, Clone, Copy)]
 pub enum UOpcode {
     Neg,
 }
 
+// functions provided by the language, they can't be defined by the user
+#[derive(Debug, PartialEq, Eq, Hash, Serialize, Deserialize, Clone, Copy)]
+pub enum Builtin {
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
+    ToBits,
+    // the Poseidon digest of the arguments, four field elements. Arrays are hashed element by
+    // element, so digests can be hashed again.
+    Poseidon,
+    // whether a leaf digest is in a Merkle tree whose inner nodes are the `poseidon` digests
+    // of their two children
+    MerkleVerify,
+}
+
+impl Builtin {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "bit_and" => Some(Builtin::BitAnd),
+            "bit_or" => Some(Builtin::BitOr),
+            "bit_xor" => Some(Builtin::BitXor),
+            "shl" => Some(Builtin::Shl),
+            "shr" => Some(Builtin::Shr),
+            "to_bits" => Some(Builtin::ToBits),
+            "poseidon" => Some(Builtin::Poseidon),
+            "merkle_verify" => Some(Builtin::MerkleVerify),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Builtin::BitAnd => "bit_and",
+            Builtin::BitOr => "bit_or",
+            Builtin::BitXor => "bit_xor",
+            Builtin::Shl => "shl",
+            Builtin::Shr => "shr",
+            Builtin::ToBits => "to_bits",
+            Builtin::Poseidon => "poseidon",
+            Builtin::MerkleVerify => "merkle_verify",
+        }
+    }
+
+    // the number of arguments, or `None` if any non-zero number is accepted
+    pub fn arity(&self) -> Option<usize> {
+        match self {
+            Builtin::BitAnd | Builtin::BitOr | Builtin::BitXor => Some(2),
+            Builtin::Shl | Builtin::Shr => Some(2),
+            Builtin::ToBits => Some(2),
+            Builtin::Poseidon => None,
+            Builtin::MerkleVerify => Some(4),
+        }
+    }
+
+    // an `ArityMismatch` error located at `span` unless `num_args` arguments are accepted
+    pub fn check_arity(&self, span: Span, num_args: usize) -> Result<()> {
+        let arity = self.arity();
+        if arity.map_or(num_args > 0, |arity| num_args == arity) {
+            return Ok(());
+        }
+        let expected = match arity {
+            Some(1) => "1 argument".to_string(),
+            Some(arity) => format!("{} arguments", arity),
+            None => "at least 1 argument".to_string(),
+        };
+        Err(anyhow!(ASTError::ArityMismatch(
+            span, *self, expected, num_args
+        )))
+    }
+}
+
+impl Display for Builtin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Deserialize, Serialize, Display)]
 pub struct Ident(String);
 
@@ -32,10 +114,50 @@ impl Ident {
     }
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize, Copy, Clone)]
+#[derive(Debug, PartialEq, Eq, Hash, Serialize, Deserialize, Clone)]
 pub enum Literal {
     Boolean(bool),
-    Field(i32),
+    Field(i128),
+    U8(u8),
+    U32(u32),
+    U64(u64),
+    // arrays can't be written in programs, they only come from the input context
+    Array(Vec<Literal>),
+}
+
+// the order of the Goldilocks field the circuits are over. Field literals are the integers
+// strictly between minus the order and the order, they are reduced when they are used.
+pub const FIELD_ORDER: i128 = 0xFFFF_FFFF_0000_0001;
+
+impl Literal {
+    // the field literal `n`, if it is in range
+    pub fn field(n: i128) -> Option<Literal> {
+        (-FIELD_ORDER < n && n < FIELD_ORDER).then_some(Literal::Field(n))
+    }
+
+    // the literal with its field elements reduced to their canonical value, two literals are
+    // the same value exactly when their canonical forms are equal
+    pub fn canonical(&self) -> Literal {
+        match self {
+            Literal::Field(n) => Literal::Field(n.rem_euclid(FIELD_ORDER)),
+            Literal::Array(elems) => Literal::Array(elems.iter().map(Literal::canonical).collect()),
+            lit => lit.clone(),
+        }
+    }
+
+    pub fn ty(&self) -> Ty {
+        match self {
+            Literal::Boolean(_) => Ty::Boolean,
+            Literal::Field(_) => Ty::Field,
+            Literal::U8(_) => Ty::U8,
+            Literal::U32(_) => Ty::U32,
+            Literal::U64(_) => Ty::U64,
+            Literal::Array(elems) => {
+                let elem_ty = elems.first().map_or(Ty::Field, |elem| elem.ty());
+                Ty::Array(Box::new(elem_ty), elems.len())
+            }
+        }
+    }
 }
 
 impl Display for Literal {
@@ -43,6 +165,18 @@ impl Display for Literal {
         match self {
             Literal::Field(n) => write!(f, "{}", n),
             Literal::Boolean(b) => write!(f, "{}", b),
+            Literal::U8(n) => write!(f, "{}u8", n),
+            Literal::U32(n) => write!(f, "{}u32", n),
+            Literal::U64(n) => write!(f, "{}u64", n),
+            Literal::Array(elems) => write!(
+                f,
+                "[{}]",
+                elems
+                    .iter()
+                    .map(|elem| elem.to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
         }
     }
 }
@@ -74,6 +208,26 @@ pub enum Expr<A> {
         _then: Box<Expr<A>>,
         _else: Box<Expr<A>>,
     },
+    Ascription {
+        ann: A,
+        expr: Box<Expr<A>>,
+        _type: Ty,
+    },
+    Cast {
+        ann: A,
+        expr: Box<Expr<A>>,
+        _type: Ty,
+    },
+    Call {
+        ann: A,
+        func: Builtin,
+        args: Vec<Expr<A>>,
+    },
+    Index {
+        ann: A,
+        expr: Box<Expr<A>>,
+        index: usize,
+    },
 }
 
 impl<A: Clone> Clone for Expr<A> {
@@ -81,7 +235,7 @@ impl<A: Clone> Clone for Expr<A> {
         match self {
             Expr::Literal { ann, value } => Expr::Literal {
                 ann: ann.clone(),
-                value: *value,
+                value: value.clone(),
             },
             Expr::Variable { ann, value } => Expr::Variable {
                 ann: ann.clone(),
@@ -109,6 +263,26 @@ impl<A: Clone> Clone for Expr<A> {
                 _then: Box::new((**_then).clone()),
                 _else: Box::new((**_else).clone()),
             },
+            Expr::Ascription { ann, expr, _type } => Expr::Ascription {
+                ann: ann.clone(),
+                expr: Box::new((**expr).clone()),
+                _type: _type.clone(),
+            },
+            Expr::Cast { ann, expr, _type } => Expr::Cast {
+                ann: ann.clone(),
+                expr: Box::new((**expr).clone()),
+                _type: _type.clone(),
+            },
+            Expr::Call { ann, func, args } => Expr::Call {
+                ann: ann.clone(),
+                func: *func,
+                args: args.to_vec(),
+            },
+            Expr::Index { ann, expr, index } => Expr::Index {
+                ann: ann.clone(),
+                expr: Box::new((**expr).clone()),
+                index: *index,
+            },
         }
     }
 }
@@ -128,6 +302,9 @@ impl<A> Expr<A> {
                 Opcode::And => format!("({} && {})", lhs.format(), rhs.format()),
                 Opcode::Or => format!("({} || {})", lhs.format(), rhs.format()),
                 Opcode::Eq => format!("({} == {})", lhs.format(), rhs.format()),
+                Opcode::WrappingAdd => format!("({} +% {})", lhs.format(), rhs.format()),
+                Opcode::WrappingSub => format!("({} -% {})", lhs.format(), rhs.format()),
+                Opcode::WrappingMul => format!("({} *% {})", lhs.format(), rhs.format()),
             },
             Expr::Variable { value, .. } => value.to_string(),
             Expr::IfThenElse {
@@ -138,6 +315,17 @@ impl<A> Expr<A> {
                 _then.format(),
                 _else.format()
             ),
+            Expr::Ascription { expr, _type, .. } => format!("({} : {})", expr.format(), _type),
+            Expr::Cast { expr, _type, .. } => format!("({} as {})", expr.format(), _type),
+            Expr::Call { func, args, .. } => format!(
+                "{}({})",
+                func,
+                args.iter()
+                    .map(|arg| arg.format())
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+            Expr::Index { expr, index, .. } => format!("{}[{}]", expr.format(), index),
         }
     }
 }
@@ -162,6 +350,10 @@ impl<A: Clone> Expr<A> {
                 deps.append(&mut _else.variables());
                 deps
             }
+            Expr::Ascription { expr, .. } => expr.variables(),
+            Expr::Cast { expr, .. } => expr.variables(),
+            Expr::Call { args, .. } => args.iter().flat_map(|arg| arg.variables()).collect(),
+            Expr::Index { expr, .. } => expr.variables(),
         }
     }
 
@@ -188,6 +380,29 @@ impl<A: Clone> Expr<A> {
                 _then: Box::new(_then.clear_annotations()),
                 _else: Box::new(_else.clear_annotations()),
             },
+            Expr::Ascription { expr, _type, .. } => Expr::Ascription {
+                ann: (),
+                expr: Box::new(expr.clear_annotations()),
+                _type,
+            },
+            Expr::Cast { expr, _type, .. } => Expr::Cast {
+                ann: (),
+                expr: Box::new(expr.clear_annotations()),
+                _type,
+            },
+            Expr::Call { func, args, .. } => Expr::Call {
+                ann: (),
+                func,
+                args: args
+                    .into_iter()
+                    .map(|arg| arg.clear_annotations())
+                    .collect(),
+            },
+            Expr::Index { expr, index, .. } => Expr::Index {
+                ann: (),
+                expr: Box::new(expr.clear_annotations()),
+                index,
+            },
         }
     }
 }
@@ -200,12 +415,16 @@ impl<A: HasSourceLoc> HasSourceLoc for Expr<A> {
             Expr::BinOp { ann, .. } => ann.source_loc(),
             Expr::Variable { ann, .. } => ann.source_loc(),
             Expr::IfThenElse { ann, .. } => ann.source_loc(),
+            Expr::Ascription { ann, .. } => ann.source_loc(),
+            Expr::Cast { ann, .. } => ann.source_loc(),
+            Expr::Call { ann, .. } => ann.source_loc(),
+            Expr::Index { ann, .. } => ann.source_loc(),
         }
     }
 }
 
 impl<A: Default> Expr<A> {
-    pub fn field_default(value: i32) -> Self {
+    pub fn field_default(value: i128) -> Self {
         Expr::Literal {
             ann: A::default(),
             value: Literal::Field(value),
@@ -242,16 +461,21 @@ impl<A: Default> Expr<A> {
             rhs: Box::new(rhs),
         }
     }
+
+    pub fn call_default(func: Builtin, args: Vec<Expr<A>>) -> Self {
+        Expr::Call {
+            ann: A::default(),
+            func,
+            args,
+        }
+    }
 }
 
 impl<A: Clone + HasSourceLoc> Expr<A> {
     pub fn typecheck(&self, context: &TypeContext) -> Result<Ty> {
         match self {
-            Expr::Literal { value, .. } => match value {
-                Literal::Field(_) => Ok(Ty::Field),
-                Literal::Boolean(_) => Ok(Ty::Boolean),
-            },
-            Expr::Variable { ann, value } => match context.context.get(&value) {
+            Expr::Literal { value, .. } => Ok(value.ty()),
+            Expr::Variable { ann, value } => match context.context.get(value) {
                 Some(ty) => Ok(ty.clone()),
                 None => Err(anyhow!(ASTError::UnboundIdentifier(
                     ann.source_loc(),
@@ -276,15 +500,22 @@ impl<A: Clone + HasSourceLoc> Expr<A> {
                 let lhs_ty = lhs.typecheck(context)?;
                 let rhs_ty = rhs.typecheck(context)?;
                 match op {
-                    Opcode::Add | Opcode::Sub | Opcode::Mul | Opcode::Pow => match (lhs_ty, rhs_ty)
-                    {
+                    // checked arithmetic works on fields and on unsigned integers of the same width
+                    Opcode::Add | Opcode::Sub | Opcode::Mul => match (&lhs_ty, &rhs_ty) {
                         (Ty::Field, Ty::Field) => Ok(Ty::Field),
+                        _ if lhs_ty.is_uint() && lhs_ty == rhs_ty => Ok(lhs_ty),
                         (Ty::Field, _) => Err(anyhow!(ASTError::TypeMismatch(
                             ann.source_loc(),
                             Ty::Field,
                             rhs.source_loc(),
                             rhs_ty,
                         ))),
+                        _ if lhs_ty.is_uint() => Err(anyhow!(ASTError::TypeMismatch(
+                            ann.source_loc(),
+                            lhs_ty,
+                            rhs.source_loc(),
+                            rhs_ty,
+                        ))),
                         _ => Err(anyhow!(ASTError::TypeMismatch(
                             ann.source_loc(),
                             Ty::Field,
@@ -292,7 +523,40 @@ impl<A: Clone + HasSourceLoc> Expr<A> {
                             lhs_ty,
                         ))),
                     },
-                    Opcode::And | Opcode::Or => match (lhs_ty, rhs_ty) {
+                    // a field exponent must be a constant, which the compiler checks once
+                    // it is folded, an integer one is decomposed into its bits
+                    Opcode::Pow => match (&lhs_ty, &rhs_ty) {
+                        (Ty::Field, Ty::Field) => Ok(Ty::Field),
+                        (Ty::Field, _) if rhs_ty.is_uint() => Ok(Ty::Field),
+                        (Ty::Field, _) => Err(anyhow!(ASTError::TypeMismatch(
+                            ann.source_loc(),
+                            Ty::Field,
+                            rhs.source_loc(),
+                            rhs_ty,
+                        ))),
+                        _ => Err(anyhow!(ASTError::TypeMismatch(
+                            ann.source_loc(),
+                            Ty::Field,
+                            lhs.source_loc(),
+                            lhs_ty,
+                        ))),
+                    },
+                    Opcode::WrappingAdd | Opcode::WrappingSub | Opcode::WrappingMul => {
+                        match (&lhs_ty, &rhs_ty) {
+                            _ if lhs_ty.is_uint() && lhs_ty == rhs_ty => Ok(lhs_ty),
+                            _ if lhs_ty.is_uint() => Err(anyhow!(ASTError::TypeMismatch(
+                                ann.source_loc(),
+                                lhs_ty,
+                                rhs.source_loc(),
+                                rhs_ty,
+                            ))),
+                            _ => Err(anyhow!(ASTError::ExpectedUnsignedInteger(
+                                lhs.source_loc(),
+                                lhs_ty,
+                            ))),
+                        }
+                    }
+                    Opcode::And | Opcode::Or => match (&lhs_ty, &rhs_ty) {
                         (Ty::Boolean, Ty::Boolean) => Ok(Ty::Boolean),
                         (Ty::Boolean, _) => Err(anyhow!(ASTError::TypeMismatch(
                             ann.source_loc(),
@@ -307,14 +571,21 @@ impl<A: Clone + HasSourceLoc> Expr<A> {
                             lhs_ty,
                         ))),
                     },
-                    Opcode::Eq => match (lhs_ty, rhs_ty) {
+                    Opcode::Eq => match (&lhs_ty, &rhs_ty) {
                         (Ty::Field, Ty::Field) => Ok(Ty::Boolean),
+                        _ if lhs_ty.is_uint() && lhs_ty == rhs_ty => Ok(Ty::Boolean),
                         (Ty::Field, _) => Err(anyhow!(ASTError::TypeMismatch(
                             ann.source_loc(),
                             Ty::Field,
                             rhs.source_loc(),
                             rhs_ty,
                         ))),
+                        _ if lhs_ty.is_uint() => Err(anyhow!(ASTError::TypeMismatch(
+                            ann.source_loc(),
+                            lhs_ty,
+                            rhs.source_loc(),
+                            rhs_ty,
+                        ))),
                         _ => Err(anyhow!(ASTError::TypeMismatch(
                             ann.source_loc(),
                             Ty::Field,
@@ -334,15 +605,18 @@ impl<A: Clone + HasSourceLoc> Expr<A> {
                 let _then_ty = _then.typecheck(context)?;
                 let _else_ty = _else.typecheck(context)?;
                 match cond_ty {
-                    Ty::Boolean => match (_then_ty, _else_ty) {
-                        (Ty::Field, Ty::Field) => Ok(Ty::Field),
-                        _ => Err(anyhow!(ASTError::TypeMismatch(
-                            ann.source_loc(),
-                            Ty::Field,
-                            _then.clone().source_loc(),
-                            _then_ty,
-                        ))),
-                    },
+                    Ty::Boolean => {
+                        if _then_ty == _else_ty {
+                            Ok(_then_ty)
+                        } else {
+                            Err(anyhow!(ASTError::TypeMismatch(
+                                ann.source_loc(),
+                                _then_ty,
+                                _else.source_loc(),
+                                _else_ty,
+                            )))
+                        }
+                    }
                     _ => Err(anyhow!(ASTError::TypeMismatch(
                         ann.source_loc(),
                         Ty::Boolean,
@@ -351,6 +625,187 @@ impl<A: Clone + HasSourceLoc> Expr<A> {
                     ))),
                 }
             }
+            Expr::Ascription { ann, expr, _type } => {
+                let expr_ty = expr.typecheck(context)?;
+                if expr_ty == *_type {
+                    Ok(expr_ty)
+                } else {
+                    Err(anyhow!(ASTError::TypeMismatch(
+                        ann.source_loc(),
+                        _type.clone(),
+                        expr.source_loc(),
+                        expr_ty,
+                    )))
+                }
+            }
+            // casts convert between fields and unsigned integers, booleans are not numeric
+            Expr::Cast { ann, expr, _type } => {
+                let expr_ty = expr.typecheck(context)?;
+                let numeric = |ty: &Ty| *ty == Ty::Field || ty.is_uint();
+                if numeric(&expr_ty) && numeric(_type) {
+                    Ok(_type.clone())
+                } else {
+                    Err(anyhow!(ASTError::InvalidCast(
+                        ann.source_loc(),
+                        expr_ty,
+                        _type.clone()
+                    )))
+                }
+            }
+            Expr::Call { ann, func, args } => {
+                func.check_arity(ann.source_loc(), args.len())?;
+                match func {
+                    // bitwise operations combine unsigned integers of the same width
+                    Builtin::BitAnd | Builtin::BitOr | Builtin::BitXor => {
+                        let lhs_ty = args[0].typecheck(context)?;
+                        let rhs_ty = args[1].typecheck(context)?;
+                        if !lhs_ty.is_uint() {
+                            Err(anyhow!(ASTError::ExpectedUnsignedInteger(
+                                args[0].source_loc(),
+                                lhs_ty
+                            )))
+                        } else if lhs_ty != rhs_ty {
+                            Err(anyhow!(ASTError::TypeMismatch(
+                                ann.source_loc(),
+                                lhs_ty,
+                                args[1].source_loc(),
+                                rhs_ty
+                            )))
+                        } else {
+                            Ok(lhs_ty)
+                        }
+                    }
+                    // the shift amount can be of any unsigned integer type
+                    Builtin::Shl | Builtin::Shr => {
+                        let lhs_ty = args[0].typecheck(context)?;
+                        let rhs_ty = args[1].typecheck(context)?;
+                        if !lhs_ty.is_uint() {
+                            Err(anyhow!(ASTError::ExpectedUnsignedInteger(
+                                args[0].source_loc(),
+                                lhs_ty
+                            )))
+                        } else if !rhs_ty.is_uint() {
+                            Err(anyhow!(ASTError::ExpectedUnsignedInteger(
+                                args[1].source_loc(),
+                                rhs_ty
+                            )))
+                        } else {
+                            Ok(lhs_ty)
+                        }
+                    }
+                    Builtin::ToBits => {
+                        let expr_ty = args[0].typecheck(context)?;
+                        let width = args[1].bit_width()?;
+                        if expr_ty == Ty::Field || expr_ty.is_uint() {
+                            Ok(Ty::Array(Box::new(Ty::Boolean), width))
+                        } else {
+                            Err(anyhow!(ASTError::TypeMismatch(
+                                ann.source_loc(),
+                                Ty::Field,
+                                args[0].source_loc(),
+                                expr_ty
+                            )))
+                        }
+                    }
+                    // hashing is defined on field elements and arrays of them only, other
+                    // values have to be cast
+                    Builtin::Poseidon => {
+                        for arg in args {
+                            let arg_ty = arg.typecheck(context)?;
+                            let hashable = match &arg_ty {
+                                Ty::Array(elem_ty, _) => **elem_ty == Ty::Field,
+                                ty => *ty == Ty::Field,
+                            };
+                            if !hashable {
+                                return Err(anyhow!(ASTError::TypeMismatch(
+                                    ann.source_loc(),
+                                    Ty::Field,
+                                    arg.source_loc(),
+                                    arg_ty
+                                )));
+                            }
+                        }
+                        Ok(Ty::digest())
+                    }
+                    // `merkle_verify(root, leaf, index, path)` where the nodes are digests, the
+                    // depth of the tree is the length of `path` and `index` needs one bit per
+                    // level
+                    Builtin::MerkleVerify => {
+                        for arg in &args[0..2] {
+                            let arg_ty = arg.typecheck(context)?;
+                            if arg_ty != Ty::digest() {
+                                return Err(anyhow!(ASTError::TypeMismatch(
+                                    ann.source_loc(),
+                                    Ty::digest(),
+                                    arg.source_loc(),
+                                    arg_ty
+                                )));
+                            }
+                        }
+                        let index_ty = args[2].typecheck(context)?;
+                        if index_ty != Ty::Field && !index_ty.is_uint() {
+                            return Err(anyhow!(ASTError::TypeMismatch(
+                                ann.source_loc(),
+                                Ty::Field,
+                                args[2].source_loc(),
+                                index_ty
+                            )));
+                        }
+                        match args[3].typecheck(context)? {
+                            Ty::Array(elem_ty, depth) if *elem_ty == Ty::digest() => {
+                                if (1..=64).contains(&depth) {
+                                    Ok(Ty::Boolean)
+                                } else {
+                                    Err(anyhow!(ASTError::InvalidBitWidth(
+                                        args[3].source_loc(),
+                                        depth as i128
+                                    )))
+                                }
+                            }
+                            Ty::Array(elem_ty, depth) => Err(anyhow!(ASTError::TypeMismatch(
+                                ann.source_loc(),
+                                Ty::Array(Box::new(Ty::digest()), depth),
+                                args[3].source_loc(),
+                                Ty::Array(elem_ty, depth)
+                            ))),
+                            path_ty => Err(anyhow!(ASTError::ExpectedArray(
+                                args[3].source_loc(),
+                                path_ty
+                            ))),
+                        }
+                    }
+                }
+            }
+            Expr::Index { ann, expr, index } => match expr.typecheck(context)? {
+                Ty::Array(elem_ty, len) => {
+                    if *index < len {
+                        Ok(*elem_ty)
+                    } else {
+                        Err(anyhow!(ASTError::IndexOutOfBounds(
+                            ann.source_loc(),
+                            *index,
+                            len
+                        )))
+                    }
+                }
+                expr_ty => Err(anyhow!(ASTError::ExpectedArray(expr.source_loc(), expr_ty))),
+            },
+        }
+    }
+
+    // the number of bits requested from `to_bits`, which has to be a constant so that
+    // the size of the circuit is known. Field elements need at most 64 bits.
+    pub fn bit_width(&self) -> Result<usize> {
+        match self {
+            Expr::Literal {
+                value: Literal::Field(n),
+                ..
+            } if (1..=64).contains(n) => Ok(*n as usize),
+            Expr::Literal {
+                ann,
+                value: Literal::Field(n),
+            } => Err(anyhow!(ASTError::InvalidBitWidth(ann.source_loc(), *n))),
+            expr => Err(anyhow!(ASTError::ExpectedConstant(expr.source_loc()))),
         }
     }
 }
@@ -2,7 +2,7 @@ use std::collections::HashMap;
 
 use crate::ast::declaration::Declaration;
 use crate::ast::expression::{Expr, Ident};
-use crate::ast::program::Program;
+use crate::ast::program::{Program, RESULT};
 
 pub struct Context<A> {
     context: HashMap<Ident, Expr<A>>,
@@ -75,25 +75,71 @@ fn inline_expr<A: Clone>(context: &mut Context<A>, expr: Expr<A>) -> Expr<A> {
                 _else: Box::new(_else),
             }
         }
+        Expr::Ascription { ann, expr, _type } => {
+            let expr = inline_expr(context, *expr);
+            Expr::Ascription {
+                ann,
+                expr: Box::new(expr),
+                _type,
+            }
+        }
+        Expr::Cast { ann, expr, _type } => {
+            let expr = inline_expr(context, *expr);
+            Expr::Cast {
+                ann,
+                expr: Box::new(expr),
+                _type,
+            }
+        }
+        Expr::Call { ann, func, args } => {
+            let args = args
+                .into_iter()
+                .map(|arg| inline_expr(context, arg))
+                .collect();
+            Expr::Call { ann, func, args }
+        }
+        Expr::Index { ann, expr, index } => {
+            let expr = inline_expr(context, *expr);
+            Expr::Index {
+                ann,
+                expr: Box::new(expr),
+                index,
+            }
+        }
     }
 }
 
 fn inline_decl<A: Clone>(mut context: Context<A>, decl: Declaration<A>) -> Context<A> {
     match decl {
-        Declaration::VarAssignment { binder, expr } => {
+        Declaration::VarAssignment { binder, expr } | Declaration::Output { binder, expr } => {
             let expr = inline_expr(&mut context, expr);
             context.insert(binder.var().clone(), expr);
             context
         }
-        Declaration::PublicVar { .. } => context,
+        Declaration::PublicVar { .. } | Declaration::PrivateVar { .. } => context,
     }
 }
 
-pub fn inline<A: Clone>(program: Program<A>) -> Expr<A> {
+// inline every output of the program, the final expression comes last
+pub fn inline<A: Clone>(program: Program<A>) -> Vec<(Ident, Expr<A>)> {
     let context = Context::new();
-    let Program { decls, expr } = program;
+    let Program {
+        decls,
+        outputs,
+        expr,
+    } = program;
     let mut context = decls.into_iter().fold(context, inline_decl);
-    inline_expr(&mut context, expr)
+    let mut inlined: Vec<(Ident, Expr<A>)> = outputs
+        .into_iter()
+        .map(|name| {
+            let expr = context.get(&name).expect("Outputs are bound").clone();
+            (name, expr)
+        })
+        .collect();
+    if let Some(expr) = expr {
+        inlined.push((Ident::new(RESULT), inline_expr(&mut context, expr)));
+    }
+    inlined
 }
 
 #[cfg(test)]
@@ -121,7 +167,39 @@ mod inliner_tests {
             Opcode::Add,
             Expr::field_default(3),
         );
-        let program = Program::new(decls, expr2).unwrap();
-        assert_eq!(inline(program), inlined);
+        let program = Program::new(decls, Some(expr2)).unwrap();
+        assert_eq!(inline(program), vec![(Ident::new(RESULT), inlined)]);
+    }
+
+    #[test]
+    fn inliner_output_test() {
+        let decls: Vec<Declaration<()>> = vec![
+            Declaration::Output {
+                binder: Binder::default(Ident::new("y"), None),
+                expr: Expr::binary_op_default(
+                    Expr::variable_default(Ident::new("x")),
+                    Opcode::Mul,
+                    Expr::field_default(2),
+                ),
+            },
+            Declaration::VarAssignment {
+                binder: Binder::default(Ident::new("x"), None),
+                expr: Expr::field_default(1),
+            },
+            Declaration::Output {
+                binder: Binder::default(Ident::new("z"), None),
+                expr: Expr::variable_default(Ident::new("x")),
+            },
+        ];
+        let program = Program::new(decls, None).unwrap();
+        let y =
+            Expr::binary_op_default(Expr::field_default(1), Opcode::Mul, Expr::field_default(2));
+        assert_eq!(
+            inline(program),
+            vec![
+                (Ident::new("y"), y),
+                (Ident::new("z"), Expr::field_default(1))
+            ]
+        );
     }
 }
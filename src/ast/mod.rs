@@ -5,11 +5,13 @@ pub mod expression;
 pub mod inliner;
 pub mod optimizer;
 pub mod program;
+pub mod simplifier;
 pub mod typechecker;
 
 pub use declaration::{Binder, Declaration};
-pub use expression::{Expr, Ident, Literal, Opcode, UOpcode};
+pub use expression::{Builtin, Expr, Ident, Literal, Opcode, UOpcode};
 pub use inliner::inline;
-pub use optimizer::optimize;
+pub use optimizer::{fold_constants, fold_exponents};
 pub use program::Program;
+pub use simplifier::simplify;
 pub use typechecker::{Ty, TypeContext};
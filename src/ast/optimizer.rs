@@ -1,11 +1,12 @@
 use crate::ast::expression::{Expr, Literal, Opcode, UOpcode};
 
-pub fn optimize<A: Clone>(expr: Expr<A>) -> Expr<A> {
-    fold_constants(expr)
+fn in_range(n: Option<i128>) -> bool {
+    n.and_then(Literal::field).is_some()
 }
 
-// fold constants in the expression in the most naive way possible
-fn fold_constants<A: Clone>(expr: Expr<A>) -> Expr<A> {
+// fold constants in the expression in the most naive way possible, field arithmetic that
+// leaves the range of field literals is left to the circuit
+pub fn fold_constants<A: Clone>(expr: Expr<A>) -> Expr<A> {
     match expr {
         Expr::Literal { ann, value } => Expr::Literal { ann, value },
         Expr::Variable { ann, value } => Expr::Variable { ann, value },
@@ -43,7 +44,7 @@ fn fold_constants<A: Clone>(expr: Expr<A>) -> Expr<A> {
                         value: Literal::Field(n2),
                         ..
                     },
-                ) => Expr::Literal {
+                ) if in_range(n1.checked_add(n2)) => Expr::Literal {
                     ann,
                     value: Literal::Field(n1 + n2),
                 },
@@ -57,7 +58,7 @@ fn fold_constants<A: Clone>(expr: Expr<A>) -> Expr<A> {
                         value: Literal::Field(n2),
                         ..
                     },
-                ) => Expr::Literal {
+                ) if in_range(n1.checked_sub(n2)) => Expr::Literal {
                     ann,
                     value: Literal::Field(n1 - n2),
                 },
@@ -71,7 +72,7 @@ fn fold_constants<A: Clone>(expr: Expr<A>) -> Expr<A> {
                         value: Literal::Field(n2),
                         ..
                     },
-                ) => Expr::Literal {
+                ) if in_range(n1.checked_mul(n2)) => Expr::Literal {
                     ann,
                     value: Literal::Field(n1 * n2),
                 },
@@ -85,10 +86,37 @@ fn fold_constants<A: Clone>(expr: Expr<A>) -> Expr<A> {
                         value: Literal::Field(n2),
                         ..
                     },
+                ) if in_range(u32::try_from(n2).ok().and_then(|n2| n1.checked_pow(n2))) => {
+                    Expr::Literal {
+                        ann,
+                        value: Literal::Field(n1.pow(n2 as u32)),
+                    }
+                }
+                (
+                    Expr::Literal {
+                        value: Literal::Boolean(b1),
+                        ..
+                    },
+                    op @ (Opcode::And | Opcode::Or),
+                    Expr::Literal {
+                        value: Literal::Boolean(b2),
+                        ..
+                    },
                 ) => Expr::Literal {
                     ann,
-                    value: Literal::Field(n1.pow(n2 as u32)),
+                    value: Literal::Boolean(if op == Opcode::And {
+                        b1 && b2
+                    } else {
+                        b1 || b2
+                    }),
                 },
+                // both sides have the same type, and field literals are compared mod the order
+                (Expr::Literal { value: v1, .. }, Opcode::Eq, Expr::Literal { value: v2, .. }) => {
+                    Expr::Literal {
+                        ann,
+                        value: Literal::Boolean(v1.canonical() == v2.canonical()),
+                    }
+                }
                 (lhs, op, rhs) => Expr::BinOp {
                     ann,
                     lhs: Box::new(lhs),
@@ -103,22 +131,104 @@ fn fold_constants<A: Clone>(expr: Expr<A>) -> Expr<A> {
             _then,
             _else,
         } => {
-            let cond = fold_constants(*cond);
-            let _then = fold_constants(*_then);
-            let _else = fold_constants(*_else);
-            Expr::IfThenElse {
+            // the dead branch is dropped, the surviving one keeps its own annotation
+            match fold_constants(*cond) {
+                Expr::Literal {
+                    value: Literal::Boolean(true),
+                    ..
+                } => fold_constants(*_then),
+                Expr::Literal {
+                    value: Literal::Boolean(false),
+                    ..
+                } => fold_constants(*_else),
+                cond => Expr::IfThenElse {
+                    ann,
+                    cond: Box::new(cond),
+                    _then: Box::new(fold_constants(*_then)),
+                    _else: Box::new(fold_constants(*_else)),
+                },
+            }
+        }
+        // ascriptions have no meaning once the program has been typechecked
+        Expr::Ascription { expr, .. } => fold_constants(*expr),
+        Expr::Cast { ann, expr, _type } => Expr::Cast {
+            ann,
+            expr: Box::new(fold_constants(*expr)),
+            _type,
+        },
+        Expr::Call { ann, func, args } => Expr::Call {
+            ann,
+            func,
+            args: args.into_iter().map(fold_constants).collect(),
+        },
+        Expr::Index { ann, expr, index } => Expr::Index {
+            ann,
+            expr: Box::new(fold_constants(*expr)),
+            index,
+        },
+    }
+}
+
+// fold the exponents of the expression only, the compiler needs field exponents to be literals
+// whichever passes run
+pub fn fold_exponents<A: Clone>(expr: Expr<A>) -> Expr<A> {
+    match expr {
+        Expr::Literal { .. } | Expr::Variable { .. } => expr,
+        Expr::UnaryOp { ann, op, expr } => Expr::UnaryOp {
+            ann,
+            op,
+            expr: Box::new(fold_exponents(*expr)),
+        },
+        Expr::BinOp { ann, lhs, op, rhs } => {
+            let rhs = match op {
+                Opcode::Pow => fold_constants(*rhs),
+                _ => fold_exponents(*rhs),
+            };
+            Expr::BinOp {
                 ann,
-                cond: Box::new(cond),
-                _then: Box::new(_then),
-                _else: Box::new(_else),
+                lhs: Box::new(fold_exponents(*lhs)),
+                op,
+                rhs: Box::new(rhs),
             }
         }
+        Expr::IfThenElse {
+            ann,
+            cond,
+            _then,
+            _else,
+        } => Expr::IfThenElse {
+            ann,
+            cond: Box::new(fold_exponents(*cond)),
+            _then: Box::new(fold_exponents(*_then)),
+            _else: Box::new(fold_exponents(*_else)),
+        },
+        Expr::Ascription { ann, expr, _type } => Expr::Ascription {
+            ann,
+            expr: Box::new(fold_exponents(*expr)),
+            _type,
+        },
+        Expr::Cast { ann, expr, _type } => Expr::Cast {
+            ann,
+            expr: Box::new(fold_exponents(*expr)),
+            _type,
+        },
+        Expr::Call { ann, func, args } => Expr::Call {
+            ann,
+            func,
+            args: args.into_iter().map(fold_exponents).collect(),
+        },
+        Expr::Index { ann, expr, index } => Expr::Index {
+            ann,
+            expr: Box::new(fold_exponents(*expr)),
+            index,
+        },
     }
 }
 
 #[cfg(test)]
 mod ast_test {
     use super::*;
+    use crate::ast::expression::{Ident, FIELD_ORDER};
 
     #[test]
     fn const_folding_basic_test() {
@@ -131,4 +241,99 @@ mod ast_test {
         let expr = Expr::binary_op_default(expr1, Opcode::Mul, expr2);
         assert_eq!(fold_constants(expr), Expr::field_default(-3));
     }
+
+    #[test]
+    fn const_folding_overflow_test() {
+        // the product is past the order of the field, the circuit reduces it
+        let expr: Expr<()> = Expr::binary_op_default(
+            Expr::field_default(1 << 32),
+            Opcode::Mul,
+            Expr::field_default(1 << 32),
+        );
+        assert_eq!(fold_constants(expr.clone()), expr);
+        let expr: Expr<()> =
+            Expr::binary_op_default(Expr::field_default(2), Opcode::Pow, Expr::field_default(64));
+        assert_eq!(fold_constants(expr.clone()), expr);
+        let expr: Expr<()> =
+            Expr::binary_op_default(Expr::field_default(2), Opcode::Pow, Expr::field_default(63));
+        assert_eq!(fold_constants(expr), Expr::field_default(1 << 63));
+    }
+
+    #[test]
+    fn const_folding_bool_test() {
+        let expr: Expr<()> = Expr::binary_op_default(
+            Expr::binary_op_default(Expr::field_default(1), Opcode::Eq, Expr::field_default(1)),
+            Opcode::And,
+            Expr::binary_op_default(
+                Expr::bool_default(false),
+                Opcode::Or,
+                Expr::bool_default(true),
+            ),
+        );
+        assert_eq!(fold_constants(expr), Expr::bool_default(true));
+        let expr: Expr<()> = Expr::binary_op_default(
+            Expr::Literal {
+                ann: (),
+                value: Literal::U8(3),
+            },
+            Opcode::Eq,
+            Expr::Literal {
+                ann: (),
+                value: Literal::U8(4),
+            },
+        );
+        assert_eq!(fold_constants(expr), Expr::bool_default(false));
+        // -1 and p - 1 are the same field element
+        let expr: Expr<()> = Expr::binary_op_default(
+            Expr::field_default(-1),
+            Opcode::Eq,
+            Expr::field_default(FIELD_ORDER - 1),
+        );
+        assert_eq!(fold_constants(expr), Expr::bool_default(true));
+    }
+
+    #[test]
+    fn const_folding_if_test() {
+        // annotate every node with a distinct number to check which one survives
+        let if_then_else = |cond| Expr::IfThenElse {
+            ann: 0,
+            cond: Box::new(cond),
+            _then: Box::new(Expr::Variable {
+                ann: 1,
+                value: Ident::new("a"),
+            }),
+            _else: Box::new(Expr::Variable {
+                ann: 2,
+                value: Ident::new("b"),
+            }),
+        };
+        let one = |ann| Expr::Literal {
+            ann,
+            value: Literal::Field(1),
+        };
+        let cond = Expr::BinOp {
+            ann: 3,
+            lhs: Box::new(one(4)),
+            op: Opcode::Eq,
+            rhs: Box::new(one(5)),
+        };
+        assert_eq!(
+            fold_constants(if_then_else(cond)),
+            Expr::Variable {
+                ann: 1,
+                value: Ident::new("a"),
+            }
+        );
+        let cond = Expr::Literal {
+            ann: 3,
+            value: Literal::Boolean(false),
+        };
+        assert_eq!(
+            fold_constants(if_then_else(cond)),
+            Expr::Variable {
+                ann: 2,
+                value: Ident::new("b"),
+            }
+        );
+    }
 }
@@ -2,16 +2,21 @@ use crate::ast::annotation::HasSourceLoc;
 use crate::ast::declaration::Declaration;
 use crate::ast::error::ASTError;
 use crate::ast::expression::{Expr, Ident};
-use crate::ast::typechecker::TypeContext;
+use crate::ast::typechecker::{Ty, TypeContext};
 use anyhow::{anyhow, Result};
 use petgraph::{algo::toposort, graph::DiGraph};
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 
+// the output name of the expression a program ends in
+pub const RESULT: &str = "result";
+
 #[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
 pub struct Program<A> {
     pub decls: Vec<Declaration<A>>,
-    pub expr: Expr<A>,
+    // the names of the `out` declarations, in the order they were written
+    pub outputs: Vec<Ident>,
+    pub expr: Option<Expr<A>>,
 }
 
 impl<A: Clone> Program<A> {
@@ -22,32 +27,46 @@ impl<A: Clone> Program<A> {
                 .into_iter()
                 .map(|decl| decl.clear_annotations())
                 .collect(),
-            expr: self.expr.clear_annotations(),
+            outputs: self.outputs,
+            expr: self.expr.map(|expr| expr.clear_annotations()),
         }
     }
 
     pub fn public_variable_decls(&self) -> Vec<Declaration<A>> {
         self.decls
             .iter()
-            .filter(|decl| match decl {
-                Declaration::PublicVar { .. } => true,
-                _ => false,
-            })
+            .filter(|decl| matches!(decl, Declaration::PublicVar { .. }))
+            .cloned()
+            .collect()
+    }
+
+    pub fn private_variable_decls(&self) -> Vec<Declaration<A>> {
+        self.decls
+            .iter()
+            .filter(|decl| matches!(decl, Declaration::PrivateVar { .. }))
             .cloned()
             .collect()
     }
 }
 
 impl<A: Clone + HasSourceLoc> Program<A> {
-    pub fn typecheck(&self) -> Result<()> {
+    // returns the types of the outputs, the final expression comes last
+    pub fn typecheck(&self) -> Result<Vec<(Ident, Ty)>> {
         let mut context = TypeContext {
             context: HashMap::new(),
         };
         for decl in &self.decls {
             decl.typecheck(&mut context)?;
         }
-        self.expr.typecheck(&context)?;
-        Ok(())
+        let mut outputs: Vec<(Ident, Ty)> = self
+            .outputs
+            .iter()
+            .map(|name| (name.clone(), context.get(name).expect("Outputs are bound")))
+            .collect();
+        if let Some(expr) = &self.expr {
+            outputs.push((Ident::new(RESULT), expr.typecheck(&context)?));
+        }
+        Ok(outputs)
     }
 }
 
@@ -56,12 +75,12 @@ impl<A: Clone + HasSourceLoc + PartialEq> Program<A> {
     // declarations only contain identifiers which are bound in previous declarations.
     // this means that if you are building up a context for evaluation in order, you
     // can be sure that all the variables you need to substitute will be bound in the context.
-    pub fn new(decls: Vec<Declaration<A>>, expr: Expr<A>) -> Result<Self> {
+    pub fn new(decls: Vec<Declaration<A>>, expr: Option<Expr<A>>) -> Result<Self> {
         // check for duplicate bindings
         let mut decls_ident_set: HashSet<Ident> = HashSet::new();
         for decl in decls.clone() {
             let binder = decl.binder();
-            if decls_ident_set.contains(&binder.var()) {
+            if decls_ident_set.contains(binder.var()) {
                 return Err(anyhow!(ASTError::DuplicateIdentifier(
                     binder.ann().source_loc(),
                     binder.var().clone()
@@ -69,6 +88,21 @@ impl<A: Clone + HasSourceLoc + PartialEq> Program<A> {
             }
             decls_ident_set.insert(binder.var().clone());
         }
+        let outputs: Vec<Ident> = decls
+            .iter()
+            .filter(|decl| matches!(decl, Declaration::Output { .. }))
+            .map(|decl| decl.binder().var().clone())
+            .collect();
+        match &expr {
+            None if outputs.is_empty() => return Err(anyhow!(ASTError::MissingOutput)),
+            Some(expr) if outputs.contains(&Ident::new(RESULT)) => {
+                return Err(anyhow!(ASTError::DuplicateIdentifier(
+                    expr.source_loc(),
+                    Ident::new(RESULT)
+                )))
+            }
+            _ => (),
+        }
         // sort the declarations so that all the dependencies of a declaration appear
         // before it in the list (i.e. topologically sorted).
         let sorted_decls = sort(decls)?;
@@ -86,7 +120,7 @@ impl<A: Clone + HasSourceLoc + PartialEq> Program<A> {
         }
 
         // check that the final expression only uses variables bound in the declarations
-        for (var, ann) in expr.variables() {
+        for (var, ann) in expr.iter().flat_map(|expr| expr.variables()) {
             if !decl_ident_set.contains(&var) {
                 return Err(anyhow!(ASTError::UnboundIdentifier(ann.source_loc(), var)));
             }
@@ -94,6 +128,7 @@ impl<A: Clone + HasSourceLoc + PartialEq> Program<A> {
 
         Ok(Program {
             decls: sorted_decls,
+            outputs,
             expr,
         })
     }
@@ -116,7 +151,7 @@ fn dependency_graph<A: Clone + PartialEq + HasSourceLoc>(
         for dep in decl.get_dependencies() {
             match ix_map.get(&dep.0) {
                 Some(ix) => {
-                    graph.add_edge(ix.clone(), ix_map[&binder.var()], ());
+                    graph.add_edge(*ix, ix_map[&binder.var()], ());
                 }
                 None => {
                     return Err(anyhow!(ASTError::UnboundIdentifier(
@@ -152,6 +187,21 @@ pub fn find_declaration<A: Clone>(
                     });
                 }
             }
+            Declaration::PrivateVar { binder } => {
+                if binder.var() == &ident {
+                    return Some(Declaration::PrivateVar {
+                        binder: binder.clone(),
+                    });
+                }
+            }
+            Declaration::Output { binder, expr } => {
+                if binder.var() == &ident {
+                    return Some(Declaration::Output {
+                        binder: binder.clone(),
+                        expr: expr.clone(),
+                    });
+                }
+            }
         }
     }
     None
@@ -199,7 +249,12 @@ fn sort<A: Clone + HasSourceLoc + PartialEq>(
 #[cfg(test)]
 mod ast_test {
     use super::*;
-    use crate::ast::{declaration::Binder, error::ASTError, typechecker::Ty};
+    use crate::ast::{
+        declaration::Binder,
+        error::ASTError,
+        expression::{Builtin, Opcode},
+        typechecker::Ty,
+    };
 
     #[test]
     fn duplicate_identifier_test() {
@@ -214,7 +269,7 @@ mod ast_test {
                 expr: Expr::field_default(2),
             },
         ];
-        match Program::new(decls, Expr::field_default(1)) {
+        match Program::new(decls, Some(Expr::field_default(1))) {
             Err(err) => match err.downcast_ref() {
                 Some(ASTError::DuplicateIdentifier(_, _)) => (),
                 _ => panic!("Expected DuplicateIdentifier error"),
@@ -223,6 +278,173 @@ mod ast_test {
         };
     }
 
+    #[test]
+    fn missing_output_test() {
+        let decls: Vec<Declaration<()>> = vec![Declaration::VarAssignment {
+            binder: Binder::default(Ident::new("x"), None),
+            expr: Expr::field_default(1),
+        }];
+        match Program::new(decls, None) {
+            Err(err) => match err.downcast_ref() {
+                Some(ASTError::MissingOutput) => (),
+                _ => panic!("Expected MissingOutput error"),
+            },
+            _ => panic!("Expected MissingOutput error"),
+        };
+        let decls: Vec<Declaration<()>> = vec![Declaration::Output {
+            binder: Binder::default(Ident::new(RESULT), None),
+            expr: Expr::field_default(1),
+        }];
+        match Program::new(decls, Some(Expr::field_default(2))) {
+            Err(err) => match err.downcast_ref() {
+                Some(ASTError::DuplicateIdentifier(_, _)) => (),
+                _ => panic!("Expected DuplicateIdentifier error"),
+            },
+            _ => panic!("Expected DuplicateIdentifier error"),
+        };
+    }
+
+    #[test]
+    fn typed_binder_mismatch_test() {
+        let decls: Vec<Declaration<()>> = vec![
+            Declaration::PublicVar {
+                binder: Binder::default(Ident::new("x"), Some(Ty::Field)),
+            },
+            Declaration::VarAssignment {
+                binder: Binder::default(Ident::new("y"), Some(Ty::Boolean)),
+                expr: Expr::binary_op_default(
+                    Expr::variable_default(Ident::new("x")),
+                    Opcode::Add,
+                    Expr::field_default(1),
+                ),
+            },
+        ];
+        let program = Program::new(decls, Some(Expr::variable_default(Ident::new("y")))).unwrap();
+        match program.typecheck() {
+            Err(err) => match err.downcast_ref() {
+                Some(ASTError::TypeMismatch(_, Ty::Boolean, _, Ty::Field)) => (),
+                _ => panic!("Expected TypeMismatch error"),
+            },
+            _ => panic!("Expected TypeMismatch error"),
+        };
+    }
+
+    #[test]
+    fn ascription_mismatch_test() {
+        let decls: Vec<Declaration<()>> = vec![Declaration::PublicVar {
+            binder: Binder::default(Ident::new("x"), Some(Ty::Field)),
+        }];
+        let expr = Expr::Ascription {
+            ann: (),
+            expr: Box::new(Expr::variable_default(Ident::new("x"))),
+            _type: Ty::Boolean,
+        };
+        let program = Program::new(decls, Some(expr)).unwrap();
+        match program.typecheck() {
+            Err(err) => match err.downcast_ref() {
+                Some(ASTError::TypeMismatch(_, Ty::Boolean, _, Ty::Field)) => (),
+                _ => panic!("Expected TypeMismatch error"),
+            },
+            _ => panic!("Expected TypeMismatch error"),
+        };
+    }
+
+    #[test]
+    fn uint_mismatch_test() {
+        let decls: Vec<Declaration<()>> = vec![
+            Declaration::PublicVar {
+                binder: Binder::default(Ident::new("x"), Some(Ty::U8)),
+            },
+            Declaration::PublicVar {
+                binder: Binder::default(Ident::new("y"), Some(Ty::U32)),
+            },
+        ];
+        let expr = Expr::binary_op_default(
+            Expr::variable_default(Ident::new("x")),
+            Opcode::WrappingAdd,
+            Expr::variable_default(Ident::new("y")),
+        );
+        let program = Program::new(decls, Some(expr)).unwrap();
+        match program.typecheck() {
+            Err(err) => match err.downcast_ref() {
+                Some(ASTError::TypeMismatch(_, Ty::U8, _, Ty::U32)) => (),
+                _ => panic!("Expected TypeMismatch error"),
+            },
+            _ => panic!("Expected TypeMismatch error"),
+        };
+    }
+
+    #[test]
+    fn to_bits_test() {
+        let decls: Vec<Declaration<()>> = vec![Declaration::PublicVar {
+            binder: Binder::default(Ident::new("x"), Some(Ty::U32)),
+        }];
+        let to_bits = |width| {
+            Expr::call_default(
+                Builtin::ToBits,
+                vec![Expr::variable_default(Ident::new("x")), width],
+            )
+        };
+        let program = Program::new(decls.clone(), Some(to_bits(Expr::field_default(4)))).unwrap();
+        assert_eq!(
+            program.typecheck().unwrap(),
+            vec![(Ident::new(RESULT), Ty::Array(Box::new(Ty::Boolean), 4))]
+        );
+        let program = Program::new(decls.clone(), Some(to_bits(Expr::field_default(65)))).unwrap();
+        match program.typecheck() {
+            Err(err) => match err.downcast_ref() {
+                Some(ASTError::InvalidBitWidth(_, 65)) => (),
+                _ => panic!("Expected InvalidBitWidth error"),
+            },
+            _ => panic!("Expected InvalidBitWidth error"),
+        };
+        let program = Program::new(
+            decls,
+            Some(to_bits(Expr::variable_default(Ident::new("x")))),
+        )
+        .unwrap();
+        match program.typecheck() {
+            Err(err) => match err.downcast_ref() {
+                Some(ASTError::ExpectedConstant(_)) => (),
+                _ => panic!("Expected ExpectedConstant error"),
+            },
+            _ => panic!("Expected ExpectedConstant error"),
+        };
+    }
+
+    #[test]
+    fn arity_test() {
+        let decls: Vec<Declaration<()>> = vec![Declaration::PublicVar {
+            binder: Binder::default(Ident::new("x"), Some(Ty::U32)),
+        }];
+        let x = Expr::variable_default(Ident::new("x"));
+        for (expr, message) in [
+            (
+                Expr::call_default(Builtin::Poseidon, vec![]),
+                "poseidon expects at least 1 argument but was called with 0",
+            ),
+            (
+                Expr::call_default(Builtin::ToBits, vec![x.clone()]),
+                "to_bits expects 2 arguments but was called with 1",
+            ),
+            (
+                Expr::call_default(Builtin::BitAnd, vec![x.clone(), x.clone(), x]),
+                "bit_and expects 2 arguments but was called with 3",
+            ),
+        ] {
+            let program = Program::new(decls.clone(), Some(expr)).unwrap();
+            match program.typecheck() {
+                Err(err) => match err.downcast_ref() {
+                    Some(ASTError::ArityMismatch(..)) => {
+                        assert!(err.to_string().ends_with(message), "{}", err)
+                    }
+                    _ => panic!("Expected ArityMismatch error"),
+                },
+                _ => panic!("Expected ArityMismatch error"),
+            };
+        }
+    }
+
     #[test]
     fn sort_decl_test() {
         let decls: Vec<Declaration<()>> = vec![
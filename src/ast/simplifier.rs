@@ -0,0 +1,490 @@
+use crate::ast::annotation::HasSourceLoc;
+use crate::ast::expression::{Builtin, Expr, Literal, Opcode, UOpcode};
+use crate::ast::typechecker::{Ty, TypeContext};
+
+fn is_zero<A>(expr: &Expr<A>) -> bool {
+    matches!(
+        expr,
+        Expr::Literal {
+            value: Literal::Field(0) | Literal::U8(0) | Literal::U32(0) | Literal::U64(0),
+            ..
+        }
+    )
+}
+
+fn is_one<A>(expr: &Expr<A>) -> bool {
+    matches!(
+        expr,
+        Expr::Literal {
+            value: Literal::Field(1) | Literal::U8(1) | Literal::U32(1) | Literal::U64(1),
+            ..
+        }
+    )
+}
+
+fn as_bool<A>(expr: &Expr<A>) -> Option<bool> {
+    match expr {
+        Expr::Literal {
+            value: Literal::Boolean(b),
+            ..
+        } => Some(*b),
+        _ => None,
+    }
+}
+
+fn as_field<A>(expr: &Expr<A>) -> Option<i128> {
+    match expr {
+        Expr::Literal {
+            value: Literal::Field(n),
+            ..
+        } => Some(*n),
+        _ => None,
+    }
+}
+
+fn zero(ty: &Ty) -> Literal {
+    match ty {
+        Ty::Field => Literal::Field(0),
+        Ty::U8 => Literal::U8(0),
+        Ty::U32 => Literal::U32(0),
+        Ty::U64 => Literal::U64(0),
+        ty => unreachable!("{} has no zero", ty),
+    }
+}
+
+fn type_of<A: Clone + HasSourceLoc>(expr: &Expr<A>, context: &TypeContext) -> Ty {
+    expr.typecheck(context)
+        .expect("Simplified expressions are well typed")
+}
+
+// whether evaluating the expression can fail (e.g. on overflow), such expressions carry
+// constraints and can't be dropped from the circuit
+fn can_fail<A: Clone + HasSourceLoc>(expr: &Expr<A>, context: &TypeContext) -> bool {
+    match expr {
+        Expr::Literal { .. } | Expr::Variable { .. } => false,
+        Expr::UnaryOp { expr, .. } | Expr::Ascription { expr, .. } | Expr::Index { expr, .. } => {
+            can_fail(expr, context)
+        }
+        Expr::BinOp { lhs, op, rhs, .. } => {
+            let checked = matches!(op, Opcode::Add | Opcode::Sub | Opcode::Mul)
+                && type_of(lhs, context).is_uint();
+            checked || can_fail(lhs, context) || can_fail(rhs, context)
+        }
+        Expr::IfThenElse {
+            cond, _then, _else, ..
+        } => can_fail(cond, context) || can_fail(_then, context) || can_fail(_else, context),
+        // only casts to a field can't go out of range
+        Expr::Cast { expr, _type, .. } => *_type != Ty::Field || can_fail(expr, context),
+        Expr::Call { func, args, .. } => {
+            matches!(func, Builtin::ToBits | Builtin::MerkleVerify)
+                || args.iter().any(|arg| can_fail(arg, context))
+        }
+    }
+}
+
+fn same<A: Clone>(lhs: &Expr<A>, rhs: &Expr<A>) -> bool {
+    lhs.clone().clear_annotations() == rhs.clone().clear_annotations()
+}
+
+// rewrite `(x op c1) op c2` into `x op (c1 op c2)` for field constants
+fn reassociate<A: Clone + HasSourceLoc>(
+    ann: A,
+    lhs: Expr<A>,
+    op: Opcode,
+    rhs: Expr<A>,
+    context: &TypeContext,
+) -> Expr<A> {
+    let combine = |c1: i128, c2: i128| match op {
+        Opcode::Add => c1.checked_add(c2).filter(|n| Literal::field(*n).is_some()),
+        Opcode::Mul => c1.checked_mul(c2).filter(|n| Literal::field(*n).is_some()),
+        _ => None,
+    };
+    match (lhs, as_field(&rhs)) {
+        (
+            Expr::BinOp {
+                ann: inner_ann,
+                lhs: x,
+                op: inner_op,
+                rhs: c1,
+            },
+            Some(c2),
+        ) if inner_op == op => match as_field(&c1).and_then(|c1| combine(c1, c2)) {
+            Some(n) => {
+                let rhs = match rhs {
+                    Expr::Literal { ann, .. } => Expr::Literal {
+                        ann,
+                        value: Literal::Field(n),
+                    },
+                    _ => unreachable!("Expected a constant"),
+                };
+                simplify_binop(ann, *x, op, rhs, context)
+            }
+            None => Expr::BinOp {
+                ann,
+                lhs: Box::new(Expr::BinOp {
+                    ann: inner_ann,
+                    lhs: x,
+                    op: inner_op,
+                    rhs: c1,
+                }),
+                op,
+                rhs: Box::new(rhs),
+            },
+        },
+        (lhs, _) => Expr::BinOp {
+            ann,
+            lhs: Box::new(lhs),
+            op,
+            rhs: Box::new(rhs),
+        },
+    }
+}
+
+fn simplify_binop<A: Clone + HasSourceLoc>(
+    ann: A,
+    lhs: Expr<A>,
+    op: Opcode,
+    rhs: Expr<A>,
+    context: &TypeContext,
+) -> Expr<A> {
+    use Opcode::*;
+    match op {
+        Add | WrappingAdd if is_zero(&rhs) => lhs,
+        Add | WrappingAdd if is_zero(&lhs) => rhs,
+        Sub | WrappingSub if is_zero(&rhs) => lhs,
+        Sub | WrappingSub if same(&lhs, &rhs) && !can_fail(&lhs, context) => Expr::Literal {
+            value: zero(&type_of(&lhs, context)),
+            ann,
+        },
+        Mul | WrappingMul if is_one(&rhs) => lhs,
+        Mul | WrappingMul if is_one(&lhs) => rhs,
+        Mul | WrappingMul if is_zero(&rhs) && !can_fail(&lhs, context) => rhs,
+        Mul | WrappingMul if is_zero(&lhs) && !can_fail(&rhs, context) => lhs,
+        Pow if is_one(&rhs) => lhs,
+        Pow if is_zero(&rhs) && !can_fail(&lhs, context) => Expr::Literal {
+            ann,
+            value: Literal::Field(1),
+        },
+        And if as_bool(&lhs) == Some(true) => rhs,
+        And if as_bool(&rhs) == Some(true) => lhs,
+        And if as_bool(&lhs) == Some(false) && !can_fail(&rhs, context) => lhs,
+        And if as_bool(&rhs) == Some(false) && !can_fail(&lhs, context) => rhs,
+        Or if as_bool(&lhs) == Some(false) => rhs,
+        Or if as_bool(&rhs) == Some(false) => lhs,
+        Or if as_bool(&lhs) == Some(true) && !can_fail(&rhs, context) => lhs,
+        Or if as_bool(&rhs) == Some(true) && !can_fail(&lhs, context) => rhs,
+        // move constants to the right so that they can be combined
+        Add | Mul if as_field(&lhs).is_some() && as_field(&rhs).is_none() => {
+            reassociate(ann, rhs, op, lhs, context)
+        }
+        Add | Mul => reassociate(ann, lhs, op, rhs, context),
+        op => Expr::BinOp {
+            ann,
+            lhs: Box::new(lhs),
+            op,
+            rhs: Box::new(rhs),
+        },
+    }
+}
+
+// apply algebraic identities bottom up, expressions are only dropped if they can't fail
+pub fn simplify<A: Clone + HasSourceLoc>(expr: Expr<A>, context: &TypeContext) -> Expr<A> {
+    match expr {
+        Expr::Literal { .. } | Expr::Variable { .. } => expr,
+        Expr::UnaryOp { ann, op, expr } => match (op, simplify(*expr, context)) {
+            (
+                UOpcode::Neg,
+                Expr::UnaryOp {
+                    op: UOpcode::Neg,
+                    expr,
+                    ..
+                },
+            ) => *expr,
+            (op, expr) => Expr::UnaryOp {
+                ann,
+                op,
+                expr: Box::new(expr),
+            },
+        },
+        Expr::BinOp { ann, lhs, op, rhs } => {
+            let lhs = simplify(*lhs, context);
+            let rhs = simplify(*rhs, context);
+            simplify_binop(ann, lhs, op, rhs, context)
+        }
+        Expr::IfThenElse {
+            ann,
+            cond,
+            _then,
+            _else,
+        } => {
+            let cond = simplify(*cond, context);
+            match as_bool(&cond) {
+                Some(true) => simplify(*_then, context),
+                Some(false) => simplify(*_else, context),
+                None => Expr::IfThenElse {
+                    ann,
+                    cond: Box::new(cond),
+                    _then: Box::new(simplify(*_then, context)),
+                    _else: Box::new(simplify(*_else, context)),
+                },
+            }
+        }
+        Expr::Ascription { ann, expr, _type } => Expr::Ascription {
+            ann,
+            expr: Box::new(simplify(*expr, context)),
+            _type,
+        },
+        Expr::Cast { ann, expr, _type } => Expr::Cast {
+            ann,
+            expr: Box::new(simplify(*expr, context)),
+            _type,
+        },
+        Expr::Call { ann, func, args } => Expr::Call {
+            ann,
+            func,
+            args: args.into_iter().map(|arg| simplify(arg, context)).collect(),
+        },
+        Expr::Index { ann, expr, index } => Expr::Index {
+            ann,
+            expr: Box::new(simplify(*expr, context)),
+            index,
+        },
+    }
+}
+
+#[cfg(test)]
+mod simplifier_tests {
+    use super::*;
+    use crate::ast::expression::Ident;
+    use crate::interpreter::{interpret, Context};
+    use std::collections::HashMap;
+
+    fn context() -> TypeContext {
+        let mut context = TypeContext::new();
+        context.context.insert(Ident::new("x"), Ty::Field);
+        context.context.insert(Ident::new("y"), Ty::Field);
+        context.context.insert(Ident::new("b"), Ty::Boolean);
+        context.context.insert(Ident::new("n"), Ty::U8);
+        context
+    }
+
+    fn var(name: &str) -> Expr<()> {
+        Expr::variable_default(Ident::new(name))
+    }
+
+    fn binop(lhs: Expr<()>, op: Opcode, rhs: Expr<()>) -> Expr<()> {
+        Expr::binary_op_default(lhs, op, rhs)
+    }
+
+    fn assert_simplifies(expr: Expr<()>, expected: Expr<()>) {
+        assert_eq!(simplify(expr, &context()), expected);
+    }
+
+    #[test]
+    fn identity_test() {
+        let x = var("x");
+        let one = Expr::field_default(1);
+        let zero = Expr::field_default(0);
+        assert_simplifies(binop(x.clone(), Opcode::Mul, one.clone()), x.clone());
+        assert_simplifies(binop(one.clone(), Opcode::Mul, x.clone()), x.clone());
+        assert_simplifies(binop(x.clone(), Opcode::Add, zero.clone()), x.clone());
+        assert_simplifies(binop(zero.clone(), Opcode::Add, x.clone()), x.clone());
+        assert_simplifies(binop(x.clone(), Opcode::Sub, zero.clone()), x.clone());
+        assert_simplifies(binop(x.clone(), Opcode::Pow, one.clone()), x.clone());
+        let n = var("n");
+        let one_u8 = Expr::Literal {
+            ann: (),
+            value: Literal::U8(1),
+        };
+        assert_simplifies(binop(n.clone(), Opcode::Mul, one_u8), n);
+    }
+
+    #[test]
+    fn annihilator_test() {
+        let x = var("x");
+        let zero = Expr::field_default(0);
+        assert_simplifies(binop(x.clone(), Opcode::Mul, zero.clone()), zero.clone());
+        assert_simplifies(binop(zero.clone(), Opcode::Mul, x.clone()), zero.clone());
+        assert_simplifies(binop(x.clone(), Opcode::Sub, x.clone()), zero.clone());
+        assert_simplifies(binop(x.clone(), Opcode::Pow, zero), Expr::field_default(1));
+        let n = var("n");
+        assert_simplifies(
+            binop(n.clone(), Opcode::WrappingSub, n),
+            Expr::Literal {
+                ann: (),
+                value: Literal::U8(0),
+            },
+        );
+    }
+
+    #[test]
+    fn failing_expression_kept_test() {
+        // `n + n` may overflow, so multiplying it by zero must keep the check
+        let n = var("n");
+        let sum = binop(n.clone(), Opcode::Add, n);
+        let expr = binop(
+            Expr::Cast {
+                ann: (),
+                expr: Box::new(sum),
+                _type: Ty::Field,
+            },
+            Opcode::Mul,
+            Expr::field_default(0),
+        );
+        assert_simplifies(expr.clone(), expr);
+        let cast = Expr::Cast {
+            ann: (),
+            expr: Box::new(var("x")),
+            _type: Ty::U8,
+        };
+        let expr = binop(cast.clone(), Opcode::Sub, cast);
+        assert_simplifies(expr.clone(), expr);
+    }
+
+    #[test]
+    fn double_negation_test() {
+        let x = var("x");
+        let expr = Expr::unary_op_default(
+            UOpcode::Neg,
+            Expr::unary_op_default(UOpcode::Neg, x.clone()),
+        );
+        assert_simplifies(expr, x);
+    }
+
+    #[test]
+    fn boolean_test() {
+        let b = var("b");
+        let t = Expr::bool_default(true);
+        let f = Expr::bool_default(false);
+        assert_simplifies(binop(t.clone(), Opcode::And, b.clone()), b.clone());
+        assert_simplifies(binop(b.clone(), Opcode::And, t.clone()), b.clone());
+        assert_simplifies(binop(f.clone(), Opcode::And, b.clone()), f.clone());
+        assert_simplifies(binop(f.clone(), Opcode::Or, b.clone()), b.clone());
+        assert_simplifies(binop(b.clone(), Opcode::Or, t.clone()), t.clone());
+    }
+
+    #[test]
+    fn if_then_else_test() {
+        let if_then_else = |cond| Expr::IfThenElse {
+            ann: (),
+            cond: Box::new(cond),
+            _then: Box::new(var("x")),
+            _else: Box::new(var("y")),
+        };
+        assert_simplifies(if_then_else(Expr::bool_default(true)), var("x"));
+        assert_simplifies(if_then_else(Expr::bool_default(false)), var("y"));
+        assert_simplifies(if_then_else(var("b")), if_then_else(var("b")));
+    }
+
+    #[test]
+    fn reassociate_test() {
+        let x = var("x");
+        let expr = binop(
+            binop(x.clone(), Opcode::Add, Expr::field_default(1)),
+            Opcode::Add,
+            Expr::field_default(2),
+        );
+        assert_simplifies(expr, binop(x.clone(), Opcode::Add, Expr::field_default(3)));
+        let expr = binop(
+            Expr::field_default(3),
+            Opcode::Mul,
+            binop(Expr::field_default(2), Opcode::Mul, x.clone()),
+        );
+        assert_simplifies(expr, binop(x.clone(), Opcode::Mul, Expr::field_default(6)));
+        // the constants cancel out
+        let expr = binop(
+            binop(x.clone(), Opcode::Add, Expr::field_default(1)),
+            Opcode::Add,
+            Expr::field_default(-1),
+        );
+        assert_simplifies(expr, x);
+    }
+
+    // a small deterministic generator, so that failures can be reproduced
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self, bound: u64) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0 % bound
+        }
+    }
+
+    fn random_field(rng: &mut Rng, depth: usize) -> Expr<()> {
+        let leaf = depth == 0 || rng.next(4) == 0;
+        if leaf {
+            return match rng.next(4) {
+                0 => var("x"),
+                1 => var("y"),
+                _ => Expr::field_default(rng.next(5) as i128 - 2),
+            };
+        }
+        match rng.next(6) {
+            0 => Expr::unary_op_default(UOpcode::Neg, random_field(rng, depth - 1)),
+            1 => binop(
+                random_field(rng, depth - 1),
+                Opcode::Pow,
+                Expr::field_default(rng.next(3) as i128),
+            ),
+            2 => Expr::IfThenElse {
+                ann: (),
+                cond: Box::new(random_bool(rng, depth - 1)),
+                _then: Box::new(random_field(rng, depth - 1)),
+                _else: Box::new(random_field(rng, depth - 1)),
+            },
+            n => {
+                let op = [Opcode::Add, Opcode::Sub, Opcode::Mul][n as usize - 3];
+                binop(
+                    random_field(rng, depth - 1),
+                    op,
+                    random_field(rng, depth - 1),
+                )
+            }
+        }
+    }
+
+    fn random_bool(rng: &mut Rng, depth: usize) -> Expr<()> {
+        if depth == 0 || rng.next(3) == 0 {
+            return match rng.next(3) {
+                0 => var("b"),
+                n => Expr::bool_default(n == 1),
+            };
+        }
+        match rng.next(3) {
+            0 => binop(
+                random_field(rng, depth - 1),
+                Opcode::Eq,
+                random_field(rng, depth - 1),
+            ),
+            n => {
+                let op = if n == 1 { Opcode::And } else { Opcode::Or };
+                binop(random_bool(rng, depth - 1), op, random_bool(rng, depth - 1))
+            }
+        }
+    }
+
+    #[test]
+    fn interpreter_agreement_test() {
+        let mut rng = Rng(0x5eed);
+        for _ in 0..500 {
+            let expr = random_field(&mut rng, 5);
+            let simplified = simplify(expr.clone(), &context());
+            let inputs: HashMap<Ident, Literal> = HashMap::from([
+                (Ident::new("x"), Literal::Field(rng.next(7) as i128 - 3)),
+                (Ident::new("y"), Literal::Field(rng.next(1000) as i128)),
+                (Ident::new("b"), Literal::Boolean(rng.next(2) == 0)),
+            ]);
+            let expected = interpret(&mut Context::from(inputs.clone()), &expr).unwrap();
+            let actual = interpret(&mut Context::from(inputs), &simplified).unwrap();
+            assert_eq!(
+                expected,
+                actual,
+                "{} simplified to {}",
+                expr.format(),
+                simplified.format()
+            );
+        }
+    }
+}
@@ -3,16 +3,61 @@ use derive_more::Display;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
-#[derive(Debug, PartialEq, Clone, Display, Copy, Serialize, Deserialize, Eq)]
+#[derive(Debug, PartialEq, Clone, Display, Serialize, Deserialize, Eq, Hash)]
 pub enum Ty {
     Field,
     Boolean,
+    U8,
+    U32,
+    U64,
+    #[display(fmt = "[{}; {}]", _0, _1)]
+    Array(Box<Ty>, usize),
+}
+
+impl Ty {
+    // the bit width of an unsigned integer type (none for the other types)
+    pub fn uint_bits(&self) -> Option<usize> {
+        match self {
+            Ty::U8 => Some(8),
+            Ty::U32 => Some(32),
+            Ty::U64 => Some(64),
+            Ty::Field | Ty::Boolean | Ty::Array(_, _) => None,
+        }
+    }
+
+    pub fn is_uint(&self) -> bool {
+        self.uint_bits().is_some()
+    }
+
+    // the number of field elements a value of the type is made of
+    pub fn num_elements(&self) -> usize {
+        match self {
+            Ty::Array(elem_ty, len) => len * elem_ty.num_elements(),
+            _ => 1,
+        }
+    }
+
+    // the type of `poseidon` digests
+    pub fn digest() -> Ty {
+        Ty::Array(Box::new(Ty::Field), 4)
+    }
+
+    // the largest value representable by an unsigned integer type
+    pub fn uint_max(&self) -> Option<u64> {
+        self.uint_bits().map(|bits| u64::MAX >> (64 - bits))
+    }
 }
 
 pub struct TypeContext {
     pub context: HashMap<Ident, Ty>,
 }
 
+impl Default for TypeContext {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl TypeContext {
     pub fn new() -> Self {
         TypeContext {
@@ -0,0 +1,200 @@
+use crate::backend::Backend;
+use crate::plonk::circuit_builder::from_bool;
+use crate::plonk::F;
+use anyhow::{anyhow, Result};
+use err_derive::Error;
+use plonky2::field::types::PrimeField64;
+use plonky2::hash::poseidon::PoseidonHash;
+use plonky2::plonk::config::Hasher;
+use std::collections::HashMap;
+
+#[derive(Debug, Error)]
+pub enum CheckerError {
+    #[error(display = "Input {} has no value", _0)]
+    MissingInput(usize),
+    #[error(display = "Assertion {} does not hold", _0)]
+    Unsatisfied(usize),
+}
+
+// how the value of a target is computed from the targets before it
+#[derive(Debug, Clone)]
+enum Node {
+    Const(F),
+    Input,
+    Add(usize, usize),
+    Sub(usize, usize),
+    Mul(usize, usize),
+    Select(usize, usize, usize),
+    IsEqual(usize, usize),
+    // the given bit of the canonical value of a target
+    Bit(usize, usize),
+    // the given element of the Poseidon digest of the targets
+    Hash(Vec<usize>, usize),
+}
+
+// a backend that evaluates the circuit instead of proving it, which makes it a fast way to
+// test the circuit builder. Proving computes every target from the inputs and checks the
+// assertions, a proof is just the public values.
+#[derive(Debug, Default)]
+pub struct ConstraintChecker {
+    nodes: Vec<Node>,
+    assertions: Vec<(usize, usize)>,
+    public: Vec<usize>,
+}
+
+pub struct CheckedProof {
+    pub public_values: Vec<F>,
+}
+
+impl ConstraintChecker {
+    fn push(&mut self, node: Node) -> usize {
+        self.nodes.push(node);
+        self.nodes.len() - 1
+    }
+}
+
+impl Backend for ConstraintChecker {
+    type Target = usize;
+    type Proof = CheckedProof;
+
+    fn constant(&mut self, value: F) -> usize {
+        self.push(Node::Const(value))
+    }
+
+    fn input(&mut self) -> usize {
+        self.push(Node::Input)
+    }
+
+    fn add(&mut self, a: usize, b: usize) -> usize {
+        self.push(Node::Add(a, b))
+    }
+
+    fn sub(&mut self, a: usize, b: usize) -> usize {
+        self.push(Node::Sub(a, b))
+    }
+
+    fn mul(&mut self, a: usize, b: usize) -> usize {
+        self.push(Node::Mul(a, b))
+    }
+
+    fn select(&mut self, cond: usize, a: usize, b: usize) -> usize {
+        self.push(Node::Select(cond, a, b))
+    }
+
+    fn is_equal(&mut self, a: usize, b: usize) -> usize {
+        self.push(Node::IsEqual(a, b))
+    }
+
+    fn assert_equal(&mut self, a: usize, b: usize) {
+        self.assertions.push((a, b));
+    }
+
+    // the bits are computed from the value, so the decomposition is checked against it
+    fn split_le(&mut self, a: usize, num_bits: usize) -> Vec<usize> {
+        let bits: Vec<usize> = (0..num_bits).map(|i| self.push(Node::Bit(a, i))).collect();
+        for bit in &bits {
+            self.assert_bool(*bit);
+        }
+        let sum = self.le_sum(&bits);
+        self.assert_equal(sum, a);
+        bits
+    }
+
+    fn hash(&mut self, inputs: Vec<usize>) -> [usize; 4] {
+        [0, 1, 2, 3].map(|i| self.push(Node::Hash(inputs.clone(), i)))
+    }
+
+    fn public_output(&mut self, a: usize) {
+        self.public.push(a);
+    }
+
+    fn size(&self) -> usize {
+        self.nodes.len() + self.assertions.len()
+    }
+
+    fn prove(self, inputs: Vec<(usize, F)>) -> Result<CheckedProof> {
+        let inputs: HashMap<usize, F> = inputs.into_iter().collect();
+        let mut values: Vec<F> = Vec::with_capacity(self.nodes.len());
+        for (target, node) in self.nodes.iter().enumerate() {
+            let value = match node {
+                Node::Const(value) => *value,
+                Node::Input => *inputs
+                    .get(&target)
+                    .ok_or_else(|| anyhow!(CheckerError::MissingInput(target)))?,
+                Node::Add(a, b) => values[*a] + values[*b],
+                Node::Sub(a, b) => values[*a] - values[*b],
+                Node::Mul(a, b) => values[*a] * values[*b],
+                Node::Select(cond, a, b) => values[*cond] * (values[*a] - values[*b]) + values[*b],
+                Node::IsEqual(a, b) => from_bool(values[*a] == values[*b]),
+                Node::Bit(a, i) => {
+                    from_bool(*i < 64 && (values[*a].to_canonical_u64() >> i) & 1 == 1)
+                }
+                Node::Hash(args, i) => {
+                    let args: Vec<F> = args.iter().map(|arg| values[*arg]).collect();
+                    PoseidonHash::hash_no_pad(&args).elements[*i]
+                }
+            };
+            values.push(value);
+        }
+        for (i, (a, b)) in self.assertions.iter().enumerate() {
+            if values[*a] != values[*b] {
+                return Err(anyhow!(CheckerError::Unsatisfied(i)));
+            }
+        }
+        Ok(CheckedProof {
+            public_values: self.public.iter().map(|target| values[*target]).collect(),
+        })
+    }
+
+    // the assertions were checked when proving
+    fn verify(_proof: &CheckedProof) -> Result<()> {
+        Ok(())
+    }
+
+    fn public_values(proof: &CheckedProof) -> Vec<F> {
+        proof.public_values.clone()
+    }
+}
+
+#[cfg(test)]
+mod checker_tests {
+    use super::*;
+    use plonky2::field::types::Field;
+
+    // x * x with x decomposed into 4 bits
+    fn square_checker() -> (ConstraintChecker, usize) {
+        let mut checker = ConstraintChecker::default();
+        let x = checker.input();
+        let bits = checker.split_le(x, 4);
+        let square = checker.mul(x, x);
+        checker.public_output(square);
+        checker.public_output(bits[3]);
+        (checker, x)
+    }
+
+    #[test]
+    fn checker_test() {
+        let (checker, x) = square_checker();
+        let proof = checker.prove(vec![(x, F::from_canonical_u32(9))]).unwrap();
+        assert_eq!(
+            ConstraintChecker::public_values(&proof),
+            vec![F::from_canonical_u32(81), F::ONE]
+        );
+        let (checker, x) = square_checker();
+        match checker.prove(vec![(x, F::from_canonical_u32(16))]) {
+            Err(err) => match err.downcast_ref() {
+                Some(CheckerError::Unsatisfied(_)) => (),
+                _ => panic!("Expected Unsatisfied error"),
+            },
+            _ => panic!("Expected Unsatisfied error"),
+        };
+        let (checker, _) = square_checker();
+        match checker.prove(vec![]) {
+            Err(err) => match err.downcast_ref() {
+                Some(CheckerError::MissingInput(_)) => (),
+                _ => panic!("Expected MissingInput error"),
+            },
+            _ => panic!("Expected MissingInput error"),
+        };
+    }
+}
@@ -0,0 +1,158 @@
+pub mod checker;
+
+pub use checker::ConstraintChecker;
+
+use crate::plonk::F;
+use anyhow::Result;
+use plonky2::field::types::Field;
+use std::fmt::Debug;
+
+// the primitives the circuit builder lowers programs to. Values are field elements and
+// booleans are values constrained to be 0 or 1. The provided methods are expressed with the
+// primitives, backends override them when they have a cheaper encoding.
+pub trait Backend {
+    type Target: Copy + Debug;
+    type Proof;
+
+    fn constant(&mut self, value: F) -> Self::Target;
+    // a value that is only known when proving
+    fn input(&mut self) -> Self::Target;
+    fn add(&mut self, a: Self::Target, b: Self::Target) -> Self::Target;
+    fn sub(&mut self, a: Self::Target, b: Self::Target) -> Self::Target;
+    fn mul(&mut self, a: Self::Target, b: Self::Target) -> Self::Target;
+    // `a` when `cond` is one and `b` when it is zero
+    fn select(&mut self, cond: Self::Target, a: Self::Target, b: Self::Target) -> Self::Target;
+    // one when the values are equal, zero otherwise
+    fn is_equal(&mut self, a: Self::Target, b: Self::Target) -> Self::Target;
+    fn assert_equal(&mut self, a: Self::Target, b: Self::Target);
+    // the little-endian bits of a value, the circuit is unsatisfiable if it does not fit
+    fn split_le(&mut self, a: Self::Target, num_bits: usize) -> Vec<Self::Target>;
+    // the Poseidon digest of the inputs
+    fn hash(&mut self, inputs: Vec<Self::Target>) -> [Self::Target; 4];
+    // append a value to the public inputs of the proof, after those registered before it
+    fn public_output(&mut self, a: Self::Target);
+    // the size of the circuit built so far, in the unit of cost of the backend
+    fn size(&self) -> usize;
+    fn prove(self, inputs: Vec<(Self::Target, F)>) -> Result<Self::Proof>;
+    fn verify(proof: &Self::Proof) -> Result<()>;
+    // the values of the public outputs in the order they were registered
+    fn public_values(proof: &Self::Proof) -> Vec<F>;
+
+    fn zero(&mut self) -> Self::Target {
+        self.constant(F::ZERO)
+    }
+
+    fn one(&mut self) -> Self::Target {
+        self.constant(F::ONE)
+    }
+
+    fn add_const(&mut self, a: Self::Target, c: F) -> Self::Target {
+        let c = self.constant(c);
+        self.add(a, c)
+    }
+
+    fn mul_const(&mut self, c: F, a: Self::Target) -> Self::Target {
+        let c = self.constant(c);
+        self.mul(c, a)
+    }
+
+    // c * a + b
+    fn mul_const_add(&mut self, c: F, a: Self::Target, b: Self::Target) -> Self::Target {
+        let product = self.mul_const(c, a);
+        self.add(product, b)
+    }
+
+    fn add_many(&mut self, terms: Vec<Self::Target>) -> Self::Target {
+        let zero = self.zero();
+        terms
+            .into_iter()
+            .fold(zero, |acc, term| self.add(acc, term))
+    }
+
+    fn assert_zero(&mut self, a: Self::Target) {
+        let zero = self.zero();
+        self.assert_equal(a, zero);
+    }
+
+    fn assert_one(&mut self, a: Self::Target) {
+        let one = self.one();
+        self.assert_equal(a, one);
+    }
+
+    fn assert_bool(&mut self, a: Self::Target) {
+        let square = self.mul(a, a);
+        self.assert_equal(square, a);
+    }
+
+    fn and(&mut self, a: Self::Target, b: Self::Target) -> Self::Target {
+        self.mul(a, b)
+    }
+
+    // a + b - ab
+    fn or(&mut self, a: Self::Target, b: Self::Target) -> Self::Target {
+        let sum = self.add(a, b);
+        let product = self.mul(a, b);
+        self.sub(sum, product)
+    }
+
+    fn not(&mut self, a: Self::Target) -> Self::Target {
+        let one = self.one();
+        self.sub(one, a)
+    }
+
+    // the number spelled by little-endian bits
+    fn le_sum(&mut self, bits: &[Self::Target]) -> Self::Target {
+        let mut sum = self.zero();
+        for bit in bits.iter().rev() {
+            sum = self.mul_const_add(F::TWO, sum, *bit);
+        }
+        sum
+    }
+
+    fn range_check(&mut self, a: Self::Target, num_bits: usize) {
+        self.split_le(a, num_bits);
+    }
+
+    // the low `n` bits of a value of at most `num_bits` bits, and the remaining high bits
+    fn split_low_high(
+        &mut self,
+        a: Self::Target,
+        n: usize,
+        num_bits: usize,
+    ) -> (Self::Target, Self::Target) {
+        let bits = self.split_le(a, num_bits);
+        let low = self.le_sum(&bits[..n]);
+        let high = self.le_sum(&bits[n..]);
+        (low, high)
+    }
+
+    // square-and-multiply with a known exponent
+    fn exp_u64(&mut self, base: Self::Target, exponent: u64) -> Self::Target {
+        let mut result = self.one();
+        let mut power = base;
+        let num_bits = 64 - exponent.leading_zeros();
+        for i in 0..num_bits {
+            if (exponent >> i) & 1 == 1 {
+                result = self.mul(result, power);
+            }
+            if i + 1 < num_bits {
+                power = self.mul(power, power);
+            }
+        }
+        result
+    }
+
+    // raise `base` to the number spelled by little-endian bits
+    fn exp_from_bits(&mut self, base: Self::Target, bits: &[Self::Target]) -> Self::Target {
+        let mut result = self.one();
+        let mut power = base;
+        for (i, bit) in bits.iter().enumerate() {
+            let product = self.mul(result, power);
+            result = self.select(*bit, product, result);
+            if i + 1 < bits.len() {
+                power = self.mul(power, power);
+            }
+        }
+        result
+    }
+}
@@ -1,52 +1,147 @@
 use crate::ast::annotation::Span;
-use crate::ast::Binder;
-use crate::ast::{annotation::HasSourceLoc, inline, optimize, Expr, Ident, Program};
+use crate::ast::{annotation::HasSourceLoc, Expr, Ident, Program};
+use crate::ast::{fold_exponents, Binder, Declaration, Literal, Opcode, Ty, TypeContext};
+use crate::ir::{lower, Circuit, Op};
+use crate::passes::PassManager;
 use anyhow::{anyhow, Result};
 use err_derive::Error;
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::hash::Hash;
 
 #[derive(Debug, Error)]
 pub enum CompilerError {
     #[error(display = "Unconstrained variable: {:?}", _0)]
     UnconstrainedVariable(Vec<(Ident, Span)>),
+    #[error(display = "Output {} can't have array type {}", _0, _1)]
+    ArrayOutput(Ident, Ty),
+    #[error(
+        display = "Exponent at {} must be a non-negative constant or an unsigned integer",
+        _0
+    )]
+    InvalidExponent(Span),
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
 pub struct CompiledProgram<A> {
-    pub public_vars: Vec<Ident>,
-    pub expr: Expr<A>,
+    pub public_vars: Vec<(Ident, Ty)>,
+    pub private_vars: Vec<(Ident, Ty)>,
+    // the public outputs in the order they are exposed by the proof
+    pub outputs: Vec<(Ident, Expr<A>)>,
+    // the outputs lowered to the representation the backends consume
+    pub ir: Circuit<A>,
 }
 
 pub fn compile<A: Clone + HasSourceLoc + Eq + Hash>(
     program: Program<A>,
 ) -> Result<CompiledProgram<A>> {
-    program.typecheck()?;
+    compile_with(program, &PassManager::default())
+}
+
+pub fn compile_with<A: Clone + HasSourceLoc + Eq + Hash>(
+    program: Program<A>,
+    passes: &PassManager,
+) -> Result<CompiledProgram<A>> {
+    // the circuit exposes outputs as single field elements
+    for (name, ty) in program.typecheck()? {
+        if let Ty::Array(_, _) = ty {
+            return Err(anyhow!(CompilerError::ArrayOutput(name, ty)));
+        }
+    }
     let public_vars: Vec<Binder<A>> = program
         .public_variable_decls()
         .iter()
         .map(|decl| decl.binder().clone())
         .collect();
-    let expr = optimize(inline(program));
-    assert_normal_form(public_vars.clone(), &expr)?;
-    let public_vars = public_vars.into_iter().map(|x| x.var().clone()).collect();
-    Ok(CompiledProgram { public_vars, expr })
+    let private_vars: Vec<Binder<A>> = program
+        .private_variable_decls()
+        .iter()
+        .map(|decl| decl.binder().clone())
+        .collect();
+    // after inlining, the outputs only refer to the inputs
+    let mut context = TypeContext::new();
+    for binder in public_vars.iter().chain(private_vars.iter()) {
+        let ty = binder.ty().unwrap_or(Ty::Field);
+        context.context.insert(binder.var().clone(), ty);
+    }
+    // the instructions computing `let` bindings are named after them, annotations without a
+    // location can't tell the bindings apart
+    let names: HashMap<Span, Ident> = program
+        .decls
+        .iter()
+        .filter_map(|decl| match decl {
+            Declaration::VarAssignment { binder, expr } => {
+                Some((expr.source_loc(), binder.var().clone()))
+            }
+            _ => None,
+        })
+        .filter(|(span, _)| *span != Span::default())
+        .collect();
+    let outputs: Vec<(Ident, Expr<A>)> = passes
+        .run_on_program(program, &context)
+        .into_iter()
+        .map(|(name, expr)| (name, fold_exponents(expr)))
+        .collect();
+    for (_, expr) in &outputs {
+        assert_normal_form([public_vars.clone(), private_vars.clone()].concat(), expr)?;
+    }
+    let ir = lower(
+        public_vars.clone(),
+        private_vars.clone(),
+        outputs.clone(),
+        names,
+    );
+    let ir = passes.run_on_circuit(ir);
+    assert_constant_exponents(&ir)?;
+    // input variables are always declared with a type by the parser
+    let typed_vars = |vars: Vec<Binder<A>>| -> Vec<(Ident, Ty)> {
+        vars.into_iter()
+            .map(|x| (x.var().clone(), x.ty().unwrap_or(Ty::Field)))
+            .collect()
+    };
+    let public_vars = typed_vars(public_vars);
+    let private_vars = typed_vars(private_vars);
+    Ok(CompiledProgram {
+        public_vars,
+        private_vars,
+        outputs,
+        ir,
+    })
+}
+
+// field exponents are unrolled into multiplications, so they must be literals once they are
+// folded. integer exponents are decomposed into their bits instead.
+fn assert_constant_exponents<A: HasSourceLoc>(ir: &Circuit<A>) -> Result<()> {
+    for instruction in &ir.instructions {
+        if let Op::BinOp(Opcode::Pow, _, rhs) = instruction.op {
+            let exponent = &ir.instructions[rhs.0];
+            match exponent.op {
+                Op::Const(Literal::Field(n)) if n >= 0 => (),
+                _ if exponent.ty.is_uint() => (),
+                _ => {
+                    return Err(anyhow!(CompilerError::InvalidExponent(
+                        exponent.ann.source_loc()
+                    )))
+                }
+            }
+        }
+    }
+    Ok(())
 }
 
-// normal form means that every public variable appears in `expr`
+// normal form means that every variable in `expr` is an input (public or private)
 fn assert_normal_form<A: Clone + HasSourceLoc>(
-    public_vars: Vec<Binder<A>>,
+    input_vars: Vec<Binder<A>>,
     expr: &Expr<A>,
 ) -> Result<()> {
-    let public_vars = public_vars
+    let input_vars = input_vars
         .into_iter()
         .map(|x| x.var().clone())
         .collect::<HashSet<Ident>>();
     let unconstrained_vars: Vec<(Ident, A)> = expr
         .variables()
         .into_iter()
-        .filter(|x| !public_vars.contains(&x.0))
+        .filter(|x| !input_vars.contains(&x.0))
         .collect();
     if !unconstrained_vars.is_empty() {
         Err(anyhow!(CompilerError::UnconstrainedVariable(
@@ -0,0 +1,336 @@
+use crate::ast::{Ident, Literal, Ty};
+use anyhow::{anyhow, Result};
+use err_derive::Error;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::Path;
+
+#[derive(Debug, Error)]
+pub enum ContextError {
+    #[error(
+        display = "Input {} is declared as {} but given a {} value",
+        _0,
+        _1,
+        _2
+    )]
+    TypeMismatch(Ident, Ty, Ty),
+    #[error(display = "Input {} of type {} can't be read from {}", _0, _1, _2)]
+    InvalidValue(Ident, Ty, String),
+    #[error(display = "Value {} of input {} doesn't fit in {}", _1, _0, _2)]
+    OutOfRange(Ident, String, Ty),
+    #[error(display = "Input {} has {} elements but {} are given", _0, _1, _2)]
+    LengthMismatch(Ident, usize, usize),
+    #[error(display = "{} is not an input of the program", _0)]
+    UnknownInput(Ident),
+    #[error(display = "No value for input {} of type {}", _0, _1)]
+    MissingInput(Ident, Ty),
+    #[error(display = "Expected name=value, got {}", _0)]
+    InvalidDefinition(String),
+}
+
+// the formats a context file can be written in, told apart by the extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContextFormat {
+    Json,
+    Toml,
+}
+
+impl ContextFormat {
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => ContextFormat::Toml,
+            _ => ContextFormat::Json,
+        }
+    }
+}
+
+// the values of a context before they are checked against the types of the inputs
+pub type RawContext = HashMap<Ident, Value>;
+
+pub fn parse_context(contents: &str, format: ContextFormat) -> Result<RawContext> {
+    let values: HashMap<String, Value> = match format {
+        ContextFormat::Json => serde_json::from_str(contents)?,
+        ContextFormat::Toml => toml::from_str(contents)?,
+    };
+    Ok(values
+        .into_iter()
+        .map(|(name, value)| (Ident::new(&name), value))
+        .collect())
+}
+
+// a `-D name=value` definition from the command line, the value is read like a string in a
+// context file
+pub fn parse_definition(definition: &str) -> Result<(Ident, Value)> {
+    match definition.split_once('=') {
+        Some((name, value)) if !name.trim().is_empty() => Ok((
+            Ident::new(name.trim()),
+            Value::String(value.trim().to_string()),
+        )),
+        _ => Err(anyhow!(ContextError::InvalidDefinition(
+            definition.to_string()
+        ))),
+    }
+}
+
+// apply the definitions on top of the context and give every input a literal of its
+// declared type
+pub fn load_context(
+    mut context: RawContext,
+    definitions: &[String],
+    inputs: &[(Ident, Ty)],
+) -> Result<HashMap<Ident, Literal>> {
+    for definition in definitions {
+        let (name, value) = parse_definition(definition)?;
+        context.insert(name, value);
+    }
+    check_context(context, inputs)
+}
+
+pub fn check_context(
+    mut context: RawContext,
+    inputs: &[(Ident, Ty)],
+) -> Result<HashMap<Ident, Literal>> {
+    let mut literals = HashMap::new();
+    for (name, ty) in inputs {
+        let value = context
+            .remove(name)
+            .ok_or_else(|| anyhow!(ContextError::MissingInput(name.clone(), ty.clone())))?;
+        literals.insert(name.clone(), literal(name, &value, ty)?);
+    }
+    let mut unknown: Vec<Ident> = context.into_keys().collect();
+    unknown.sort_by_key(|name| name.to_string());
+    match unknown.into_iter().next() {
+        Some(name) => Err(anyhow!(ContextError::UnknownInput(name))),
+        None => Ok(literals),
+    }
+}
+
+// the type a literal is tagged with, as in `{"U8": 3}`
+fn tag_type(tag: &str) -> Option<Ty> {
+    match tag {
+        "Field" => Some(Ty::Field),
+        "Boolean" => Some(Ty::Boolean),
+        "U8" => Some(Ty::U8),
+        "U32" => Some(Ty::U32),
+        "U64" => Some(Ty::U64),
+        _ => None,
+    }
+}
+
+fn literal(name: &Ident, value: &Value, ty: &Ty) -> Result<Literal> {
+    let invalid = || {
+        anyhow!(ContextError::InvalidValue(
+            name.clone(),
+            ty.clone(),
+            value.to_string()
+        ))
+    };
+    match value {
+        Value::Object(fields) if fields.len() == 1 => {
+            let (tag, value) = fields.iter().next().unwrap();
+            match (tag_type(tag), ty) {
+                (Some(tagged), _) if tagged == *ty => literal(name, value, ty),
+                (None, Ty::Array(_, _)) if tag == "Array" => literal(name, value, ty),
+                (Some(tagged), _) => Err(anyhow!(ContextError::TypeMismatch(
+                    name.clone(),
+                    ty.clone(),
+                    tagged
+                ))),
+                (None, _) => Err(invalid()),
+            }
+        }
+        Value::Array(values) => match ty {
+            Ty::Array(elem, len) if values.len() == *len => values
+                .iter()
+                .map(|value| literal(name, value, elem))
+                .collect::<Result<Vec<Literal>>>()
+                .map(Literal::Array),
+            Ty::Array(_, len) => Err(anyhow!(ContextError::LengthMismatch(
+                name.clone(),
+                *len,
+                values.len()
+            ))),
+            _ => Err(invalid()),
+        },
+        Value::Bool(b) => match ty {
+            Ty::Boolean => Ok(Literal::Boolean(*b)),
+            _ => Err(anyhow!(ContextError::TypeMismatch(
+                name.clone(),
+                ty.clone(),
+                Ty::Boolean
+            ))),
+        },
+        Value::String(s) if ty == &Ty::Boolean => match s.as_str() {
+            "true" => Ok(Literal::Boolean(true)),
+            "false" => Ok(Literal::Boolean(false)),
+            _ => Err(invalid()),
+        },
+        Value::Number(_) if !is_number(ty) => Err(anyhow!(ContextError::TypeMismatch(
+            name.clone(),
+            ty.clone(),
+            Ty::Field
+        ))),
+        Value::Number(n) => {
+            let n = n.as_i64().map(i128::from).or(n.as_u64().map(i128::from));
+            number(name, n.ok_or_else(invalid)?, ty)
+        }
+        Value::String(s) if is_number(ty) => {
+            number(name, parse_integer(s).ok_or_else(invalid)?, ty)
+        }
+        _ => Err(invalid()),
+    }
+}
+
+fn is_number(ty: &Ty) -> bool {
+    *ty == Ty::Field || ty.is_uint()
+}
+
+// a decimal or 0x-prefixed hexadecimal integer, possibly negative
+fn parse_integer(s: &str) -> Option<i128> {
+    let (negative, digits) = match s.trim().strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, s.trim()),
+    };
+    let n = match digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        Some(hex) => i128::from_str_radix(hex, 16).ok()?,
+        None => digits.parse::<i128>().ok()?,
+    };
+    Some(if negative { -n } else { n })
+}
+
+fn number(name: &Ident, n: i128, ty: &Ty) -> Result<Literal> {
+    let literal = match ty {
+        Ty::Field => Literal::field(n),
+        Ty::U8 => u8::try_from(n).ok().map(Literal::U8),
+        Ty::U32 => u32::try_from(n).ok().map(Literal::U32),
+        Ty::U64 => u64::try_from(n).ok().map(Literal::U64),
+        ty => unreachable!("Numbers are only read for fields and integers, not {}", ty),
+    };
+    literal.ok_or_else(|| {
+        anyhow!(ContextError::OutOfRange(
+            name.clone(),
+            n.to_string(),
+            ty.clone()
+        ))
+    })
+}
+
+#[cfg(test)]
+mod context_tests {
+    use super::*;
+
+    fn inputs(inputs: Vec<(&str, Ty)>) -> Vec<(Ident, Ty)> {
+        inputs
+            .into_iter()
+            .map(|(name, ty)| (Ident::new(name), ty))
+            .collect()
+    }
+
+    fn context_error(contents: &str, inputs: &[(Ident, Ty)]) -> ContextError {
+        let context = parse_context(contents, ContextFormat::Json).unwrap();
+        match check_context(context, inputs) {
+            Err(err) => err.downcast().unwrap(),
+            Ok(_) => panic!("Expected {} to be rejected", contents),
+        }
+    }
+
+    #[test]
+    fn literals_test() {
+        let inputs = inputs(vec![
+            ("a", Ty::Field),
+            ("b", Ty::Boolean),
+            ("c", Ty::U8),
+            ("d", Ty::U32),
+            ("e", Ty::U64),
+            ("f", Ty::Field),
+            ("g", Ty::Array(Box::new(Ty::U8), 2)),
+        ]);
+        let json = r#"{"a": -3, "b": true, "c": "0xff", "d": {"U32": "70000"},
+                       "e": 18446744073709551615, "f": {"Field": 2}, "g": {"Array": [1, "2"]}}"#;
+        let toml = "a = -3\nb = true\nc = \"0xff\"\nd = { U32 = \"70000\" }\n\
+                    e = \"18446744073709551615\"\nf = { Field = 2 }\ng = [1, { U8 = 2 }]\n";
+        for (contents, format) in [(json, ContextFormat::Json), (toml, ContextFormat::Toml)] {
+            let context = check_context(parse_context(contents, format).unwrap(), &inputs);
+            let context = context.unwrap();
+            assert_eq!(context[&Ident::new("a")], Literal::Field(-3));
+            assert_eq!(context[&Ident::new("b")], Literal::Boolean(true));
+            assert_eq!(context[&Ident::new("c")], Literal::U8(255));
+            assert_eq!(context[&Ident::new("d")], Literal::U32(70000));
+            assert_eq!(context[&Ident::new("e")], Literal::U64(u64::MAX));
+            assert_eq!(context[&Ident::new("f")], Literal::Field(2));
+            assert_eq!(
+                context[&Ident::new("g")],
+                Literal::Array(vec![Literal::U8(1), Literal::U8(2)])
+            );
+        }
+    }
+
+    #[test]
+    fn field_test() {
+        // any canonical field element can be given, such as a hash digest
+        let inputs = inputs(vec![("x", Ty::Field)]);
+        let context = parse_context(r#"{"x": 18446744069414584320}"#, ContextFormat::Json);
+        let context = check_context(context.unwrap(), &inputs).unwrap();
+        assert_eq!(
+            context[&Ident::new("x")],
+            Literal::Field(18446744069414584320)
+        );
+        match context_error(r#"{"x": "0xffffffff00000001"}"#, &inputs) {
+            ContextError::OutOfRange(_, _, Ty::Field) => (),
+            err => panic!("Expected OutOfRange error, got {}", err),
+        }
+    }
+
+    #[test]
+    fn definitions_test() {
+        let inputs = inputs(vec![("x", Ty::U8), ("y", Ty::Boolean)]);
+        let context = parse_context(r#"{"x": 1, "y": false}"#, ContextFormat::Json).unwrap();
+        let definitions = ["x = 5".to_string(), "y=true".to_string()];
+        let context = load_context(context, &definitions, &inputs).unwrap();
+        assert_eq!(context[&Ident::new("x")], Literal::U8(5));
+        assert_eq!(context[&Ident::new("y")], Literal::Boolean(true));
+        assert!(parse_definition("x").is_err());
+        assert!(parse_definition("=5").is_err());
+    }
+
+    #[test]
+    fn errors_test() {
+        let inputs = inputs(vec![("x", Ty::U8)]);
+        match context_error(r#"{"x": {"U32": 3}}"#, &inputs) {
+            ContextError::TypeMismatch(_, Ty::U8, Ty::U32) => (),
+            err => panic!("Expected TypeMismatch error, got {}", err),
+        }
+        match context_error(r#"{"x": true}"#, &inputs) {
+            ContextError::TypeMismatch(_, Ty::U8, Ty::Boolean) => (),
+            err => panic!("Expected TypeMismatch error, got {}", err),
+        }
+        match context_error(r#"{"x": 256}"#, &inputs) {
+            ContextError::OutOfRange(_, _, Ty::U8) => (),
+            err => panic!("Expected OutOfRange error, got {}", err),
+        }
+        match context_error(r#"{"x": "ten"}"#, &inputs) {
+            ContextError::InvalidValue(_, Ty::U8, _) => (),
+            err => panic!("Expected InvalidValue error, got {}", err),
+        }
+        match context_error(r#"{"x": [1]}"#, &inputs) {
+            ContextError::InvalidValue(_, Ty::U8, _) => (),
+            err => panic!("Expected InvalidValue error, got {}", err),
+        }
+        let path = self::inputs(vec![("path", Ty::Array(Box::new(Ty::Field), 2))]);
+        match context_error(r#"{"path": [1, 2, 3]}"#, &path) {
+            ContextError::LengthMismatch(_, 2, 3) => (),
+            err => panic!("Expected LengthMismatch error, got {}", err),
+        }
+        match context_error(r#"{}"#, &inputs) {
+            ContextError::MissingInput(_, Ty::U8) => (),
+            err => panic!("Expected MissingInput error, got {}", err),
+        }
+        match context_error(r#"{"x": 1, "z": 2}"#, &inputs) {
+            ContextError::UnknownInput(name) => assert_eq!(name, Ident::new("z")),
+            err => panic!("Expected UnknownInput error, got {}", err),
+        }
+    }
+}
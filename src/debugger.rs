@@ -0,0 +1,251 @@
+use crate::ast::annotation::{HasSourceLoc, Span};
+use crate::ast::{Expr, Ident, Literal};
+use crate::interpreter::{Observer, Value};
+use anyhow::{anyhow, Result};
+use err_derive::Error;
+use std::collections::{BTreeSet, HashMap};
+use std::io::{BufRead, Write};
+
+#[derive(Debug, Error)]
+pub enum DebugError {
+    #[error(display = "Stopped by the user")]
+    Quit,
+}
+
+const HELP: &str = "\
+s, step        evaluate the next expression
+n, next        evaluate the current expression without stopping inside it
+c, continue    run until the next breakpoint
+b, break LINE  stop at the first expression on the line
+d, delete LINE remove the breakpoint on the line
+p, print NAME  print an input or a binding that was evaluated
+l, list        print the source around the current expression
+q, quit        stop the program";
+
+// how far to run before stopping again
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    // at the next expression
+    Step,
+    // at the next expression that isn't inside the current one, which is that deep
+    Next(usize),
+    // at the next breakpoint
+    Continue,
+}
+
+// steps through the evaluation of a program, reading commands from `input`. Once the input
+// ends the program runs to completion.
+pub struct Debugger<'a, R, W> {
+    source: Vec<&'a str>,
+    breakpoints: BTreeSet<u32>,
+    mode: Mode,
+    // the lines of the expressions being evaluated, innermost last, and whether evaluation
+    // stopped at them so that their value is shown once they are evaluated
+    stack: Vec<(u32, bool)>,
+    // the inputs and the bindings evaluated so far
+    values: HashMap<Ident, Value>,
+    binding: Option<Ident>,
+    input: R,
+    output: W,
+}
+
+impl<'a, R: BufRead, W: Write> Debugger<'a, R, W> {
+    // stop at the first expression if there are no breakpoints
+    pub fn new(
+        source: &'a str,
+        inputs: &HashMap<Ident, Literal>,
+        breakpoints: &[u32],
+        input: R,
+        output: W,
+    ) -> Self {
+        Debugger {
+            source: source.lines().collect(),
+            breakpoints: breakpoints.iter().cloned().collect(),
+            mode: match breakpoints.is_empty() {
+                true => Mode::Step,
+                false => Mode::Continue,
+            },
+            stack: Vec::new(),
+            values: inputs
+                .iter()
+                .map(|(name, value)| (name.clone(), Value::from(value.clone())))
+                .collect(),
+            binding: None,
+            input,
+            output,
+        }
+    }
+
+    fn list(&mut self, line: u32) -> Result<()> {
+        let first = line.saturating_sub(2).max(1);
+        for n in first..=line + 2 {
+            if let Some(text) = self.source.get(n as usize - 1) {
+                let marker = if n == line { ">" } else { " " };
+                writeln!(self.output, "{}{:>4} | {}", marker, n, text)?;
+            }
+        }
+        Ok(())
+    }
+
+    // read commands until one of them resumes the evaluation
+    fn prompt(&mut self, span: Span, expr: String) -> Result<()> {
+        writeln!(self.output, "Stopped at {}: {}", span, expr)?;
+        if let Some(text) = self.source.get((span.line() as usize).wrapping_sub(1)) {
+            writeln!(self.output, "{:>5} | {}", span.line(), text)?;
+        }
+        loop {
+            write!(self.output, "(debug) ")?;
+            self.output.flush()?;
+            let mut command = String::new();
+            if self.input.read_line(&mut command)? == 0 {
+                self.breakpoints.clear();
+                self.mode = Mode::Continue;
+                return Ok(writeln!(self.output)?);
+            }
+            let mut words = command.split_whitespace();
+            let (command, arg) = (words.next().unwrap_or("s"), words.next());
+            let line = arg.and_then(|arg| arg.parse::<u32>().ok());
+            match (command, line) {
+                ("s" | "step", _) => self.mode = Mode::Step,
+                ("n" | "next", _) => self.mode = Mode::Next(self.stack.len() - 1),
+                ("c" | "continue", _) => self.mode = Mode::Continue,
+                ("b" | "break", Some(line)) => {
+                    self.breakpoints.insert(line);
+                    writeln!(self.output, "Breakpoint on line {}", line)?;
+                    continue;
+                }
+                ("d" | "delete", Some(line)) => {
+                    self.breakpoints.remove(&line);
+                    continue;
+                }
+                ("p" | "print", _) => {
+                    match arg.and_then(|name| self.values.get(&Ident::new(name))) {
+                        Some(value) => writeln!(self.output, "{} = {:?}", arg.unwrap(), value)?,
+                        None => writeln!(self.output, "No value for {}", arg.unwrap_or(""))?,
+                    }
+                    continue;
+                }
+                ("l" | "list", _) => {
+                    self.list(span.line())?;
+                    continue;
+                }
+                ("q" | "quit", _) => return Err(anyhow!(DebugError::Quit)),
+                _ => {
+                    writeln!(self.output, "{}", HELP)?;
+                    continue;
+                }
+            }
+            return Ok(());
+        }
+    }
+}
+
+impl<'a, R: BufRead, W: Write> Observer for Debugger<'a, R, W> {
+    fn binding(&mut self, name: &Ident) {
+        self.binding = Some(name.clone());
+    }
+
+    fn enter<A: Clone + HasSourceLoc>(&mut self, expr: &Expr<A>) -> Result<()> {
+        let line = expr.source_loc().line();
+        // only the first expression on the line stops at a breakpoint
+        let breakpoint = self.breakpoints.contains(&line)
+            && self.stack.last().map(|(parent, _)| *parent) != Some(line);
+        let stop = breakpoint
+            || match self.mode {
+                Mode::Step => true,
+                Mode::Next(depth) => self.stack.len() <= depth,
+                Mode::Continue => false,
+            };
+        self.stack.push((line, stop));
+        if stop {
+            self.prompt(expr.source_loc(), expr.format())?;
+        }
+        Ok(())
+    }
+
+    fn exit<A: Clone + HasSourceLoc>(&mut self, expr: &Expr<A>, result: &Result<Value>) {
+        let (_, stopped) = self.stack.pop().unwrap();
+        if let Ok(value) = result {
+            // the observer can't fail here, a broken output shows up at the next prompt
+            if stopped {
+                let _ = writeln!(self.output, "{} => {:?}", expr.format(), value);
+            }
+            if let (true, Some(name)) = (self.stack.is_empty(), &self.binding) {
+                self.values.insert(name.clone(), value.clone());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod debugger_tests {
+    use super::*;
+    use crate::ast::Program;
+    use crate::interpreter::interpret_program_with;
+    use crate::parser;
+
+    // the output of a debugging session with the commands
+    fn debug(source: &str, breakpoints: &[u32], commands: &str) -> (String, Result<()>) {
+        let program: Program<Span> = parser::parse(source).unwrap();
+        let inputs = HashMap::from([(Ident::new("x"), Literal::Field(3))]);
+        let mut output = Vec::new();
+        let mut debugger = Debugger::new(
+            source,
+            &inputs,
+            breakpoints,
+            commands.as_bytes(),
+            &mut output,
+        );
+        let result = interpret_program_with(&program, &inputs, &mut debugger).map(|_| ());
+        (String::from_utf8(output).unwrap(), result)
+    }
+
+    #[test]
+    fn step_test() {
+        let source = "pub x: F;\nlet a = x * x;\na + 1";
+        let (output, result) = debug(source, &[], "s\nn\np a\np y\nc\n");
+        result.unwrap();
+        assert_eq!(
+            output,
+            "Stopped at line 2, columns 11-12: (x * x)\n    2 | let a = x * x;\n(debug) \
+             Stopped at line 2, columns 9-10: x\n    2 | let a = x * x;\n(debug) \
+             x => Field(3)\n\
+             Stopped at line 2, columns 13-14: x\n    2 | let a = x * x;\n(debug) \
+             No value for a\n(debug) No value for y\n(debug) \
+             x => Field(3)\n(x * x) => Field(9)\n"
+        );
+    }
+
+    #[test]
+    fn breakpoint_test() {
+        let source = "pub x: F;\nlet a = x * x;\nlet b = a + x;\nb * 2";
+        let (output, result) = debug(source, &[3], "p a\nb 4\nc\nc\n");
+        result.unwrap();
+        let stops: Vec<&str> = output
+            .lines()
+            .filter(|line| line.starts_with("Stopped"))
+            .collect();
+        assert_eq!(
+            stops,
+            vec![
+                "Stopped at line 3, columns 11-12: (a + x)",
+                "Stopped at line 4, columns 3-4: (b * 2)"
+            ]
+        );
+        assert!(output.contains("a = Field(9)"));
+    }
+
+    #[test]
+    fn quit_test() {
+        let (_, result) = debug("pub x: F;\nx + 1", &[], "q\n");
+        match result {
+            Err(err) => match err.downcast_ref() {
+                Some(DebugError::Quit) => (),
+                _ => panic!("Expected Quit error"),
+            },
+            _ => panic!("Expected Quit error"),
+        };
+        // without commands the program runs to the end
+        assert!(debug("pub x: F;\nx + 1", &[], "").1.is_ok());
+    }
+}
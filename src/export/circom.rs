@@ -0,0 +1,336 @@
+use crate::ast::{Ident, Literal, Opcode, Ty, UOpcode};
+use crate::compiler::CompiledProgram;
+use crate::export::ExportError;
+use crate::ir::{Instruction, Op, Wire};
+use crate::plonk::circuit_builder::from_literal;
+use anyhow::{anyhow, Result};
+use plonky2::field::types::PrimeField64;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+// prints a program as a circom template. Every instruction of the IR is an intermediate
+// signal named after the `let` it was bound to, or after its wire for anonymous ones,
+// constants are inlined. calc identifiers start with a letter, so they can't clash with
+// the generated names.
+struct CircomPrinter {
+    lines: Vec<String>,
+    // the number of helper signals of the instruction being printed
+    temps: usize,
+}
+
+impl CircomPrinter {
+    fn line(&mut self, line: String) {
+        self.lines.push(line);
+    }
+
+    fn signal(&mut self, name: &str, expr: String) {
+        self.line(format!("signal {};", name));
+        self.line(format!("{} <== {};", name, expr));
+    }
+
+    // a helper signal for the instruction `wire`
+    fn temp(&mut self, wire: usize, expr: String) -> String {
+        let name = format!("_w{}_{}", wire, self.temps);
+        self.temps += 1;
+        self.signal(&name, expr);
+        name
+    }
+
+    // square-and-multiply, keeping the last product as the expression of the instruction
+    fn pow(&mut self, wire: usize, base: &str, exponent: u64) -> String {
+        let num_bits = 64 - exponent.leading_zeros();
+        // base^(2^i) for every bit i of the exponent that is set
+        let mut factors = Vec::new();
+        let mut power = base.to_string();
+        for i in 0..num_bits {
+            if i > 0 {
+                let square = format!("{} * {}", power, power);
+                if i + 1 == num_bits && factors.is_empty() {
+                    return square;
+                }
+                power = self.temp(wire, square);
+            }
+            if (exponent >> i) & 1 == 1 {
+                factors.push(power.clone());
+            }
+        }
+        let last = match factors.pop() {
+            Some(last) => last,
+            None => return "1".to_string(),
+        };
+        let mut factors = factors.into_iter();
+        match factors.next() {
+            Some(first) => {
+                let product = factors.fold(first, |product, factor| {
+                    self.temp(wire, format!("{} * {}", product, factor))
+                });
+                format!("{} * {}", product, last)
+            }
+            None => last,
+        }
+    }
+
+    // assign the signal `name` of the instruction `wire`, given the names of its operands
+    fn instruction(
+        &mut self,
+        name: &str,
+        wire: usize,
+        names: &[String],
+        constants: &[Option<Literal>],
+        op: &Op,
+    ) -> Result<()> {
+        let arg = |wire: &Wire| names[wire.0].clone();
+        let expr = match op {
+            Op::Const(_) | Op::Input(_) => unreachable!("{} has no signal", op),
+            Op::UnaryOp(UOpcode::Neg, x) => format!("-{}", arg(x)),
+            Op::BinOp(Opcode::Pow, lhs, rhs) => match &constants[rhs.0] {
+                Some(Literal::Field(n)) => self.pow(wire, &arg(lhs), *n as u64),
+                _ => {
+                    return Err(anyhow!(ExportError::Unsupported(
+                        "integer exponents",
+                        "circom"
+                    )))
+                }
+            },
+            Op::BinOp(opcode, lhs, rhs) => {
+                let (lhs, rhs) = (arg(lhs), arg(rhs));
+                match opcode {
+                    Opcode::Add => format!("{} + {}", lhs, rhs),
+                    Opcode::Sub => format!("{} - {}", lhs, rhs),
+                    Opcode::Mul | Opcode::And => format!("{} * {}", lhs, rhs),
+                    Opcode::Or => format!("{} + {} - {} * {}", lhs, rhs, lhs, rhs),
+                    // one exactly when the difference has no inverse
+                    Opcode::Eq => {
+                        let inv = format!("_w{}_inv", wire);
+                        let diff = difference(&lhs, &rhs);
+                        self.line(format!("signal {};", inv));
+                        self.line(format!("{} <-- {} != 0 ? 1 / {} : 0;", inv, diff, diff));
+                        self.signal(name, format!("1 - {} * {}", diff, inv));
+                        self.line(format!("{} * {} === 0;", diff, name));
+                        return Ok(());
+                    }
+                    _ => return Err(anyhow!(ExportError::Unsupported(op.name(), "circom"))),
+                }
+            }
+            Op::Select(cond, _then, _else) => {
+                let (_then, _else) = (arg(_then), arg(_else));
+                let diff = format!("{} * {}", arg(cond), difference(&_then, &_else));
+                match _else.as_str() {
+                    "0" => diff,
+                    _ => format!("{} + {}", diff, _else),
+                }
+            }
+            op => return Err(anyhow!(ExportError::Unsupported(op.name(), "circom"))),
+        };
+        self.signal(name, expr);
+        Ok(())
+    }
+}
+
+// `lhs - rhs` as an operand of a product
+fn difference(lhs: &str, rhs: &str) -> String {
+    match rhs {
+        "0" => lhs.to_string(),
+        _ => format!("({} - {})", lhs, rhs),
+    }
+}
+
+fn literal(lit: &Literal) -> Result<String> {
+    match lit {
+        Literal::Field(n) if *n < 0 => Ok(format!("({})", n)),
+        Literal::Field(n) => Ok(n.to_string()),
+        Literal::Boolean(b) => Ok((*b as u8).to_string()),
+        Literal::Array(_) => Err(anyhow!(ExportError::Unsupported("arrays", "circom"))),
+        _ => Err(anyhow!(ExportError::Unsupported(
+            "unsigned integers",
+            "circom"
+        ))),
+    }
+}
+
+fn check_input_type(ty: &Ty) -> Result<()> {
+    match ty {
+        Ty::Field | Ty::Boolean => Ok(()),
+        Ty::Array(_, _) => Err(anyhow!(ExportError::Unsupported("arrays", "circom"))),
+        _ => Err(anyhow!(ExportError::Unsupported(
+            "unsigned integers",
+            "circom"
+        ))),
+    }
+}
+
+// a circom 2 program with a `Main` template computing the outputs of the program. The
+// arithmetic is that of the Goldilocks field, so it has to be compiled with
+// `circom --prime goldilocks`. Only fields and booleans are supported for now.
+pub fn to_circom<A>(program: &CompiledProgram<A>) -> Result<String> {
+    let ir = &program.ir;
+    let mut printer = CircomPrinter {
+        lines: Vec::new(),
+        temps: 0,
+    };
+    for (ident, ty) in ir.public_inputs.iter().chain(ir.private_inputs.iter()) {
+        check_input_type(ty)?;
+        printer.line(format!("signal input {};", ident));
+    }
+    for (ident, _) in &ir.outputs {
+        printer.line(format!("signal output {};", ident));
+    }
+    let constants: Vec<Option<Literal>> = ir
+        .instructions
+        .iter()
+        .map(|instruction| match &instruction.op {
+            Op::Const(lit) => Some(lit.clone()),
+            _ => None,
+        })
+        .collect();
+    // a binding lowered more than once without cse, or named like an output, gets a number
+    let mut used: HashSet<String> = ir
+        .public_inputs
+        .iter()
+        .chain(ir.private_inputs.iter())
+        .map(|(ident, _)| ident.to_string())
+        .chain(ir.outputs.iter().map(|(ident, _)| ident.to_string()))
+        .collect();
+    let mut names: Vec<String> = Vec::with_capacity(ir.instructions.len());
+    for (wire, Instruction { ty, op, name, .. }) in ir.instructions.iter().enumerate() {
+        let name = match op {
+            Op::Const(lit) => literal(lit)?,
+            Op::Input(ident) => {
+                if *ty == Ty::Boolean {
+                    printer.line(format!("{} * ({} - 1) === 0;", ident, ident));
+                }
+                ident.to_string()
+            }
+            op => {
+                let name = match name {
+                    Some(ident) => (0..)
+                        .map(|i| match i {
+                            0 => ident.to_string(),
+                            i => format!("{}_{}", ident, i),
+                        })
+                        .find(|name| !used.contains(name))
+                        .expect("Names are not exhausted"),
+                    None => format!("_w{}", wire),
+                };
+                used.insert(name.clone());
+                printer.temps = 0;
+                printer.instruction(&name, wire, &names, &constants, op)?;
+                name
+            }
+        };
+        names.push(name);
+    }
+    for (ident, wire) in &ir.outputs {
+        printer.line(format!("{} <== {};", ident, names[wire.0]));
+    }
+    let public: Vec<String> = ir
+        .public_inputs
+        .iter()
+        .map(|(ident, _)| ident.to_string())
+        .collect();
+    let main = match public.is_empty() {
+        true => "component main = Main();".to_string(),
+        false => format!(
+            "component main {{public [{}]}} = Main();",
+            public.join(", ")
+        ),
+    };
+    let body: String = printer
+        .lines
+        .iter()
+        .flat_map(|line| ["    ", line.as_str(), "\n"])
+        .collect();
+    Ok(format!(
+        "pragma circom 2.1.0;\n\n\
+         // compile with `circom --prime goldilocks`\n\
+         template Main() {{\n{}}}\n\n{}\n",
+        body, main
+    ))
+}
+
+// the inputs in the `input.json` format of circom witness generators
+pub fn to_circom_input(context: &HashMap<Ident, Literal>) -> Result<String> {
+    let inputs = context
+        .iter()
+        .map(|(ident, value)| {
+            if let Literal::Array(_) = value {
+                return Err(anyhow!(ExportError::Unsupported("arrays", "circom")));
+            }
+            let value = from_literal(value.clone()).to_canonical_u64();
+            Ok((ident.to_string(), value.to_string()))
+        })
+        .collect::<Result<BTreeMap<String, String>>>()?;
+    Ok(serde_json::to_string_pretty(&inputs)?)
+}
+
+#[cfg(test)]
+mod circom_tests {
+    use super::*;
+    use crate::passes::PassManager;
+    use crate::{compiler, parser};
+    use std::ffi::OsStr;
+    use std::fs;
+    use std::path::Path;
+
+    // every example has a golden `.circom` file next to it, regenerate them with
+    // `calc export --format circom` when the output is meant to change
+    #[test]
+    fn examples_test() {
+        let examples = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+        let mut sources: Vec<_> = fs::read_dir(examples)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension() == Some(OsStr::new("calc")))
+            .collect();
+        sources.sort();
+        assert!(!sources.is_empty());
+        for source in sources {
+            let program = parser::parse(&fs::read_to_string(&source).unwrap()).unwrap();
+            let program = compiler::compile(program).unwrap();
+            let expected = fs::read_to_string(source.with_extension("circom")).unwrap();
+            assert_eq!(
+                to_circom(&program).unwrap(),
+                expected,
+                "{} does not match its golden file",
+                source.display()
+            );
+        }
+    }
+
+    #[test]
+    fn names_test() {
+        let program = "pub x: F; let y = x * x; out z = y + y; y * x";
+        let program = parser::parse(program).unwrap();
+        let passes = PassManager::from_level(0).unwrap();
+        let circom = to_circom(&compiler::compile_with(program, &passes).unwrap()).unwrap();
+        let signals: Vec<&str> = circom
+            .lines()
+            .filter_map(|line| line.trim().strip_prefix("signal "))
+            .collect();
+        assert_eq!(
+            signals,
+            vec![
+                "input x;",
+                "output z;",
+                "output result;",
+                "y;",
+                "y_1;",
+                "_w3;",
+                "y_2;",
+                "_w5;"
+            ]
+        );
+    }
+
+    #[test]
+    fn unsupported_test() {
+        let program = "pub x: u8; x + 1u8";
+        let program = compiler::compile(parser::parse(program).unwrap()).unwrap();
+        match to_circom(&program) {
+            Err(err) => match err.downcast_ref() {
+                Some(ExportError::Unsupported("unsigned integers", "circom")) => (),
+                _ => panic!("Expected Unsupported error"),
+            },
+            _ => panic!("Expected Unsupported error"),
+        };
+    }
+}
@@ -0,0 +1,19 @@
+pub mod circom;
+pub mod r1cs;
+
+use crate::ast::{Ident, Literal};
+use err_derive::Error;
+
+#[derive(Debug, Error)]
+pub enum ExportError {
+    #[error(display = "{} has no {} encoding yet", _0, _1)]
+    Unsupported(&'static str, &'static str),
+    #[error(display = "Missing input {} in the context", _0)]
+    MissingInput(Ident),
+    #[error(display = "Input {} = {} does not fit in the field", _0, _1)]
+    InputTooLarge(Ident, Literal),
+    #[error(display = "Input {} = {} does not match its declared type", _0, _1)]
+    InputMismatch(Ident, Literal),
+    #[error(display = "The witness does not satisfy constraint {}", _0)]
+    Unsatisfied(usize),
+}
@@ -0,0 +1,803 @@
+use crate::ast::{Builtin, Ident, Literal, Opcode, Ty, UOpcode};
+use crate::compiler::CompiledProgram;
+use crate::export::ExportError;
+use crate::ir::{Instruction, Op, Wire};
+use crate::plonk::circuit_builder::{flatten_literal, from_bool, from_literal};
+use crate::plonk::prove::fits_in_field;
+use crate::plonk::F;
+use anyhow::{anyhow, Result};
+use plonky2::field::types::{Field, Field64, PrimeField64};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+
+// the wire holding the constant one
+const ONE: usize = 0;
+// the size in bytes of a field element in the binary formats
+const FIELD_BYTES: u32 = 8;
+
+// a linear combination of wires, without zero coefficients
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Lc(pub BTreeMap<usize, F>);
+
+impl Lc {
+    fn wire(wire: usize) -> Self {
+        Lc(BTreeMap::from([(wire, F::ONE)]))
+    }
+
+    fn constant(c: F) -> Self {
+        Lc::wire(ONE).scale(c)
+    }
+
+    fn one() -> Self {
+        Lc::wire(ONE)
+    }
+
+    fn scale(&self, c: F) -> Self {
+        Lc(self
+            .0
+            .iter()
+            .map(|(wire, coeff)| (*wire, *coeff * c))
+            .filter(|(_, coeff)| !coeff.is_zero())
+            .collect())
+    }
+
+    fn add(&self, rhs: &Lc) -> Self {
+        let mut terms = self.0.clone();
+        for (wire, coeff) in &rhs.0 {
+            let sum = terms.get(wire).copied().unwrap_or(F::ZERO) + *coeff;
+            if sum.is_zero() {
+                terms.remove(wire);
+            } else {
+                terms.insert(*wire, sum);
+            }
+        }
+        Lc(terms)
+    }
+
+    fn sub(&self, rhs: &Lc) -> Self {
+        self.add(&rhs.scale(F::NEG_ONE))
+    }
+
+    // the value of a combination of the constant wire alone
+    fn as_constant(&self) -> Option<F> {
+        match self.0.keys().all(|wire| *wire == ONE) {
+            true => Some(self.0.get(&ONE).copied().unwrap_or(F::ZERO)),
+            false => None,
+        }
+    }
+
+    pub fn eval(&self, witness: &[F]) -> F {
+        self.0
+            .iter()
+            .fold(F::ZERO, |acc, (wire, coeff)| acc + witness[*wire] * *coeff)
+    }
+}
+
+// the little-endian number spelled by `bits`
+fn le_sum(bits: &[Lc]) -> Lc {
+    bits.iter()
+        .enumerate()
+        .fold(Lc::default(), |acc, (i, bit)| {
+            acc.add(&bit.scale(F::from_canonical_u64(1 << i)))
+        })
+}
+
+// a * b = c
+#[derive(Debug, Clone, PartialEq)]
+pub struct Constraint {
+    pub a: Lc,
+    pub b: Lc,
+    pub c: Lc,
+}
+
+// a rank-1 constraint system in the iden3 wire order: the constant one, the outputs, the
+// public inputs, the private inputs and then the internal wires
+#[derive(Debug)]
+pub struct R1cs {
+    pub num_wires: usize,
+    pub num_outputs: usize,
+    pub num_public_inputs: usize,
+    pub num_private_inputs: usize,
+    pub constraints: Vec<Constraint>,
+}
+
+// the constraint system representation of a value of each type. Unsigned integers are kept
+// as their little-endian bits.
+#[derive(Debug, Clone)]
+enum R1csValue {
+    Field(Lc),
+    Boolean(Lc),
+    UInt(Vec<Lc>),
+    Array(Vec<R1csValue>),
+}
+
+impl R1csValue {
+    fn field(self) -> Lc {
+        match self {
+            R1csValue::Field(lc) => lc,
+            value => unreachable!("Expected a field value, found {:?}", value),
+        }
+    }
+
+    fn boolean(self) -> Lc {
+        match self {
+            R1csValue::Boolean(lc) => lc,
+            value => unreachable!("Expected a boolean value, found {:?}", value),
+        }
+    }
+
+    fn bits(self) -> Vec<Lc> {
+        match self {
+            R1csValue::UInt(bits) => bits,
+            value => unreachable!("Expected an unsigned integer value, found {:?}", value),
+        }
+    }
+
+    // a single field element standing for the value, like `CircuitValue::to_field`
+    fn into_field(self) -> Lc {
+        match self {
+            R1csValue::Field(lc) | R1csValue::Boolean(lc) => lc,
+            R1csValue::UInt(bits) => le_sum(&bits),
+            R1csValue::Array(_) => unreachable!("Arrays are not a single field element"),
+        }
+    }
+}
+
+// collects the constraints together with the witness, every wire gets its value when it is
+// allocated. Without a context the inputs are zero and the witness is meaningless.
+struct R1csBuilder {
+    constraints: Vec<Constraint>,
+    witness: Vec<F>,
+}
+
+impl R1csBuilder {
+    fn alloc(&mut self, value: F) -> Lc {
+        self.witness.push(value);
+        Lc::wire(self.witness.len() - 1)
+    }
+
+    fn eval(&self, lc: &Lc) -> F {
+        lc.eval(&self.witness)
+    }
+
+    fn constrain(&mut self, a: Lc, b: Lc, c: Lc) {
+        self.constraints.push(Constraint { a, b, c });
+    }
+
+    // products with a constant are linear and need no constraint
+    fn mul(&mut self, a: &Lc, b: &Lc) -> Lc {
+        if let Some(k) = a.as_constant() {
+            return b.scale(k);
+        }
+        if let Some(k) = b.as_constant() {
+            return a.scale(k);
+        }
+        let c = self.alloc(self.eval(a) * self.eval(b));
+        self.constrain(a.clone(), b.clone(), c.clone());
+        c
+    }
+
+    fn assert_zero(&mut self, x: &Lc) {
+        self.constrain(x.clone(), Lc::one(), Lc::default());
+    }
+
+    fn assert_bool(&mut self, x: &Lc) {
+        self.constrain(x.clone(), x.sub(&Lc::one()), Lc::default());
+    }
+
+    fn select(&mut self, cond: &Lc, _then: &Lc, _else: &Lc) -> Lc {
+        let diff = self.mul(cond, &_then.sub(_else));
+        _else.add(&diff)
+    }
+
+    // one when `x` is zero: x * inv = 1 - out and x * out = 0
+    fn is_zero(&mut self, x: &Lc) -> Lc {
+        let value = self.eval(x);
+        let inv = self.alloc(value.try_inverse().unwrap_or(F::ZERO));
+        let out = self.alloc(from_bool(value.is_zero()));
+        self.constrain(x.clone(), inv, Lc::one().sub(&out));
+        self.constrain(x.clone(), out.clone(), Lc::default());
+        out
+    }
+
+    // decompose into `width` little-endian bits, the system is unsatisfiable if the value
+    // does not fit. At 64 bits the decomposition is not unique, see `uint_from_field`.
+    fn split_le(&mut self, x: &Lc, width: usize) -> Vec<Lc> {
+        let value = self.eval(x).to_canonical_u64();
+        let bits: Vec<Lc> = (0..width)
+            .map(|i| {
+                let bit = self.alloc(from_bool(i < 64 && (value >> i) & 1 == 1));
+                self.assert_bool(&bit);
+                bit
+            })
+            .collect();
+        self.assert_zero(&le_sum(&bits).sub(x));
+        bits
+    }
+
+    // the bits of a field element as an integer of type `ty`. As in `UIntTarget::from_field`,
+    // 64 bits could also spell out `x + p`, so if the high half is all ones the low half
+    // must be zero.
+    fn uint_from_field(&mut self, x: &Lc, ty: &Ty) -> Vec<Lc> {
+        let width = ty.uint_bits().expect("Expected an unsigned integer type");
+        let bits = self.split_le(x, width);
+        if width == 64 {
+            let low = le_sum(&bits[..32]);
+            let max = Lc::constant(F::from_canonical_u32(u32::MAX));
+            let high_is_max = self.is_zero(&le_sum(&bits[32..]).sub(&max));
+            let overflow = self.mul(&high_is_max, &low);
+            self.assert_zero(&overflow);
+        }
+        bits
+    }
+
+    // keep `width` bits, the system is unsatisfiable if the dropped ones are set
+    fn resize(&mut self, mut bits: Vec<Lc>, width: usize) -> Vec<Lc> {
+        for bit in bits.iter().skip(width) {
+            self.assert_zero(bit);
+        }
+        bits.resize(width, Lc::default());
+        bits
+    }
+
+    // add two integers in chunks of at most 32 bits, so that chunk sums never wrap around
+    // the field. Returns the bits of the sum and the carry out of the top chunk.
+    fn add_bits(&mut self, a: &[Lc], b: &[Lc], mut carry: Lc) -> (Vec<Lc>, Lc) {
+        let chunk = a.len().min(32);
+        let mut bits = Vec::with_capacity(a.len());
+        for (a, b) in a.chunks(chunk).zip(b.chunks(chunk)) {
+            let sum = le_sum(a).add(&le_sum(b)).add(&carry);
+            let mut sum_bits = self.split_le(&sum, chunk + 1);
+            carry = sum_bits.pop().expect("Expected a carry bit");
+            bits.extend(sum_bits);
+        }
+        (bits, carry)
+    }
+
+    fn add_uint(&mut self, a: &[Lc], b: &[Lc], checked: bool) -> Vec<Lc> {
+        let (bits, carry) = self.add_bits(a, b, Lc::default());
+        if checked {
+            self.assert_zero(&carry);
+        }
+        bits
+    }
+
+    // a - b = a + !b + 1, which carries out exactly when a >= b
+    fn sub_uint(&mut self, a: &[Lc], b: &[Lc], checked: bool) -> Vec<Lc> {
+        let not_b: Vec<Lc> = b.iter().map(|bit| Lc::one().sub(bit)).collect();
+        let (bits, carry) = self.add_bits(a, &not_b, Lc::one());
+        if checked {
+            self.assert_zero(&carry.sub(&Lc::one()));
+        }
+        bits
+    }
+
+    // schoolbook multiplication over 16 bit limbs, like `UIntTarget::mul`
+    fn mul_uint(&mut self, a: &[Lc], b: &[Lc], checked: bool) -> Vec<Lc> {
+        let limb_bits = a.len().min(16);
+        let a: Vec<Lc> = a.chunks(limb_bits).map(le_sum).collect();
+        let b: Vec<Lc> = b.chunks(limb_bits).map(le_sum).collect();
+        let n = a.len();
+        let column_bits = 2 * limb_bits + n.next_power_of_two().trailing_zeros() as usize + 1;
+        let mut bits = Vec::with_capacity(n * limb_bits);
+        let mut carry = Lc::default();
+        for column in 0..n {
+            let mut sum = carry;
+            for (a, b) in a[..=column].iter().zip(b[..=column].iter().rev()) {
+                sum = sum.add(&self.mul(a, b));
+            }
+            let column = self.split_le(&sum, column_bits);
+            carry = le_sum(&column[limb_bits..]);
+            bits.extend_from_slice(&column[..limb_bits]);
+        }
+        if checked {
+            // the terms of the high half are small and non-negative, so they are all zero
+            // exactly when their sum is
+            let mut overflow = carry;
+            for (i, a) in a.iter().enumerate().skip(1) {
+                for b in &b[n - i..] {
+                    overflow = overflow.add(&self.mul(a, b));
+                }
+            }
+            self.assert_zero(&overflow);
+        }
+        bits
+    }
+
+    fn eq_uint(&mut self, a: &[Lc], b: &[Lc]) -> Lc {
+        let chunk = a.len().min(32);
+        let mut result = Lc::one();
+        for (a, b) in a.chunks(chunk).zip(b.chunks(chunk)) {
+            let eq = self.is_zero(&le_sum(a).sub(&le_sum(b)));
+            result = self.mul(&result, &eq);
+        }
+        result
+    }
+
+    fn bitwise(&mut self, a: &[Lc], b: &[Lc], func: Builtin) -> Vec<Lc> {
+        a.iter()
+            .zip(b)
+            .map(|(a, b)| {
+                let prod = self.mul(a, b);
+                match func {
+                    Builtin::BitAnd => prod,
+                    Builtin::BitOr => a.add(b).sub(&prod),
+                    Builtin::BitXor => a.add(b).sub(&prod.scale(F::TWO)),
+                    func => unreachable!("{} is not a bitwise operation", func),
+                }
+            })
+            .collect()
+    }
+
+    // a barrel shifter over the bits of `amount`, as in `UIntTarget::shift`
+    fn shift(&mut self, mut bits: Vec<Lc>, amount: &[Lc], left: bool) -> Vec<Lc> {
+        let width = bits.len();
+        let stages = width.trailing_zeros() as usize;
+        for (stage, cond) in amount.iter().take(stages).enumerate() {
+            let shift = 1 << stage;
+            bits = (0..width)
+                .map(|i| {
+                    let from = if left {
+                        i.checked_sub(shift)
+                    } else {
+                        Some(i + shift).filter(|j| *j < width)
+                    };
+                    let shifted = from.map_or(Lc::default(), |j| bits[j].clone());
+                    self.select(cond, &shifted, &bits[i])
+                })
+                .collect();
+        }
+        // any of the remaining bits of the amount shifts everything out
+        let mut keep = Lc::one();
+        for bit in amount.iter().skip(stages) {
+            let not_bit = Lc::one().sub(bit);
+            keep = self.mul(&keep, &not_bit);
+        }
+        bits.iter().map(|bit| self.mul(bit, &keep)).collect()
+    }
+
+    fn exp_u64(&mut self, base: &Lc, exponent: u64) -> Lc {
+        let num_bits = 64 - exponent.leading_zeros();
+        let mut result = Lc::one();
+        let mut power = base.clone();
+        for i in 0..num_bits {
+            if (exponent >> i) & 1 == 1 {
+                result = self.mul(&result, &power);
+            }
+            if i + 1 < num_bits {
+                power = self.mul(&power, &power);
+            }
+        }
+        result
+    }
+
+    // multiply by base^(2^i) for every bit i of the exponent that is set
+    fn exp_bits(&mut self, base: &Lc, exponent: &[Lc]) -> Lc {
+        let mut result = Lc::one();
+        let mut power = base.clone();
+        for (i, bit) in exponent.iter().enumerate() {
+            let factor = Lc::one().add(&self.mul(bit, &power.sub(&Lc::one())));
+            result = self.mul(&result, &factor);
+            if i + 1 < exponent.len() {
+                power = self.mul(&power, &power);
+            }
+        }
+        result
+    }
+
+    // the value of an input of type `ty`, constraining the wires holding it
+    fn input(&mut self, ty: &Ty, wires: &mut impl Iterator<Item = Lc>) -> R1csValue {
+        match ty {
+            Ty::Array(elem_ty, len) => {
+                R1csValue::Array((0..*len).map(|_| self.input(elem_ty, wires)).collect())
+            }
+            ty => {
+                let wire = wires
+                    .next()
+                    .expect("Expected a wire for every input element");
+                match ty {
+                    Ty::Field => R1csValue::Field(wire),
+                    Ty::Boolean => {
+                        self.assert_bool(&wire);
+                        R1csValue::Boolean(wire)
+                    }
+                    ty => R1csValue::UInt(self.uint_from_field(&wire, ty)),
+                }
+            }
+        }
+    }
+
+    fn cast(&mut self, value: R1csValue, ty: &Ty) -> R1csValue {
+        match (value, ty) {
+            (R1csValue::Field(x), Ty::Field) => R1csValue::Field(x),
+            (R1csValue::Field(x), ty) => R1csValue::UInt(self.uint_from_field(&x, ty)),
+            (R1csValue::UInt(bits), Ty::Field) => R1csValue::Field(le_sum(&bits)),
+            (R1csValue::UInt(bits), ty) => {
+                let width = ty.uint_bits().expect("Expected an unsigned integer type");
+                R1csValue::UInt(self.resize(bits, width))
+            }
+            (value, ty) => unreachable!("Cannot cast {:?} to {}", value, ty),
+        }
+    }
+
+    fn decompose(&mut self, value: R1csValue, width: usize) -> Vec<Lc> {
+        match value {
+            // below 64 bits the decomposition can't wrap around the field order
+            R1csValue::Field(x) if width < 64 => self.split_le(&x, width),
+            R1csValue::Field(x) => {
+                let bits = self.uint_from_field(&x, &Ty::U64);
+                self.resize(bits, width)
+            }
+            R1csValue::UInt(bits) => self.resize(bits, width),
+            value => unreachable!("Cannot decompose {:?} into bits", value),
+        }
+    }
+
+    fn select_value(&mut self, cond: &Lc, _then: R1csValue, _else: R1csValue) -> R1csValue {
+        match (_then, _else) {
+            (R1csValue::Field(_then), R1csValue::Field(_else)) => {
+                R1csValue::Field(self.select(cond, &_then, &_else))
+            }
+            (R1csValue::Boolean(_then), R1csValue::Boolean(_else)) => {
+                R1csValue::Boolean(self.select(cond, &_then, &_else))
+            }
+            (R1csValue::UInt(_then), R1csValue::UInt(_else)) => R1csValue::UInt(
+                _then
+                    .iter()
+                    .zip(_else.iter())
+                    .map(|(_then, _else)| self.select(cond, _then, _else))
+                    .collect(),
+            ),
+            (R1csValue::Array(_then), R1csValue::Array(_else)) => R1csValue::Array(
+                _then
+                    .into_iter()
+                    .zip(_else)
+                    .map(|(_then, _else)| self.select_value(cond, _then, _else))
+                    .collect(),
+            ),
+            (_then, _else) => unreachable!("Branches {:?} and {:?} differ", _then, _else),
+        }
+    }
+
+    // the value of an instruction given the values of all the wires before it
+    fn instruction(&mut self, values: &[R1csValue], ty: &Ty, op: Op) -> Result<R1csValue> {
+        let value = |wire: Wire| values[wire.0].clone();
+        Ok(match op {
+            Op::Const(lit) => literal(lit),
+            Op::Input(ident) => unreachable!("Input {} is created by build_r1cs", ident),
+            Op::UnaryOp(UOpcode::Neg, wire) => {
+                R1csValue::Field(value(wire).field().scale(F::NEG_ONE))
+            }
+            // the compiler only lets through constant field exponents
+            Op::BinOp(Opcode::Pow, lhs, rhs) => {
+                let base = value(lhs).field();
+                R1csValue::Field(match value(rhs) {
+                    R1csValue::Field(exponent) => {
+                        let exponent = exponent
+                            .as_constant()
+                            .expect("Expected a constant exponent")
+                            .to_canonical_u64();
+                        self.exp_u64(&base, exponent)
+                    }
+                    exponent => self.exp_bits(&base, &exponent.bits()),
+                })
+            }
+            Op::BinOp(op, lhs, rhs) => match (value(lhs), value(rhs)) {
+                (R1csValue::UInt(lhs), R1csValue::UInt(rhs)) => match op {
+                    Opcode::Add => R1csValue::UInt(self.add_uint(&lhs, &rhs, true)),
+                    Opcode::Sub => R1csValue::UInt(self.sub_uint(&lhs, &rhs, true)),
+                    Opcode::Mul => R1csValue::UInt(self.mul_uint(&lhs, &rhs, true)),
+                    Opcode::WrappingAdd => R1csValue::UInt(self.add_uint(&lhs, &rhs, false)),
+                    Opcode::WrappingSub => R1csValue::UInt(self.sub_uint(&lhs, &rhs, false)),
+                    Opcode::WrappingMul => R1csValue::UInt(self.mul_uint(&lhs, &rhs, false)),
+                    Opcode::Eq => R1csValue::Boolean(self.eq_uint(&lhs, &rhs)),
+                    op => unreachable!("{:?} is not defined on unsigned integers", op),
+                },
+                (R1csValue::Boolean(lhs), R1csValue::Boolean(rhs)) => {
+                    let prod = self.mul(&lhs, &rhs);
+                    match op {
+                        Opcode::And => R1csValue::Boolean(prod),
+                        Opcode::Or => R1csValue::Boolean(lhs.add(&rhs).sub(&prod)),
+                        op => unreachable!("{:?} is not defined on booleans", op),
+                    }
+                }
+                (lhs, rhs) => {
+                    let lhs = lhs.field();
+                    let rhs = rhs.field();
+                    match op {
+                        Opcode::Add => R1csValue::Field(lhs.add(&rhs)),
+                        Opcode::Sub => R1csValue::Field(lhs.sub(&rhs)),
+                        Opcode::Mul => R1csValue::Field(self.mul(&lhs, &rhs)),
+                        Opcode::Eq => R1csValue::Boolean(self.is_zero(&lhs.sub(&rhs))),
+                        op => unreachable!("{:?} is not defined on fields", op),
+                    }
+                }
+            },
+            Op::Select(cond, _then, _else) => {
+                let cond = value(cond).boolean();
+                self.select_value(&cond, value(_then), value(_else))
+            }
+            Op::Cast(wire) => self.cast(value(wire), ty),
+            Op::ToBits(wire, width) => {
+                let bits = self.decompose(value(wire), width);
+                R1csValue::Array(bits.into_iter().map(R1csValue::Boolean).collect())
+            }
+            Op::Call(func @ (Builtin::Poseidon | Builtin::MerkleVerify), _) => {
+                return Err(anyhow!(ExportError::Unsupported(func.name(), "R1CS")))
+            }
+            Op::Call(func, args) => {
+                let lhs = value(args[0]).bits();
+                let rhs = value(args[1]).bits();
+                R1csValue::UInt(match func {
+                    Builtin::Shl => self.shift(lhs, &rhs, true),
+                    Builtin::Shr => self.shift(lhs, &rhs, false),
+                    func => self.bitwise(&lhs, &rhs, func),
+                })
+            }
+            Op::Index(wire, index) => match value(wire) {
+                R1csValue::Array(mut values) => values.swap_remove(index),
+                value => unreachable!("Expected an array, found {:?}", value),
+            },
+        })
+    }
+}
+
+fn constant_bits(n: u64, width: usize) -> Vec<Lc> {
+    (0..width)
+        .map(|i| Lc::constant(from_bool((n >> i) & 1 == 1)))
+        .collect()
+}
+
+fn literal(lit: Literal) -> R1csValue {
+    match lit {
+        Literal::Field(_) => R1csValue::Field(Lc::constant(from_literal(lit))),
+        Literal::Boolean(b) => R1csValue::Boolean(Lc::constant(from_bool(b))),
+        Literal::U8(n) => R1csValue::UInt(constant_bits(n as u64, 8)),
+        Literal::U32(n) => R1csValue::UInt(constant_bits(n as u64, 32)),
+        Literal::U64(n) => R1csValue::UInt(constant_bits(n, 64)),
+        Literal::Array(elems) => R1csValue::Array(elems.into_iter().map(literal).collect()),
+    }
+}
+
+// the field elements of an input taken from the context, zero without a context
+fn input_values(
+    context: Option<&HashMap<Ident, Literal>>,
+    ident: &Ident,
+    ty: &Ty,
+) -> Result<Vec<F>> {
+    let context = match context {
+        Some(context) => context,
+        None => return Ok(vec![F::ZERO; ty.num_elements()]),
+    };
+    let value = context
+        .get(ident)
+        .ok_or_else(|| anyhow!(ExportError::MissingInput(ident.clone())))?;
+    if !fits_in_field(value) {
+        return Err(anyhow!(ExportError::InputTooLarge(
+            ident.clone(),
+            value.clone()
+        )));
+    }
+    let values = flatten_literal(value.clone());
+    if values.len() != ty.num_elements() {
+        return Err(anyhow!(ExportError::InputMismatch(
+            ident.clone(),
+            value.clone()
+        )));
+    }
+    Ok(values)
+}
+
+// lower the circuit of a program to R1CS, along with the witness for the inputs in
+// `context`. The witness is only meaningful when a context is given.
+pub fn build_r1cs<A>(
+    program: &CompiledProgram<A>,
+    context: Option<&HashMap<Ident, Literal>>,
+) -> Result<(R1cs, Vec<F>)> {
+    let ir = &program.ir;
+    let num_outputs = ir.outputs.len();
+    let mut builder = R1csBuilder {
+        constraints: Vec::new(),
+        witness: vec![F::ZERO; 1 + num_outputs],
+    };
+    builder.witness[ONE] = F::ONE;
+    // the input wires come before any internal one, so they are all allocated first
+    let mut input_wires: HashMap<Ident, (Ty, Vec<Lc>)> = HashMap::new();
+    for (ident, ty) in ir.public_inputs.iter().chain(ir.private_inputs.iter()) {
+        let wires = input_values(context, ident, ty)?
+            .into_iter()
+            .map(|value| builder.alloc(value))
+            .collect();
+        input_wires.insert(ident.clone(), (ty.clone(), wires));
+    }
+    let num_elements =
+        |inputs: &[(Ident, Ty)]| -> usize { inputs.iter().map(|(_, ty)| ty.num_elements()).sum() };
+    let num_public_inputs = num_elements(&ir.public_inputs);
+    let num_private_inputs = num_elements(&ir.private_inputs);
+    let mut values: Vec<R1csValue> = Vec::with_capacity(ir.instructions.len());
+    for Instruction { ty, op, .. } in &ir.instructions {
+        let value = match op {
+            Op::Input(ident) => {
+                let (ty, wires) = input_wires
+                    .remove(ident)
+                    .expect("Expected every input to be declared");
+                builder.input(&ty, &mut wires.into_iter())
+            }
+            op => builder.instruction(&values, ty, op.clone())?,
+        };
+        values.push(value);
+    }
+    for (i, (_, wire)) in ir.outputs.iter().enumerate() {
+        let output = values[wire.0].clone().into_field();
+        builder.witness[1 + i] = builder.eval(&output);
+        builder.constrain(output, Lc::one(), Lc::wire(1 + i));
+    }
+    let r1cs = R1cs {
+        num_wires: builder.witness.len(),
+        num_outputs,
+        num_public_inputs,
+        num_private_inputs,
+        constraints: builder.constraints,
+    };
+    Ok((r1cs, builder.witness))
+}
+
+fn write_field(buf: &mut Vec<u8>, value: F) {
+    buf.extend(value.to_canonical_u64().to_le_bytes());
+}
+
+fn write_lc(buf: &mut Vec<u8>, lc: &Lc) {
+    buf.extend((lc.0.len() as u32).to_le_bytes());
+    for (wire, coeff) in &lc.0 {
+        buf.extend((*wire as u32).to_le_bytes());
+        write_field(buf, *coeff);
+    }
+}
+
+fn write_section(writer: &mut impl Write, section_type: u32, content: &[u8]) -> io::Result<()> {
+    writer.write_all(&section_type.to_le_bytes())?;
+    writer.write_all(&(content.len() as u64).to_le_bytes())?;
+    writer.write_all(content)
+}
+
+// the field size and prime shared by the headers of both formats
+fn field_header() -> Vec<u8> {
+    let mut header = FIELD_BYTES.to_le_bytes().to_vec();
+    header.extend(F::ORDER.to_le_bytes());
+    header
+}
+
+impl R1cs {
+    // the first unsatisfied constraint, if any
+    pub fn check(&self, witness: &[F]) -> Result<()> {
+        for (i, Constraint { a, b, c }) in self.constraints.iter().enumerate() {
+            if a.eval(witness) * b.eval(witness) != c.eval(witness) {
+                return Err(anyhow!(ExportError::Unsatisfied(i)));
+            }
+        }
+        Ok(())
+    }
+
+    // the iden3 `.r1cs` binary format: a header, the constraints and the wire to label map,
+    // where every wire is its own label
+    pub fn write(&self, writer: &mut impl Write) -> io::Result<()> {
+        let mut header = field_header();
+        header.extend((self.num_wires as u32).to_le_bytes());
+        header.extend((self.num_outputs as u32).to_le_bytes());
+        header.extend((self.num_public_inputs as u32).to_le_bytes());
+        header.extend((self.num_private_inputs as u32).to_le_bytes());
+        header.extend((self.num_wires as u64).to_le_bytes());
+        header.extend((self.constraints.len() as u32).to_le_bytes());
+        let mut constraints = Vec::new();
+        for Constraint { a, b, c } in &self.constraints {
+            write_lc(&mut constraints, a);
+            write_lc(&mut constraints, b);
+            write_lc(&mut constraints, c);
+        }
+        let labels: Vec<u8> = (0..self.num_wires as u64)
+            .flat_map(|label| label.to_le_bytes())
+            .collect();
+        writer.write_all(b"r1cs")?;
+        writer.write_all(&1u32.to_le_bytes())?;
+        writer.write_all(&3u32.to_le_bytes())?;
+        write_section(writer, 1, &header)?;
+        write_section(writer, 2, &constraints)?;
+        write_section(writer, 3, &labels)
+    }
+}
+
+// the iden3 `.wtns` binary format: a header and the value of every wire
+pub fn write_witness(witness: &[F], writer: &mut impl Write) -> io::Result<()> {
+    let mut header = field_header();
+    header.extend((witness.len() as u32).to_le_bytes());
+    let mut values = Vec::new();
+    for value in witness {
+        write_field(&mut values, *value);
+    }
+    writer.write_all(b"wtns")?;
+    writer.write_all(&2u32.to_le_bytes())?;
+    writer.write_all(&2u32.to_le_bytes())?;
+    write_section(writer, 1, &header)?;
+    write_section(writer, 2, &values)
+}
+
+#[cfg(test)]
+mod r1cs_tests {
+    use super::*;
+    use crate::interpreter::{interpret, Context};
+    use crate::{compiler, parser};
+
+    // export the program and check that the witness satisfies the constraints and holds
+    // the outputs computed by the interpreter
+    fn check_against_interpreter(input: &str, inputs: Vec<(&str, Literal)>) -> (R1cs, Vec<F>) {
+        let context: HashMap<Ident, Literal> = inputs
+            .into_iter()
+            .map(|(name, value)| (Ident::new(name), value))
+            .collect();
+        let program = compiler::compile(parser::parse(input).unwrap()).unwrap();
+        let (r1cs, witness) = build_r1cs(&program, Some(&context)).unwrap();
+        r1cs.check(&witness).unwrap();
+        for (i, (_, expr)) in program.outputs.iter().enumerate() {
+            let expected = interpret(&mut Context::from(context.clone()), expr).unwrap();
+            assert_eq!(witness[1 + i], expected.to_field());
+        }
+        (r1cs, witness)
+    }
+
+    #[test]
+    fn witness_test() {
+        let inputs = vec![
+            ("x", Literal::Field(3)),
+            ("y", Literal::U8(200)),
+            ("z", Literal::U64(u64::MAX - (1 << 32))),
+            ("b", Literal::Boolean(true)),
+        ];
+        let program = "pub x: F; pub y: u8; priv z: u64; priv b: Bool; \
+                       out poly = x ^ 3 + x * x - 1; \
+                       out cond = if x == 3 && b then x else -x; \
+                       out sum: u8 = y +% 100u8; \
+                       out diff: u8 = y - 100u8; \
+                       out prod = z *% 3u64; \
+                       out bits: u8 = bit_xor(shr(y, 3u8), bit_or(y, 1u8)); \
+                       out bit = to_bits(x, 4)[1]; \
+                       out pow = x ^ (y as u32);";
+        let (r1cs, mut witness) = check_against_interpreter(program, inputs);
+        assert_eq!(r1cs.num_outputs, 8);
+        assert_eq!(r1cs.num_public_inputs, 2);
+        assert_eq!(r1cs.num_private_inputs, 2);
+        // a wrong output breaks the constraint exposing it
+        witness[1] += F::ONE;
+        assert!(r1cs.check(&witness).is_err());
+    }
+
+    #[test]
+    fn overflow_test() {
+        let program = "pub y: u8; y * 2u8";
+        let program = compiler::compile(parser::parse(program).unwrap()).unwrap();
+        let context = HashMap::from([(Ident::new("y"), Literal::U8(200))]);
+        let (r1cs, witness) = build_r1cs(&program, Some(&context)).unwrap();
+        assert!(r1cs.check(&witness).is_err());
+    }
+
+    #[test]
+    fn format_test() {
+        let (r1cs, witness) =
+            check_against_interpreter("pub x: F; x * x + 1", vec![("x", Literal::Field(5))]);
+        let mut bytes = Vec::new();
+        r1cs.write(&mut bytes).unwrap();
+        assert_eq!(&bytes[..4], b"r1cs");
+        // the header section follows the version and the number of sections
+        assert_eq!(bytes[12..16], 1u32.to_le_bytes());
+        assert_eq!(bytes[24..28], FIELD_BYTES.to_le_bytes());
+        assert_eq!(bytes[28..36], F::ORDER.to_le_bytes());
+        assert_eq!(bytes[36..40], (r1cs.num_wires as u32).to_le_bytes());
+        let mut bytes = Vec::new();
+        write_witness(&witness, &mut bytes).unwrap();
+        assert_eq!(&bytes[..4], b"wtns");
+        assert_eq!(bytes.len(), 12 + 12 + 16 + 12 + 8 * witness.len());
+        assert_eq!(
+            bytes[bytes.len() - 8 * witness.len()..][8..16],
+            26u64.to_le_bytes()
+        );
+    }
+}
@@ -0,0 +1,565 @@
+// differential testing of the interpreter against the circuit: random well-typed programs
+// are interpreted and proved, and both have to agree on the outputs (or both fail). Failing
+// programs are shrunk before being reported.
+//
+// Set CALC_FUZZ_SEED to replay a run and CALC_FUZZ_ITERATIONS to run longer.
+
+use crate::ast::{
+    Binder, Builtin, Declaration, Expr, Ident, Literal, Opcode, Program, Ty, UOpcode,
+};
+use crate::backend::{Backend, ConstraintChecker};
+use crate::compiler;
+use crate::interpreter::{interpret, Context};
+use crate::plonk::{prove, Plonky2Backend, F};
+use plonky2::field::types::Field64;
+use std::collections::HashMap;
+
+const SCALARS: [Ty; 5] = [Ty::Field, Ty::Boolean, Ty::U8, Ty::U32, Ty::U64];
+
+// xorshift64*, good enough to draw programs reproducibly from a seed
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Rng(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn chance(&mut self, percent: usize) -> bool {
+        self.below(100) < percent
+    }
+
+    fn scalar(&mut self) -> Ty {
+        SCALARS[self.below(SCALARS.len())].clone()
+    }
+
+    // a scalar type other than Boolean
+    fn number(&mut self) -> Ty {
+        match self.scalar() {
+            Ty::Boolean => Ty::Field,
+            ty => ty,
+        }
+    }
+
+    // mostly small numbers, with the edges of the type now and then
+    fn uint(&mut self, ty: &Ty) -> u64 {
+        let max = match ty {
+            // u64 inputs have to fit in the field
+            Ty::U64 => F::ORDER - 1,
+            ty => ty.uint_max().unwrap(),
+        };
+        match self.below(4) {
+            0 => max - self.below(3) as u64,
+            1 => self.next() % (max + 1),
+            _ => self.below(16) as u64,
+        }
+    }
+
+    fn literal(&mut self, ty: &Ty) -> Literal {
+        match ty {
+            Ty::Field => Literal::Field(self.below(40) as i128 - 10),
+            Ty::Boolean => Literal::Boolean(self.chance(50)),
+            Ty::U8 => Literal::U8(self.uint(ty) as u8),
+            Ty::U32 => Literal::U32(self.uint(ty) as u32),
+            Ty::U64 => Literal::U64(self.uint(ty)),
+            ty => unreachable!("No literals of type {}", ty),
+        }
+    }
+}
+
+fn literal(value: Literal) -> Expr<()> {
+    Expr::Literal { ann: (), value }
+}
+
+fn binop(lhs: Expr<()>, op: Opcode, rhs: Expr<()>) -> Expr<()> {
+    Expr::BinOp {
+        ann: (),
+        lhs: Box::new(lhs),
+        op,
+        rhs: Box::new(rhs),
+    }
+}
+
+fn call(func: Builtin, args: Vec<Expr<()>>) -> Expr<()> {
+    Expr::Call {
+        ann: (),
+        func,
+        args,
+    }
+}
+
+fn cast(expr: Expr<()>, ty: &Ty) -> Expr<()> {
+    Expr::Cast {
+        ann: (),
+        expr: Box::new(expr),
+        _type: ty.clone(),
+    }
+}
+
+struct Generator {
+    rng: Rng,
+    // the variables in scope with their types, the inputs come first
+    vars: Vec<(Ident, Ty)>,
+}
+
+impl Generator {
+    fn leaf(&mut self, ty: &Ty) -> Expr<()> {
+        let vars: Vec<Ident> = self
+            .vars
+            .iter()
+            .filter(|(_, var_ty)| var_ty == ty)
+            .map(|(var, _)| var.clone())
+            .collect();
+        if vars.is_empty() || self.rng.chance(30) {
+            return literal(self.rng.literal(ty));
+        }
+        Expr::Variable {
+            ann: (),
+            value: vars[self.rng.below(vars.len())].clone(),
+        }
+    }
+
+    fn expr(&mut self, ty: &Ty, depth: usize) -> Expr<()> {
+        if depth == 0 || self.rng.chance(20) {
+            return self.leaf(ty);
+        }
+        let depth = depth - 1;
+        if self.rng.chance(10) {
+            let cond = self.expr(&Ty::Boolean, depth);
+            let (_then, _else) = (self.expr(ty, depth), self.expr(ty, depth));
+            return Expr::IfThenElse {
+                ann: (),
+                cond: Box::new(cond),
+                _then: Box::new(_then),
+                _else: Box::new(_else),
+            };
+        }
+        match ty {
+            Ty::Field => match self.rng.below(6) {
+                0 => Expr::UnaryOp {
+                    ann: (),
+                    op: UOpcode::Neg,
+                    expr: Box::new(self.expr(ty, depth)),
+                },
+                1 => {
+                    let exponent = match self.rng.chance(50) {
+                        true => Literal::Field(self.rng.below(6) as i128),
+                        false => Literal::U8(self.rng.below(6) as u8),
+                    };
+                    binop(self.expr(ty, depth), Opcode::Pow, literal(exponent))
+                }
+                2 => {
+                    let from = [Ty::U8, Ty::U32, Ty::U64][self.rng.below(3)].clone();
+                    cast(self.expr(&from, depth), ty)
+                }
+                3 => {
+                    let args = (0..1 + self.rng.below(2))
+                        .map(|_| self.expr(ty, depth))
+                        .collect();
+                    Expr::Index {
+                        ann: (),
+                        expr: Box::new(call(Builtin::Poseidon, args)),
+                        index: self.rng.below(4),
+                    }
+                }
+                n => {
+                    let op = [Opcode::Add, Opcode::Sub, Opcode::Mul][n % 3];
+                    binop(self.expr(ty, depth), op, self.expr(ty, depth))
+                }
+            },
+            Ty::Boolean => match self.rng.below(4) {
+                0 => {
+                    let op = [Opcode::And, Opcode::Or][self.rng.below(2)];
+                    binop(self.expr(ty, depth), op, self.expr(ty, depth))
+                }
+                1 => {
+                    let operand_ty = self.rng.number();
+                    binop(
+                        self.expr(&operand_ty, depth),
+                        Opcode::Eq,
+                        self.expr(&operand_ty, depth),
+                    )
+                }
+                _ => {
+                    let from = self.rng.number();
+                    let width = from.uint_bits().unwrap_or(1 + self.rng.below(64));
+                    let bits = call(
+                        Builtin::ToBits,
+                        vec![
+                            self.expr(&from, depth),
+                            literal(Literal::Field(width as i128)),
+                        ],
+                    );
+                    Expr::Index {
+                        ann: (),
+                        expr: Box::new(bits),
+                        index: self.rng.below(width),
+                    }
+                }
+            },
+            ty => match self.rng.below(5) {
+                0 => {
+                    let from = self.rng.number();
+                    cast(self.expr(&from, depth), ty)
+                }
+                1 => {
+                    let func = [
+                        Builtin::BitAnd,
+                        Builtin::BitOr,
+                        Builtin::BitXor,
+                        Builtin::Shl,
+                        Builtin::Shr,
+                    ][self.rng.below(5)];
+                    call(func, vec![self.expr(ty, depth), self.expr(ty, depth)])
+                }
+                _ => {
+                    let ops = [
+                        Opcode::WrappingAdd,
+                        Opcode::WrappingSub,
+                        Opcode::WrappingMul,
+                        Opcode::Add,
+                        Opcode::Sub,
+                        Opcode::Mul,
+                    ];
+                    let op = ops[self.rng.below(6)];
+                    binop(self.expr(ty, depth), op, self.expr(ty, depth))
+                }
+            },
+        }
+    }
+}
+
+// a random well-typed program over a few inputs, and values for its inputs
+fn generate(seed: u64) -> (Program<()>, HashMap<Ident, Literal>) {
+    let mut gen = Generator {
+        rng: Rng::new(seed),
+        vars: Vec::new(),
+    };
+    let mut decls = Vec::new();
+    let mut context = HashMap::new();
+    for i in 0..1 + gen.rng.below(3) {
+        let var = Ident::new(&format!("x{}", i));
+        let ty = gen.rng.scalar();
+        let binder = Binder::TypedBinder {
+            ann: (),
+            var: var.clone(),
+            _type: ty.clone(),
+        };
+        decls.push(match gen.rng.chance(50) {
+            true => Declaration::PublicVar { binder },
+            false => Declaration::PrivateVar { binder },
+        });
+        context.insert(var.clone(), gen.rng.literal(&ty));
+        gen.vars.push((var, ty));
+    }
+    for i in 0..gen.rng.below(4) {
+        let var = Ident::new(&format!("a{}", i));
+        let ty = gen.rng.scalar();
+        let expr = gen.expr(&ty, 3);
+        let binder = Binder::VarBinder {
+            ann: (),
+            var: var.clone(),
+        };
+        decls.push(match gen.rng.chance(30) {
+            true => Declaration::Output { binder, expr },
+            false => Declaration::VarAssignment { binder, expr },
+        });
+        gen.vars.push((var, ty));
+    }
+    let ty = gen.rng.scalar();
+    let expr = gen.expr(&ty, 3);
+    (Program::new(decls, Some(expr)).unwrap(), context)
+}
+
+// how the interpreter and a proof disagree on a program, if they do
+fn check<B: Backend>(
+    backend: B,
+    program: &Program<()>,
+    context: &HashMap<Ident, Literal>,
+) -> Option<String> {
+    let program = compiler::compile(program.clone()).unwrap();
+    let expected = program
+        .outputs
+        .iter()
+        .map(|(_, expr)| {
+            interpret(&mut Context::from(context.clone()), expr).map(|value| value.to_field())
+        })
+        .collect::<anyhow::Result<Vec<F>>>();
+    let proved = prove(backend, context.clone(), program).and_then(|proving_data| {
+        B::verify(&proving_data.proof)?;
+        Ok(B::public_values(&proving_data.proof))
+    });
+    match (expected, proved) {
+        (Ok(expected), Ok(values)) => {
+            let outputs = &values[values.len() - expected.len()..];
+            match outputs == expected.as_slice() {
+                true => None,
+                false => Some(format!(
+                    "the interpreter computes {:?} but the proof has {:?}",
+                    expected, outputs
+                )),
+            }
+        }
+        (Err(_), Err(_)) => None,
+        (Ok(expected), Err(err)) => Some(format!(
+            "the interpreter computes {:?} but proving fails with {}",
+            expected, err
+        )),
+        (Err(err), Ok(_)) => Some(format!(
+            "the interpreter fails with {} but the proof succeeds",
+            err
+        )),
+    }
+}
+
+// the direct subexpressions of an expression
+fn children(expr: &Expr<()>) -> Vec<Expr<()>> {
+    match expr {
+        Expr::Literal { .. } | Expr::Variable { .. } => vec![],
+        Expr::UnaryOp { expr, .. }
+        | Expr::Ascription { expr, .. }
+        | Expr::Cast { expr, .. }
+        | Expr::Index { expr, .. } => vec![*expr.clone()],
+        Expr::BinOp { lhs, rhs, .. } => vec![*lhs.clone(), *rhs.clone()],
+        Expr::IfThenElse {
+            cond, _then, _else, ..
+        } => vec![*cond.clone(), *_then.clone(), *_else.clone()],
+        Expr::Call { args, .. } => args.clone(),
+    }
+}
+
+// the expression with its `i`th subexpression replaced
+fn with_child(expr: &Expr<()>, i: usize, child: Expr<()>) -> Expr<()> {
+    let mut expr = expr.clone();
+    match &mut expr {
+        Expr::Literal { .. } | Expr::Variable { .. } => unreachable!("Leaves have no children"),
+        Expr::UnaryOp { expr, .. }
+        | Expr::Ascription { expr, .. }
+        | Expr::Cast { expr, .. }
+        | Expr::Index { expr, .. } => **expr = child,
+        Expr::BinOp { lhs, rhs, .. } => *[lhs, rhs][i].as_mut() = child,
+        Expr::IfThenElse {
+            cond, _then, _else, ..
+        } => *[cond, _then, _else][i].as_mut() = child,
+        Expr::Call { args, .. } => args[i] = child,
+    }
+    expr
+}
+
+// expressions that are smaller than `expr`, not all of them well-typed
+fn shrink_expr(expr: &Expr<()>) -> Vec<Expr<()>> {
+    let mut smaller = children(expr);
+    if !matches!(expr, Expr::Literal { .. }) {
+        let zeros = [
+            Literal::Field(0),
+            Literal::Boolean(false),
+            Literal::U8(0),
+            Literal::U32(0),
+            Literal::U64(0),
+        ];
+        smaller.extend(zeros.into_iter().map(literal));
+    }
+    for (i, child) in children(expr).iter().enumerate() {
+        for shrunk in shrink_expr(child) {
+            smaller.push(with_child(expr, i, shrunk));
+        }
+    }
+    smaller
+}
+
+// programs that are smaller than `program`, together with their inputs
+fn shrink_program(
+    program: &Program<()>,
+    context: &HashMap<Ident, Literal>,
+) -> Vec<(Program<()>, HashMap<Ident, Literal>)> {
+    let mut candidates = Vec::new();
+    let mut push = |decls: Vec<Declaration<()>>, expr: Option<Expr<()>>, context| {
+        if let Ok(program) = Program::new(decls, expr) {
+            if program.typecheck().is_ok() {
+                candidates.push((program, context));
+            }
+        }
+    };
+    let decls = &program.decls;
+    for (i, decl) in decls.iter().enumerate() {
+        let mut without = decls.clone();
+        without.remove(i);
+        let mut inputs = context.clone();
+        inputs.remove(decl.binder().var());
+        push(without, program.expr.clone(), inputs);
+        if let Declaration::VarAssignment { binder, expr } | Declaration::Output { binder, expr } =
+            decl
+        {
+            for shrunk in shrink_expr(expr) {
+                let mut decls = decls.clone();
+                decls[i] = match decl {
+                    Declaration::Output { .. } => Declaration::Output {
+                        binder: binder.clone(),
+                        expr: shrunk,
+                    },
+                    _ => Declaration::VarAssignment {
+                        binder: binder.clone(),
+                        expr: shrunk,
+                    },
+                };
+                push(decls, program.expr.clone(), context.clone());
+            }
+        }
+    }
+    if let Some(expr) = &program.expr {
+        for shrunk in shrink_expr(expr) {
+            push(decls.clone(), Some(shrunk), context.clone());
+        }
+    }
+    candidates
+}
+
+// shrink a failing program greedily, as long as some smaller one still fails
+fn minimize(
+    mut program: Program<()>,
+    mut context: HashMap<Ident, Literal>,
+    fails: impl Fn(&Program<()>, &HashMap<Ident, Literal>) -> bool,
+) -> (Program<()>, HashMap<Ident, Literal>) {
+    'shrink: loop {
+        for (smaller, inputs) in shrink_program(&program, &context) {
+            if fails(&smaller, &inputs) {
+                program = smaller;
+                context = inputs;
+                continue 'shrink;
+            }
+        }
+        return (program, context);
+    }
+}
+
+fn source_type(ty: &Ty) -> String {
+    match ty {
+        Ty::Field => "F".to_string(),
+        Ty::Boolean => "Bool".to_string(),
+        Ty::Array(elem, len) => format!("[{}; {}]", source_type(elem), len),
+        ty => ty.to_string().to_lowercase(),
+    }
+}
+
+fn format_program(program: &Program<()>) -> String {
+    let mut lines: Vec<String> = program
+        .decls
+        .iter()
+        .map(|decl| {
+            let var = decl.binder().var();
+            let ty = decl.binder().ty().map(|ty| source_type(&ty));
+            match decl {
+                Declaration::PublicVar { .. } => format!("pub {}: {};", var, ty.unwrap()),
+                Declaration::PrivateVar { .. } => format!("priv {}: {};", var, ty.unwrap()),
+                Declaration::VarAssignment { expr, .. } => {
+                    format!("let {} = {};", var, expr.format())
+                }
+                Declaration::Output { expr, .. } => format!("out {} = {};", var, expr.format()),
+            }
+        })
+        .collect();
+    lines.extend(program.expr.iter().map(|expr| expr.format()));
+    lines.join("\n")
+}
+
+fn env_or(name: &str, default: u64) -> u64 {
+    std::env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+fn fuzz<B: Backend>(backend: impl Fn() -> B, seed: u64, iterations: u64) {
+    for seed in seed..seed + iterations {
+        let (program, context) = generate(seed);
+        if check(backend(), &program, &context).is_some() {
+            let (program, context) = minimize(program, context, |program, context| {
+                check(backend(), program, context).is_some()
+            });
+            panic!(
+                "Seed {}: {}\n{}\nwith inputs {:?}",
+                seed,
+                check(backend(), &program, &context).unwrap(),
+                format_program(&program),
+                context
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod fuzz_tests {
+    use super::*;
+    use crate::parser;
+
+    #[test]
+    fn generator_test() {
+        for seed in 0..100 {
+            let (program, context) = generate(seed);
+            assert!(compiler::compile(program.clone()).is_ok());
+            assert_eq!(
+                context.len(),
+                program.public_variable_decls().len() + program.private_variable_decls().len()
+            );
+        }
+    }
+
+    #[test]
+    fn shrink_test() {
+        // a bug that shows on any multiplication is shrunk to a single one
+        let (program, context) = (0..)
+            .map(generate)
+            .find(|(program, _)| format_program(program).contains(" * "))
+            .unwrap();
+        let (program, _) = minimize(program, context, |program, _| {
+            format_program(program).contains(" * ")
+        });
+        assert_eq!(format_program(&program).matches(" * ").count(), 1);
+        assert!(program.decls.len() <= 1);
+    }
+
+    // the circuit used to check the overflow in the branch that is not taken, which the
+    // interpreter never evaluates
+    #[test]
+    fn untaken_branch_test() {
+        let program = parser::parse("pub c: Bool; pub x: u8; if c then x + 200u8 else 0u8")
+            .unwrap()
+            .clear_annotations();
+        for c in [false, true] {
+            let context = HashMap::from([
+                (Ident::new("c"), Literal::Boolean(c)),
+                (Ident::new("x"), Literal::U8(100)),
+            ]);
+            assert_eq!(
+                check(ConstraintChecker::default(), &program, &context),
+                None
+            );
+            assert_eq!(check(Plonky2Backend::default(), &program, &context), None);
+        }
+    }
+
+    #[test]
+    fn checker_fuzz_test() {
+        let seed = env_or("CALC_FUZZ_SEED", 0);
+        let iterations = env_or("CALC_FUZZ_ITERATIONS", 200);
+        fuzz(ConstraintChecker::default, seed, iterations);
+    }
+
+    // proving is slower, so fewer programs are proved with plonky2
+    #[test]
+    fn plonky2_fuzz_test() {
+        let seed = env_or("CALC_FUZZ_SEED", 0);
+        let iterations = env_or("CALC_FUZZ_ITERATIONS", 200) / 10;
+        fuzz(Plonky2Backend::default, seed, iterations);
+    }
+}
@@ -1,19 +1,58 @@
 use crate::ast::{
-    annotation::HasSourceLoc, error::ASTError, Expr, Ident, Literal, Opcode, UOpcode,
+    annotation::{HasSourceLoc, Span},
+    error::ASTError,
+    program::RESULT,
+    Builtin, Declaration, Expr, Ident, Literal, Opcode, Program, Ty, UOpcode,
+};
+use crate::ir::{Circuit, Instruction, Op, Wire};
+use crate::plonk::{
+    circuit_builder::{from_bool, from_literal},
+    F,
 };
 use anyhow::{anyhow, Result};
 use core::ops::{Add, Mul, Neg, Sub};
+use err_derive::Error;
+use plonky2::field::types::{Field, PrimeField64};
+use plonky2::hash::poseidon::PoseidonHash;
+use plonky2::plonk::config::Hasher;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+#[derive(Debug, Error)]
+pub enum RuntimeError {
+    #[error(display = "Integer overflow at {}: {}", _0, _1)]
+    IntegerOverflow(Span, String),
+    #[error(display = "Invalid cast at {}: {} does not fit in {}", _0, _1, _2)]
+    InvalidCast(Span, String, Ty),
+    #[error(display = "Value of {} at {} does not fit in {} bits", _1, _0, _2)]
+    ValueTooWide(Span, String, usize),
+    #[error(display = "Invalid operands at {}: {}", _0, _1)]
+    InvalidOperands(Span, String),
+    #[error(display = "Condition at {} is not a boolean: {}", _0, _1)]
+    NonBooleanCondition(Span, String),
+    #[error(display = "Indexing a value that is not an array at {}: {}", _0, _1)]
+    NotAnArray(Span, String),
+    #[error(display = "Index out of bounds at {}: {} has {} elements", _0, _1, _2)]
+    IndexOutOfBounds(Span, String, usize),
+}
+
 pub struct Context<A> {
     pub context: HashMap<Ident, Expr<A>>,
+    // bindings that were already evaluated, they take precedence over `context`
+    pub values: HashMap<Ident, Value>,
+}
+
+impl<A: Clone> Default for Context<A> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<A: Clone> Context<A> {
     pub fn new() -> Self {
         Context {
             context: HashMap::new(),
+            values: HashMap::new(),
         }
     }
 
@@ -36,131 +75,651 @@ impl<A: Clone + Default> From<HashMap<Ident, Literal>> for Context<A> {
                 )
             })
             .collect();
-        Context { context }
+        Context {
+            context,
+            values: HashMap::new(),
+        }
     }
 }
 
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub enum Value {
-    Field(i32),
+    Field(F),
     Boolean(bool),
+    U8(u8),
+    U32(u32),
+    U64(u64),
+    Array(Vec<Value>),
 }
 
+impl From<Literal> for Value {
+    fn from(lit: Literal) -> Self {
+        match lit {
+            Literal::Field(_) => Value::Field(from_literal(lit)),
+            Literal::Boolean(b) => Value::Boolean(b),
+            Literal::U8(n) => Value::U8(n),
+            Literal::U32(n) => Value::U32(n),
+            Literal::U64(n) => Value::U64(n),
+            Literal::Array(elems) => Value::Array(elems.into_iter().map(Value::from).collect()),
+        }
+    }
+}
+
+// the operators return `None` when the operands have the wrong types, only Fields can be
+// combined with them
 impl Neg for Value {
-    type Output = Self;
-    fn neg(self) -> Self {
+    type Output = Option<Self>;
+    fn neg(self) -> Option<Self> {
         match self {
-            Value::Field(n) => Value::Field(-n),
-            _ => unreachable!("Only Fields can be negated"),
+            Value::Field(n) => Some(Value::Field(-n)),
+            _ => None,
         }
     }
 }
 
 impl Add for Value {
-    type Output = Self;
-    fn add(self, rhs: Self) -> Self {
+    type Output = Option<Self>;
+    fn add(self, rhs: Self) -> Option<Self> {
         match (self, rhs) {
-            (Value::Field(lhs), Value::Field(rhs)) => Value::Field(lhs + rhs),
-            _ => unreachable!("Only Fields can be added"),
+            (Value::Field(lhs), Value::Field(rhs)) => Some(Value::Field(lhs + rhs)),
+            _ => None,
         }
     }
 }
 
 impl Sub for Value {
-    type Output = Self;
-    fn sub(self, rhs: Self) -> Self {
+    type Output = Option<Self>;
+    fn sub(self, rhs: Self) -> Option<Self> {
         match (self, rhs) {
-            (Value::Field(lhs), Value::Field(rhs)) => Value::Field(lhs - rhs),
-            _ => unreachable!("Only Fields can be subtracted"),
+            (Value::Field(lhs), Value::Field(rhs)) => Some(Value::Field(lhs - rhs)),
+            _ => None,
         }
     }
 }
 
 impl Mul for Value {
-    type Output = Self;
-    fn mul(self, rhs: Self) -> Self {
+    type Output = Option<Self>;
+    fn mul(self, rhs: Self) -> Option<Self> {
         match (self, rhs) {
-            (Value::Field(lhs), Value::Field(rhs)) => Value::Field(lhs * rhs),
-            _ => unreachable!("Only Fields can be multiplied"),
+            (Value::Field(lhs), Value::Field(rhs)) => Some(Value::Field(lhs * rhs)),
+            _ => None,
         }
     }
 }
 
 impl Value {
-    pub fn pow(self, rhs: Value) -> Self {
-        match (self, rhs) {
-            (Value::Field(n), Value::Field(m)) => Value::Field(n.pow(m as u32)),
-            _ => unreachable!("Only Fields can be raised to a power"),
+    // the field element a scalar value is exposed as in a proof
+    pub fn to_field(&self) -> F {
+        match self {
+            Value::Field(n) => *n,
+            Value::Boolean(b) => from_bool(*b),
+            Value::U8(n) => F::from_canonical_u8(*n),
+            Value::U32(n) => F::from_canonical_u32(*n),
+            Value::U64(n) => F::from_noncanonical_u64(*n),
+            Value::Array(_) => unreachable!("Arrays are not exposed in proofs"),
+        }
+    }
+
+    fn uint(ty: Ty, n: u64) -> Self {
+        match ty {
+            Ty::U8 => Value::U8(n as u8),
+            Ty::U32 => Value::U32(n as u32),
+            Ty::U64 => Value::U64(n),
+            _ => unreachable!("{} is not an unsigned integer type", ty),
+        }
+    }
+
+    fn is_number(&self) -> bool {
+        matches!(self, Value::Field(_)) || self.as_uint().is_some()
+    }
+
+    fn as_uint(&self) -> Option<(Ty, u64)> {
+        match self {
+            Value::U8(n) => Some((Ty::U8, *n as u64)),
+            Value::U32(n) => Some((Ty::U32, *n as u64)),
+            Value::U64(n) => Some((Ty::U64, *n)),
+            _ => None,
+        }
+    }
+
+    // unsigned integer arithmetic, returning the result truncated to the width of the
+    // operands together with a flag telling whether the exact result would have overflowed,
+    // or `None` if the operands aren't unsigned integers of the same type
+    fn uint_arith(self, op: Opcode, rhs: Self) -> Option<(Self, bool)> {
+        match (self.as_uint(), rhs.as_uint()) {
+            (Some((ty, lhs)), Some((rhs_ty, rhs))) if ty == rhs_ty => {
+                let max = ty.uint_max().unwrap();
+                let (result, overflow) = match op {
+                    Opcode::Add | Opcode::WrappingAdd => lhs.overflowing_add(rhs),
+                    Opcode::Sub | Opcode::WrappingSub => lhs.overflowing_sub(rhs),
+                    Opcode::Mul | Opcode::WrappingMul => lhs.overflowing_mul(rhs),
+                    op => unreachable!("{:?} is not an integer operation", op),
+                };
+                Some((Value::uint(ty, result & max), overflow || result > max))
+            }
+            _ => None,
+        }
+    }
+
+    // convert between fields and unsigned integers, returning `None` if the value does not fit
+    // or isn't a number
+    fn cast(self, ty: &Ty) -> Option<Self> {
+        let n = match (self, ty) {
+            (Value::Field(n), Ty::Field) => return Some(Value::Field(n)),
+            (Value::Field(n), _) => n.to_canonical_u64(),
+            (value, Ty::Field) => {
+                let (_, n) = value.as_uint()?;
+                return Some(Value::Field(F::from_noncanonical_u64(n)));
+            }
+            (value, _) => value.as_uint()?.1,
+        };
+        match ty.uint_max() {
+            Some(max) if n <= max => Some(Value::uint(ty.clone(), n)),
+            _ => None,
+        }
+    }
+
+    // `None` if the operands aren't unsigned integers of the same type, the shift amount
+    // can be of any unsigned integer type
+    fn bitwise(self, func: Builtin, rhs: Self) -> Option<Self> {
+        let shift = matches!(func, Builtin::Shl | Builtin::Shr);
+        match (self.as_uint(), rhs.as_uint()) {
+            (Some((ty, lhs)), Some((rhs_ty, rhs))) if shift || ty == rhs_ty => {
+                let bits = ty.uint_bits().unwrap() as u64;
+                let max = ty.uint_max().unwrap();
+                let result = match func {
+                    Builtin::BitAnd => lhs & rhs,
+                    Builtin::BitOr => lhs | rhs,
+                    Builtin::BitXor => lhs ^ rhs,
+                    // shifting by the width or more moves every bit out
+                    Builtin::Shl if rhs < bits => (lhs << rhs) & max,
+                    Builtin::Shr if rhs < bits => lhs >> rhs,
+                    Builtin::Shl | Builtin::Shr => 0,
+                    func => unreachable!("{} is not a bitwise operation", func),
+                };
+                Some(Value::uint(ty, result))
+            }
+            _ => None,
+        }
+    }
+
+    // the Poseidon digest of a list of field elements and arrays of them, the arrays are
+    // hashed element by element
+    fn poseidon(values: &[Value]) -> Self {
+        let inputs: Vec<F> = values
+            .iter()
+            .flat_map(|value| match value {
+                Value::Array(elems) => elems.iter().collect(),
+                value => vec![value],
+            })
+            .map(|value| match value {
+                Value::Field(n) => *n,
+                _ => unreachable!("Only Fields can be hashed"),
+            })
+            .collect();
+        let digest = PoseidonHash::hash_no_pad(&inputs).elements;
+        Value::Array(digest.into_iter().map(Value::Field).collect())
+    }
+
+    // hash `leaf` up to the root along `path`, each bit of the index tells whether the
+    // node is the right child at that level
+    fn merkle_root(leaf: Value, index_bits: Value, path: Value) -> Self {
+        match (index_bits, path) {
+            (Value::Array(bits), Value::Array(path)) => {
+                bits.into_iter()
+                    .zip(path)
+                    .fold(leaf, |node, (bit, sibling)| match bit {
+                        Value::Boolean(true) => Value::poseidon(&[sibling, node]),
+                        _ => Value::poseidon(&[node, sibling]),
+                    })
+            }
+            _ => unreachable!("Merkle paths are arrays"),
         }
     }
-    fn and(self, rhs: Self) -> Self {
+
+    // the little-endian bits of a number, returning `None` if it needs more than `width` bits
+    fn to_bits(&self, width: usize) -> Option<Self> {
+        let n = match self {
+            Value::Field(n) => n.to_canonical_u64(),
+            value => value.as_uint().expect("Only numbers have bits").1,
+        };
+        if width < 64 && n >> width != 0 {
+            return None;
+        }
+        Some(Value::Array(
+            (0..width)
+                .map(|i| Value::Boolean((n >> i) & 1 == 1))
+                .collect(),
+        ))
+    }
+
+    fn merkle_depth(path: &Value) -> usize {
+        match path {
+            Value::Array(path) => path.len(),
+            _ => unreachable!("Merkle paths are arrays"),
+        }
+    }
+
+    // whether `leaf` is at position `index` under `root` along `path`, or `None` if the index
+    // does not fit in the depth of the path
+    fn merkle_verify(root: &Value, leaf: &Value, index: &Value, path: &Value) -> Option<Self> {
+        let index_bits = index.to_bits(Value::merkle_depth(path))?;
+        let computed = Value::merkle_root(leaf.clone(), index_bits, path.clone());
+        Some(Value::Boolean(computed == *root))
+    }
+
+    // `None` unless a Field is raised to a number
+    pub fn pow(self, rhs: Value) -> Option<Self> {
+        let exponent = match (&self, rhs) {
+            (Value::Field(_), Value::Field(m)) => m.to_canonical_u64(),
+            (Value::Field(_), rhs) => rhs.as_uint()?.1,
+            _ => return None,
+        };
+        match self {
+            Value::Field(n) => Some(Value::Field(n.exp_u64(exponent))),
+            _ => None,
+        }
+    }
+    fn and(self, rhs: Self) -> Option<Self> {
         match (self, rhs) {
-            (Value::Boolean(lhs), Value::Boolean(rhs)) => Value::Boolean(lhs && rhs),
-            _ => unreachable!("Only Booleans can be and-ed"),
+            (Value::Boolean(lhs), Value::Boolean(rhs)) => Some(Value::Boolean(lhs && rhs)),
+            _ => None,
         }
     }
-    fn or(self, rhs: Self) -> Self {
+    fn or(self, rhs: Self) -> Option<Self> {
         match (self, rhs) {
-            (Value::Boolean(lhs), Value::Boolean(rhs)) => Value::Boolean(lhs || rhs),
-            _ => unreachable!("Only Booleans can be or-ed"),
+            (Value::Boolean(lhs), Value::Boolean(rhs)) => Some(Value::Boolean(lhs || rhs)),
+            _ => None,
+        }
+    }
+    // `None` unless both sides are values of the same type
+    fn equals(self, rhs: Self) -> Option<Self> {
+        match (&self, &rhs) {
+            (Value::Array(_), _) | (_, Value::Array(_)) => None,
+            _ if std::mem::discriminant(&self) == std::mem::discriminant(&rhs) => {
+                Some(Value::Boolean(self == rhs))
+            }
+            _ => None,
         }
     }
 }
 
+// whether the builtin can be applied to the values, which the typechecker guarantees for the
+// arguments of well typed calls. The width of `to_bits` is not checked, it is a literal.
+fn valid_operands(func: Builtin, values: &[Value]) -> bool {
+    let is_field = |value: &Value| matches!(value, Value::Field(_));
+    let is_digest = |value: &Value| match value {
+        Value::Array(elems) => elems.len() == 4 && elems.iter().all(is_field),
+        _ => false,
+    };
+    match (func, values) {
+        (Builtin::BitAnd | Builtin::BitOr | Builtin::BitXor, [lhs, rhs]) => {
+            matches!((lhs.as_uint(), rhs.as_uint()), (Some((lhs, _)), Some((rhs, _))) if lhs == rhs)
+        }
+        // the shift amount can be of any unsigned integer type
+        (Builtin::Shl | Builtin::Shr, [lhs, rhs]) => {
+            lhs.as_uint().is_some() && rhs.as_uint().is_some()
+        }
+        (Builtin::ToBits, [value, _]) => value.is_number(),
+        (Builtin::Poseidon, values) => {
+            let hashable = |value: &Value| match value {
+                Value::Array(elems) => elems.iter().all(is_field),
+                value => is_field(value),
+            };
+            !values.is_empty() && values.iter().all(hashable)
+        }
+        (Builtin::MerkleVerify, [root, leaf, index, Value::Array(path)]) => {
+            is_digest(root) && is_digest(leaf) && index.is_number() && path.iter().all(is_digest)
+        }
+        _ => false,
+    }
+}
+
+// apply a binary operator, the errors are located at `span` and show the operation formatted
+// by `format`
+fn binop(
+    op: Opcode,
+    lhs: Value,
+    rhs: Value,
+    span: Span,
+    format: impl Fn() -> String,
+) -> Result<Value> {
+    let result = match op {
+        Opcode::Add | Opcode::Sub | Opcode::Mul if lhs.as_uint().is_some() => {
+            match lhs.uint_arith(op, rhs) {
+                Some((_, true)) => {
+                    return Err(anyhow!(RuntimeError::IntegerOverflow(span, format())))
+                }
+                result => result.map(|(result, _)| result),
+            }
+        }
+        Opcode::WrappingAdd | Opcode::WrappingSub | Opcode::WrappingMul => {
+            lhs.uint_arith(op, rhs).map(|(result, _)| result)
+        }
+        Opcode::Add => lhs + rhs,
+        Opcode::Sub => lhs - rhs,
+        Opcode::Mul => lhs * rhs,
+        Opcode::Pow => lhs.pow(rhs),
+        Opcode::And => lhs.and(rhs),
+        Opcode::Or => lhs.or(rhs),
+        Opcode::Eq => lhs.equals(rhs),
+    };
+    result.ok_or_else(|| anyhow!(RuntimeError::InvalidOperands(span, format())))
+}
+
+// the element of an array, the errors are located at `span` and show the operation formatted
+// by `format`
+fn index(value: Value, index: usize, span: Span, format: impl Fn() -> String) -> Result<Value> {
+    match value {
+        Value::Array(mut values) if index < values.len() => Ok(values.swap_remove(index)),
+        Value::Array(values) => Err(anyhow!(RuntimeError::IndexOutOfBounds(
+            span,
+            format(),
+            values.len()
+        ))),
+        _ => Err(anyhow!(RuntimeError::NotAnArray(span, format()))),
+    }
+}
+
+// notified of every expression the interpreter evaluates
+pub trait Observer {
+    // called before the expression bound to `name` is evaluated
+    fn binding(&mut self, _name: &Ident) {}
+
+    // called before the expression is evaluated, an error stops the evaluation
+    fn enter<A: Clone + HasSourceLoc>(&mut self, _expr: &Expr<A>) -> Result<()> {
+        Ok(())
+    }
+
+    fn exit<A: Clone + HasSourceLoc>(&mut self, _expr: &Expr<A>, _result: &Result<Value>) {}
+}
+
+impl Observer for () {}
+
 pub fn interpret<A: Clone + HasSourceLoc>(
     context: &mut Context<A>,
     expr: &Expr<A>,
+) -> Result<Value> {
+    interpret_with(context, expr, &mut ())
+}
+
+// interpret the expression, telling the observer about it and each of its subexpressions
+pub fn interpret_with<A: Clone + HasSourceLoc, O: Observer>(
+    context: &mut Context<A>,
+    expr: &Expr<A>,
+    observer: &mut O,
+) -> Result<Value> {
+    observer.enter(expr)?;
+    let result = evaluate(context, expr, observer);
+    observer.exit(expr, &result);
+    result
+}
+
+fn evaluate<A: Clone + HasSourceLoc, O: Observer>(
+    context: &mut Context<A>,
+    expr: &Expr<A>,
+    observer: &mut O,
 ) -> Result<Value> {
     match expr {
-        Expr::Literal {
-            value: Literal::Field(n),
-            ..
-        } => Ok(Value::Field(*n)),
-        Expr::Literal {
-            value: Literal::Boolean(b),
-            ..
-        } => Ok(Value::Boolean(*b)),
-        Expr::UnaryOp { op, expr, .. } => {
-            let expr = interpret(context, expr)?;
-            match op {
-                UOpcode::Neg => Ok(-expr),
-            }
+        Expr::Literal { value, .. } => Ok(Value::from(value.clone())),
+        Expr::UnaryOp { ann, op, expr: arg } => {
+            let value = interpret_with(context, arg, observer)?;
+            let result = match op {
+                UOpcode::Neg => -value,
+            };
+            result.ok_or_else(|| {
+                anyhow!(RuntimeError::InvalidOperands(
+                    ann.source_loc(),
+                    expr.format()
+                ))
+            })
         }
-        Expr::BinOp { lhs, op, rhs, .. } => {
-            let lhs = interpret(context, lhs)?;
-            let rhs = interpret(context, rhs)?;
-            match op {
-                Opcode::Add => Ok(lhs + rhs),
-                Opcode::Sub => Ok(lhs - rhs),
-                Opcode::Mul => Ok(lhs * rhs),
-                Opcode::Pow => Ok(lhs.pow(rhs)),
-                Opcode::And => Ok(lhs.and(rhs)),
-                Opcode::Or => Ok(lhs.or(rhs)),
-                Opcode::Eq => Ok(Value::Boolean(lhs == rhs)),
-            }
+        Expr::BinOp { ann, lhs, op, rhs } => {
+            let lhs = interpret_with(context, lhs, observer)?;
+            let rhs = interpret_with(context, rhs, observer)?;
+            binop(*op, lhs, rhs, ann.source_loc(), || expr.format())
         }
+        Expr::Variable { value, .. } if context.values.contains_key(value) => {
+            Ok(context.values[value].clone())
+        }
+        // the value a variable is bound to is part of the variable for the observer, it is
+        // cached so that shared bindings are only evaluated once
         Expr::Variable { value, ann } => match context.get(value) {
-            Some(expr) => interpret(context, &expr),
-            None => {
-                return Err(anyhow!(ASTError::UnboundIdentifier(
-                    ann.source_loc(),
-                    value.clone()
-                )))
+            Some(expr) => {
+                let result = evaluate(context, &expr, observer)?;
+                context.values.insert(value.clone(), result.clone());
+                Ok(result)
             }
+            None => Err(anyhow!(ASTError::UnboundIdentifier(
+                ann.source_loc(),
+                value.clone()
+            ))),
         },
         Expr::IfThenElse {
             cond, _then, _else, ..
-        } => {
-            let cond = interpret(context, cond)?;
-            match cond {
-                Value::Boolean(true) => interpret(context, _then),
-                Value::Boolean(false) => interpret(context, _else),
-                _ => unreachable!("Only booleans can be used as conditions"),
+        } => match interpret_with(context, cond, observer)? {
+            Value::Boolean(true) => interpret_with(context, _then, observer),
+            Value::Boolean(false) => interpret_with(context, _else, observer),
+            _ => Err(anyhow!(RuntimeError::NonBooleanCondition(
+                cond.source_loc(),
+                cond.format()
+            ))),
+        },
+        Expr::Ascription { expr, .. } => interpret_with(context, expr, observer),
+        Expr::Cast { ann, expr, _type } => {
+            let value = interpret_with(context, expr, observer)?;
+            value.cast(_type).ok_or_else(|| {
+                anyhow!(RuntimeError::InvalidCast(
+                    ann.source_loc(),
+                    expr.format(),
+                    _type.clone()
+                ))
+            })
+        }
+        Expr::Call { ann, func, args } => {
+            func.check_arity(ann.source_loc(), args.len())?;
+            let values = args
+                .iter()
+                .map(|arg| interpret_with(context, arg, observer))
+                .collect::<Result<Vec<Value>>>()?;
+            if !valid_operands(*func, &values) {
+                return Err(anyhow!(RuntimeError::InvalidOperands(
+                    ann.source_loc(),
+                    expr.format()
+                )));
             }
+            match func {
+                Builtin::ToBits => {
+                    let width = args[1].bit_width()?;
+                    values[0].to_bits(width).ok_or_else(|| {
+                        anyhow!(RuntimeError::ValueTooWide(
+                            ann.source_loc(),
+                            args[0].format(),
+                            width
+                        ))
+                    })
+                }
+                Builtin::Poseidon => Ok(Value::poseidon(&values)),
+                Builtin::MerkleVerify => Value::merkle_verify(
+                    &values[0], &values[1], &values[2], &values[3],
+                )
+                .ok_or_else(|| {
+                    anyhow!(RuntimeError::ValueTooWide(
+                        args[2].source_loc(),
+                        args[2].format(),
+                        Value::merkle_depth(&values[3])
+                    ))
+                }),
+                func => values[0]
+                    .clone()
+                    .bitwise(*func, values[1].clone())
+                    .ok_or_else(|| {
+                        anyhow!(RuntimeError::InvalidOperands(
+                            ann.source_loc(),
+                            expr.format()
+                        ))
+                    }),
+            }
+        }
+        Expr::Index {
+            ann,
+            expr: array,
+            index: i,
+        } => {
+            let value = interpret_with(context, array, observer)?;
+            index(value, *i, ann.source_loc(), || expr.format())
         }
     }
 }
 
+// evaluate the let-bindings and outputs of the program once each, in the order
+// `Program::new` sorted them, and then its final expression. every binding is returned with
+// its value in that order, except the lets that fail: the circuit only checks a let where it
+// is used, which may be a branch that is not taken, so they only fail once they are used
+pub fn interpret_program<A: Clone + Default + HasSourceLoc>(
+    program: &Program<A>,
+    inputs: &HashMap<Ident, Literal>,
+) -> Result<Vec<(Ident, Value)>> {
+    interpret_program_with(program, inputs, &mut ())
+}
+
+// `interpret_program`, telling the observer which binding it is evaluating
+pub fn interpret_program_with<A: Clone + Default + HasSourceLoc, O: Observer>(
+    program: &Program<A>,
+    inputs: &HashMap<Ident, Literal>,
+    observer: &mut O,
+) -> Result<Vec<(Ident, Value)>> {
+    let mut context = Context::from(inputs.clone());
+    let exprs = program
+        .decls
+        .iter()
+        .filter_map(|decl| match decl {
+            Declaration::VarAssignment { binder, expr } => Some((binder.var().clone(), expr, true)),
+            Declaration::Output { binder, expr } => Some((binder.var().clone(), expr, false)),
+            _ => None,
+        })
+        .chain(
+            program
+                .expr
+                .iter()
+                .map(|expr| (Ident::new(RESULT), expr, false)),
+        );
+    let mut bindings = Vec::new();
+    for (name, expr, is_let) in exprs {
+        observer.binding(&name);
+        // a failed let is evaluated again, and fails again, wherever it is used
+        context.context.insert(name.clone(), expr.clone());
+        let value = match interpret_with(&mut context, expr, observer) {
+            Ok(value) => value,
+            Err(err) if is_let && err.is::<RuntimeError>() => continue,
+            Err(err) => return Err(err),
+        };
+        context.values.insert(name.clone(), value.clone());
+        bindings.push((name, value));
+    }
+    Ok(bindings)
+}
+
+// evaluate every wire of a circuit on the given inputs, with the same semantics as `interpret`
+pub fn interpret_circuit<A: HasSourceLoc>(
+    circuit: &Circuit<A>,
+    inputs: &HashMap<Ident, Literal>,
+) -> Result<Vec<Value>> {
+    let mut values: Vec<Value> = Vec::with_capacity(circuit.instructions.len());
+    for Instruction { ann, ty, op, .. } in &circuit.instructions {
+        let value = |wire: &Wire| values[wire.0].clone();
+        let result = match op {
+            Op::Const(lit) => Value::from(lit.clone()),
+            Op::Input(ident) => match inputs.get(ident) {
+                Some(lit) => Value::from(lit.clone()),
+                None => {
+                    return Err(anyhow!(ASTError::UnboundIdentifier(
+                        ann.source_loc(),
+                        ident.clone()
+                    )))
+                }
+            },
+            Op::UnaryOp(UOpcode::Neg, wire) => (-value(wire)).ok_or_else(|| {
+                anyhow!(RuntimeError::InvalidOperands(
+                    ann.source_loc(),
+                    op.to_string()
+                ))
+            })?,
+            Op::BinOp(opcode, lhs, rhs) => {
+                binop(*opcode, value(lhs), value(rhs), ann.source_loc(), || {
+                    op.to_string()
+                })?
+            }
+            Op::Select(cond, _then, _else) => match value(cond) {
+                Value::Boolean(true) => value(_then),
+                Value::Boolean(false) => value(_else),
+                _ => {
+                    return Err(anyhow!(RuntimeError::NonBooleanCondition(
+                        ann.source_loc(),
+                        op.to_string()
+                    )))
+                }
+            },
+            Op::Cast(wire) => value(wire).cast(ty).ok_or_else(|| {
+                anyhow!(RuntimeError::InvalidCast(
+                    ann.source_loc(),
+                    op.to_string(),
+                    ty.clone()
+                ))
+            })?,
+            Op::ToBits(wire, _) if !value(wire).is_number() => {
+                return Err(anyhow!(RuntimeError::InvalidOperands(
+                    ann.source_loc(),
+                    op.to_string()
+                )))
+            }
+            Op::ToBits(wire, width) => value(wire).to_bits(*width).ok_or_else(|| {
+                anyhow!(RuntimeError::ValueTooWide(
+                    ann.source_loc(),
+                    op.to_string(),
+                    *width
+                ))
+            })?,
+            Op::Call(func, args) => {
+                func.check_arity(ann.source_loc(), args.len())?;
+                let args: Vec<Value> = args.iter().map(value).collect();
+                if !valid_operands(*func, &args) {
+                    return Err(anyhow!(RuntimeError::InvalidOperands(
+                        ann.source_loc(),
+                        op.to_string()
+                    )));
+                }
+                match func {
+                    Builtin::Poseidon => Value::poseidon(&args),
+                    Builtin::MerkleVerify => Value::merkle_verify(
+                        &args[0], &args[1], &args[2], &args[3],
+                    )
+                    .ok_or_else(|| {
+                        anyhow!(RuntimeError::ValueTooWide(
+                            ann.source_loc(),
+                            op.to_string(),
+                            Value::merkle_depth(&args[3])
+                        ))
+                    })?,
+                    Builtin::ToBits => unreachable!("to_bits is lowered to its own operation"),
+                    func => args[0]
+                        .clone()
+                        .bitwise(*func, args[1].clone())
+                        .ok_or_else(|| {
+                            anyhow!(RuntimeError::InvalidOperands(
+                                ann.source_loc(),
+                                op.to_string()
+                            ))
+                        })?,
+                }
+            }
+            Op::Index(wire, i) => index(value(wire), *i, ann.source_loc(), || op.to_string())?,
+        };
+        values.push(result);
+    }
+    Ok(values)
+}
+
 #[cfg(test)]
 mod interpreter_tests {
     use super::*;
@@ -171,7 +730,10 @@ mod interpreter_tests {
         let input = "22 * 44 + 66";
         let expr = parser::parse_single_expression(input).unwrap();
         let mut context = Context::new();
-        assert_eq!(interpret(&mut context, &expr).unwrap(), Value::Field(1034));
+        assert_eq!(
+            interpret(&mut context, &expr).unwrap(),
+            Value::Field(F::from_canonical_u32(1034))
+        );
     }
 
     #[test]
@@ -179,7 +741,246 @@ mod interpreter_tests {
         let input = "22 * (44 + 66)";
         let expr = parser::parse_single_expression(input).unwrap();
         let mut context = Context::new();
-        assert_eq!(interpret(&mut context, &expr).unwrap(), Value::Field(2420));
+        assert_eq!(
+            interpret(&mut context, &expr).unwrap(),
+            Value::Field(F::from_canonical_u32(2420))
+        );
+    }
+
+    #[test]
+    fn field_wraparound_test() {
+        let input = "0 - 1 + 1";
+        let expr = parser::parse_single_expression(input).unwrap();
+        let mut context = Context::new();
+        assert_eq!(
+            interpret(&mut context, &expr).unwrap(),
+            Value::Field(F::ZERO)
+        );
+    }
+
+    #[test]
+    fn uint_overflow_test() {
+        let mut context = Context::new();
+        let checked = parser::parse_single_expression("200u8 + 56u8").unwrap();
+        match interpret(&mut context, &checked) {
+            Err(err) => match err.downcast_ref() {
+                Some(RuntimeError::IntegerOverflow(_, _)) => (),
+                _ => panic!("Expected IntegerOverflow error"),
+            },
+            _ => panic!("Expected IntegerOverflow error"),
+        };
+        let wrapping = parser::parse_single_expression("200u8 +% 56u8").unwrap();
+        assert_eq!(interpret(&mut context, &wrapping).unwrap(), Value::U8(0));
+        let wrapping = parser::parse_single_expression("0u64 -% 1u64").unwrap();
+        assert_eq!(
+            interpret(&mut context, &wrapping).unwrap(),
+            Value::U64(u64::MAX)
+        );
+        let wrapping = parser::parse_single_expression("65536u32 *% 65536u32").unwrap();
+        assert_eq!(interpret(&mut context, &wrapping).unwrap(), Value::U32(0));
+    }
+
+    #[test]
+    fn cast_test() {
+        let mut context = Context::new();
+        let expr = parser::parse_single_expression("(0 - 1) as u64").unwrap();
+        assert_eq!(
+            interpret(&mut context, &expr).unwrap(),
+            Value::U64(F::NEG_ONE.to_canonical_u64())
+        );
+        let expr = parser::parse_single_expression("18446744073709551615u64 as F").unwrap();
+        assert_eq!(
+            interpret(&mut context, &expr).unwrap(),
+            Value::Field(F::from_noncanonical_u64(u64::MAX))
+        );
+        let expr = parser::parse_single_expression("256 as u8").unwrap();
+        match interpret(&mut context, &expr) {
+            Err(err) => match err.downcast_ref() {
+                Some(RuntimeError::InvalidCast(_, _, Ty::U8)) => (),
+                _ => panic!("Expected InvalidCast error"),
+            },
+            _ => panic!("Expected InvalidCast error"),
+        };
+    }
+
+    #[test]
+    fn bitwise_test() {
+        let mut context = Context::new();
+        let cases = [
+            ("bit_and(12u8, 10u8)", Value::U8(8)),
+            ("bit_or(12u8, 10u8)", Value::U8(14)),
+            ("bit_xor(255u8, 15u8)", Value::U8(240)),
+            ("shl(255u8, 7u8)", Value::U8(128)),
+            ("shl(255u8, 8u64)", Value::U8(0)),
+            ("shr(4294967295u32, 31u8)", Value::U32(1)),
+            ("shr(1u64, 64u8)", Value::U64(0)),
+        ];
+        for (input, expected) in cases {
+            let expr = parser::parse_single_expression(input).unwrap();
+            assert_eq!(interpret(&mut context, &expr).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn to_bits_test() {
+        let mut context = Context::new();
+        let expr = parser::parse_single_expression("to_bits(6, 3)").unwrap();
+        assert_eq!(
+            interpret(&mut context, &expr).unwrap(),
+            Value::Array(vec![
+                Value::Boolean(false),
+                Value::Boolean(true),
+                Value::Boolean(true)
+            ])
+        );
+        let expr = parser::parse_single_expression("to_bits(0 - 1, 64)[63]").unwrap();
+        assert_eq!(
+            interpret(&mut context, &expr).unwrap(),
+            Value::Boolean(true)
+        );
+        let expr = parser::parse_single_expression("to_bits(8u32, 3)").unwrap();
+        match interpret(&mut context, &expr) {
+            Err(err) => match err.downcast_ref() {
+                Some(RuntimeError::ValueTooWide(_, _, 3)) => (),
+                _ => panic!("Expected ValueTooWide error"),
+            },
+            _ => panic!("Expected ValueTooWide error"),
+        };
+    }
+
+    #[test]
+    fn untaken_let_test() {
+        // the circuit only checks the overflow where `y` is used, which it is not when c is false
+        let source = "pub c: Bool; pub x: u8; let y = x + 200u8; if c then y else 0u8";
+        let program: Program<Span> = parser::parse(source).unwrap();
+        let inputs = |c| {
+            HashMap::from([
+                (Ident::new("c"), Literal::Boolean(c)),
+                (Ident::new("x"), Literal::U8(100)),
+            ])
+        };
+        let bindings = interpret_program(&program, &inputs(false)).unwrap();
+        assert_eq!(bindings, vec![(Ident::new(RESULT), Value::U8(0))]);
+        match interpret_program(&program, &inputs(true)) {
+            Err(err) => match err.downcast_ref() {
+                Some(RuntimeError::IntegerOverflow(_, _)) => (),
+                _ => panic!("Expected IntegerOverflow error"),
+            },
+            _ => panic!("Expected IntegerOverflow error"),
+        };
+    }
+
+    #[test]
+    fn shared_bindings_test() {
+        // every binding is used twice by the next one, evaluating them again on every use
+        // would take 2^64 steps
+        let mut source = "pub x: F;\nlet a0 = x;\n".to_string();
+        for i in 1..=64 {
+            source += &format!("let a{} = a{} + a{};\n", i, i - 1, i - 1);
+        }
+        source += "a64";
+        let program: Program<Span> = parser::parse(&source).unwrap();
+        let inputs = HashMap::from([(Ident::new("x"), Literal::Field(1))]);
+        let bindings = interpret_program(&program, &inputs).unwrap();
+        assert_eq!(bindings.len(), 66);
+        assert_eq!(
+            bindings[3],
+            (Ident::new("a3"), Value::Field(F::from_canonical_u32(8)))
+        );
+        assert_eq!(
+            bindings[65],
+            (
+                Ident::new(RESULT),
+                Value::Field(F::from_noncanonical_u64(1 << 63) * F::TWO)
+            )
+        );
+
+        // variables bound to expressions in the context are cached as well
+        let mut context = Context::new();
+        context.context.insert(
+            Ident::new("a0"),
+            parser::parse_single_expression("1").unwrap(),
+        );
+        for i in 1..=64 {
+            let expr = format!("a{} + a{}", i - 1, i - 1);
+            context.context.insert(
+                Ident::new(&format!("a{}", i)),
+                parser::parse_single_expression(&expr).unwrap(),
+            );
+        }
+        let expr = parser::parse_single_expression("a64 - a63").unwrap();
+        assert_eq!(
+            interpret(&mut context, &expr).unwrap(),
+            Value::Field(F::from_noncanonical_u64(1 << 63))
+        );
+    }
+
+    #[test]
+    fn type_error_test() {
+        // unchecked expressions with operands of the wrong types
+        let mut context = Context::new();
+        for input in [
+            "true + 1",
+            "1 - 2u8",
+            "3u8 * 4u32",
+            "-true",
+            "true ^ 2",
+            "2 ^ true",
+            "1 && true",
+            "false || 0u8",
+            "1 == true",
+            "1u8 == 1u32",
+            "2u64 == 2",
+            "bit_and(1, 2)",
+            "bit_or(1u8, 300u32)",
+            "poseidon(true)",
+            "to_bits(true, 2)",
+            "merkle_verify(1, 2, 0u8, 3)",
+            "merkle_verify(poseidon(1), poseidon(2), 0u8, 3)",
+        ] {
+            let expr = parser::parse_single_expression(input).unwrap();
+            match interpret(&mut context, &expr) {
+                Err(err) => match err.downcast_ref() {
+                    Some(RuntimeError::InvalidOperands(span, _)) => assert_eq!(span.line(), 1),
+                    _ => panic!("Expected InvalidOperands error for {}", input),
+                },
+                _ => panic!("Expected InvalidOperands error for {}", input),
+            };
+        }
+        for input in ["bit_and(1u8)", "to_bits(1)", "merkle_verify(1, 2)"] {
+            let expr = parser::parse_single_expression(input).unwrap();
+            match interpret(&mut context, &expr) {
+                Err(err) => match err.downcast_ref() {
+                    Some(ASTError::ArityMismatch(..)) => (),
+                    _ => panic!("Expected ArityMismatch error for {}", input),
+                },
+                _ => panic!("Expected ArityMismatch error for {}", input),
+            };
+        }
+        let expr = parser::parse_single_expression("if 1 then 2 else 3").unwrap();
+        match interpret(&mut context, &expr) {
+            Err(err) => match err.downcast_ref() {
+                Some(RuntimeError::NonBooleanCondition(_, cond)) => assert_eq!(cond, "1"),
+                _ => panic!("Expected NonBooleanCondition error"),
+            },
+            _ => panic!("Expected NonBooleanCondition error"),
+        };
+        let expr = parser::parse_single_expression("1[0]").unwrap();
+        match interpret(&mut context, &expr) {
+            Err(err) => match err.downcast_ref() {
+                Some(RuntimeError::NotAnArray(_, _)) => (),
+                _ => panic!("Expected NotAnArray error"),
+            },
+            _ => panic!("Expected NotAnArray error"),
+        };
+        let expr = parser::parse_single_expression("to_bits(1, 2)[2]").unwrap();
+        match interpret(&mut context, &expr) {
+            Err(err) => match err.downcast_ref() {
+                Some(RuntimeError::IndexOutOfBounds(_, _, 2)) => (),
+                _ => panic!("Expected IndexOutOfBounds error"),
+            },
+            _ => panic!("Expected IndexOutOfBounds error"),
+        };
     }
 
     #[test]
@@ -187,6 +988,9 @@ mod interpreter_tests {
         let input = "2^4 + 1";
         let expr = parser::parse_single_expression(input).unwrap();
         let mut context = Context::new();
-        assert_eq!(interpret(&mut context, &expr).unwrap(), Value::Field(17));
+        assert_eq!(
+            interpret(&mut context, &expr).unwrap(),
+            Value::Field(F::from_canonical_u32(17))
+        );
     }
 }
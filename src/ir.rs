@@ -0,0 +1,544 @@
+use crate::ast::annotation::{HasSourceLoc, Span};
+use crate::ast::{Binder, Builtin, Expr, Ident, Literal, Opcode, Ty, UOpcode};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::Display;
+
+// a wire is the index of the instruction computing it
+#[derive(Debug, PartialEq, Eq, Hash, Serialize, Deserialize, Clone, Copy)]
+pub struct Wire(pub usize);
+
+impl Display for Wire {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "%{}", self.0)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Serialize, Deserialize, Clone)]
+pub enum Op {
+    Const(Literal),
+    Input(Ident),
+    UnaryOp(UOpcode, Wire),
+    BinOp(Opcode, Wire, Wire),
+    Select(Wire, Wire, Wire),
+    // the target type is the type of the instruction
+    Cast(Wire),
+    // little-endian decomposition into the given number of bits
+    ToBits(Wire, usize),
+    Call(Builtin, Vec<Wire>),
+    Index(Wire, usize),
+}
+
+impl Op {
+    // the wires this operation reads
+    pub fn operands(&self) -> Vec<Wire> {
+        match self {
+            Op::Const(_) | Op::Input(_) => vec![],
+            Op::UnaryOp(_, wire) | Op::Cast(wire) | Op::ToBits(wire, _) | Op::Index(wire, _) => {
+                vec![*wire]
+            }
+            Op::BinOp(_, lhs, rhs) => vec![*lhs, *rhs],
+            Op::Select(cond, _then, _else) => vec![*cond, *_then, *_else],
+            Op::Call(_, args) => args.clone(),
+        }
+    }
+
+    fn map_operands(self, f: impl Fn(Wire) -> Wire) -> Self {
+        match self {
+            Op::Const(_) | Op::Input(_) => self,
+            Op::UnaryOp(op, wire) => Op::UnaryOp(op, f(wire)),
+            Op::BinOp(op, lhs, rhs) => Op::BinOp(op, f(lhs), f(rhs)),
+            Op::Select(cond, _then, _else) => Op::Select(f(cond), f(_then), f(_else)),
+            Op::Cast(wire) => Op::Cast(f(wire)),
+            Op::ToBits(wire, width) => Op::ToBits(f(wire), width),
+            Op::Call(func, args) => Op::Call(func, args.into_iter().map(f).collect()),
+            Op::Index(wire, index) => Op::Index(f(wire), index),
+        }
+    }
+}
+
+fn opcode_name(op: Opcode) -> &'static str {
+    match op {
+        Opcode::Add => "add",
+        Opcode::Sub => "sub",
+        Opcode::Mul => "mul",
+        Opcode::Pow => "pow",
+        Opcode::And => "and",
+        Opcode::Or => "or",
+        Opcode::Eq => "eq",
+        Opcode::WrappingAdd => "wrapping_add",
+        Opcode::WrappingSub => "wrapping_sub",
+        Opcode::WrappingMul => "wrapping_mul",
+    }
+}
+
+impl Op {
+    // the mnemonic of the operation, as printed in the IR
+    pub fn name(&self) -> &'static str {
+        match self {
+            Op::Const(_) => "const",
+            Op::Input(_) => "input",
+            Op::UnaryOp(UOpcode::Neg, _) => "neg",
+            Op::BinOp(op, _, _) => opcode_name(*op),
+            Op::Select(_, _, _) => "select",
+            Op::Cast(_) => "cast",
+            Op::ToBits(_, _) => "to_bits",
+            Op::Call(func, _) => func.name(),
+            Op::Index(_, _) => "index",
+        }
+    }
+}
+
+impl Display for Op {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())?;
+        match self {
+            Op::Const(value) => write!(f, " {}", value),
+            Op::Input(ident) => write!(f, " {}", ident),
+            Op::ToBits(wire, n) | Op::Index(wire, n) => write!(f, " {} {}", wire, n),
+            op => {
+                for wire in op.operands() {
+                    write!(f, " {}", wire)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+// `ann` is the annotation of the expression the instruction was lowered from, `name` the
+// `let` the expression was bound to, if any
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct Instruction<A> {
+    pub ann: A,
+    pub ty: Ty,
+    pub op: Op,
+    pub name: Option<Ident>,
+}
+
+// a program in SSA form: every instruction defines one wire and only reads earlier ones
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct Circuit<A> {
+    pub public_inputs: Vec<(Ident, Ty)>,
+    pub private_inputs: Vec<(Ident, Ty)>,
+    pub instructions: Vec<Instruction<A>>,
+    pub outputs: Vec<(Ident, Wire)>,
+}
+
+impl<A> Circuit<A> {
+    pub fn ty(&self, wire: Wire) -> &Ty {
+        &self.instructions[wire.0].ty
+    }
+
+    // rebuild the circuit from the instructions for which `keep` returns a wire,
+    // `keep` gets the instruction with its operands already renumbered
+    fn rebuild(
+        self,
+        mut keep: impl FnMut(&[Instruction<A>], &Instruction<A>) -> Option<Wire>,
+    ) -> Self {
+        let mut instructions: Vec<Instruction<A>> = Vec::new();
+        let mut renumbering: Vec<Option<Wire>> = Vec::with_capacity(self.instructions.len());
+        for Instruction { ann, ty, op, name } in self.instructions {
+            let op = op.map_operands(|wire| renumbering[wire.0].expect("Operands are kept"));
+            let instruction = Instruction { ann, ty, op, name };
+            let wire = keep(&instructions, &instruction);
+            match wire {
+                Some(Wire(i)) if i == instructions.len() => instructions.push(instruction),
+                // an instruction merged into an earlier one lends it its name
+                Some(Wire(i)) if instructions[i].name.is_none() => {
+                    instructions[i].name = instruction.name
+                }
+                _ => (),
+            }
+            renumbering.push(wire);
+        }
+        Circuit {
+            public_inputs: self.public_inputs,
+            private_inputs: self.private_inputs,
+            instructions,
+            outputs: self
+                .outputs
+                .into_iter()
+                .map(|(ident, wire)| (ident, renumbering[wire.0].expect("Outputs are kept")))
+                .collect(),
+        }
+    }
+
+    // common subexpression elimination: identical instructions are computed only once
+    pub fn cse(self) -> Self {
+        let mut seen: HashMap<(Ty, Op), Wire> = HashMap::new();
+        self.rebuild(|instructions, instruction| {
+            // constants are merged when they are the same value, not the same literal
+            let op = match &instruction.op {
+                Op::Const(value) => Op::Const(value.canonical()),
+                op => op.clone(),
+            };
+            let key = (instruction.ty.clone(), op);
+            Some(*seen.entry(key).or_insert(Wire(instructions.len())))
+        })
+    }
+
+    // dead code elimination: drop the instructions no output depends on, inputs are
+    // always kept since they are part of the interface of the circuit
+    pub fn dce(self) -> Self {
+        let mut live = vec![false; self.instructions.len()];
+        for (_, wire) in &self.outputs {
+            live[wire.0] = true;
+        }
+        for (i, instruction) in self.instructions.iter().enumerate().rev() {
+            if live[i] || matches!(instruction.op, Op::Input(_)) {
+                live[i] = true;
+                for wire in instruction.op.operands() {
+                    live[wire.0] = true;
+                }
+            }
+        }
+        let mut live = live.into_iter();
+        self.rebuild(|instructions, _| live.next().unwrap().then_some(Wire(instructions.len())))
+    }
+}
+
+impl<A> Display for Circuit<A> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (ident, ty) in &self.public_inputs {
+            writeln!(f, "pub {}: {}", ident, ty)?;
+        }
+        for (ident, ty) in &self.private_inputs {
+            writeln!(f, "priv {}: {}", ident, ty)?;
+        }
+        for (i, instruction) in self.instructions.iter().enumerate() {
+            writeln!(f, "{}: {} = {}", Wire(i), instruction.ty, instruction.op)?;
+        }
+        for (ident, wire) in &self.outputs {
+            writeln!(f, "out {} = {}", ident, wire)?;
+        }
+        Ok(())
+    }
+}
+
+struct Lowering<A> {
+    instructions: Vec<Instruction<A>>,
+    inputs: HashMap<Ident, Wire>,
+    // the conditions of the branches being lowered, outermost first, with whether it is the
+    // then branch, and the wire of the conjunction of the path up to each one once needed
+    path: Vec<(Wire, bool)>,
+    guards: Vec<Option<Wire>>,
+    // the `let` bindings by the location of their expression
+    names: HashMap<Span, Ident>,
+}
+
+// the value partial operations are given in branches that are not taken
+fn zero(ty: &Ty) -> Literal {
+    match ty {
+        Ty::Field => Literal::Field(0),
+        Ty::Boolean => Literal::Boolean(false),
+        Ty::U8 => Literal::U8(0),
+        Ty::U32 => Literal::U32(0),
+        Ty::U64 => Literal::U64(0),
+        ty => unreachable!("{} has no zero", ty),
+    }
+}
+
+impl<A: Clone + HasSourceLoc> Lowering<A> {
+    fn push(&mut self, ann: A, ty: Ty, op: Op) -> Wire {
+        self.instructions.push(Instruction {
+            ann,
+            ty,
+            op,
+            name: None,
+        });
+        Wire(self.instructions.len() - 1)
+    }
+
+    fn ty(&self, wire: Wire) -> Ty {
+        self.instructions[wire.0].ty.clone()
+    }
+
+    fn constant(&mut self, ann: &A, value: Literal) -> Wire {
+        self.push(ann.clone(), value.ty(), Op::Const(value))
+    }
+
+    // the wire that is true when every branch being lowered is taken, `None` outside of them
+    fn guard(&mut self, ann: &A) -> Option<Wire> {
+        for i in 0..self.path.len() {
+            if self.guards[i].is_some() {
+                continue;
+            }
+            let cond = match self.path[i] {
+                (cond, true) => cond,
+                (cond, false) => {
+                    let _false = self.constant(ann, Literal::Boolean(false));
+                    let _true = self.constant(ann, Literal::Boolean(true));
+                    self.push(ann.clone(), Ty::Boolean, Op::Select(cond, _false, _true))
+                }
+            };
+            let guard = match i {
+                0 => cond,
+                _ => {
+                    let outer = self.guards[i - 1].expect("Outer guards come first");
+                    self.push(
+                        ann.clone(),
+                        Ty::Boolean,
+                        Op::BinOp(Opcode::And, outer, cond),
+                    )
+                }
+            };
+            self.guards[i] = Some(guard);
+        }
+        self.guards.last().copied().flatten()
+    }
+
+    // the operand of an operation that can fail, replaced by zero in branches that are not
+    // taken. The circuit computes both branches of an `if` while the interpreter only
+    // evaluates the one that is taken, so the untaken one must not make the circuit
+    // unsatisfiable.
+    fn guarded(&mut self, ann: &A, wire: Wire) -> Wire {
+        match self.guard(ann) {
+            Some(guard) => {
+                let ty = self.ty(wire);
+                let zero = self.constant(ann, zero(&ty));
+                self.push(ann.clone(), ty, Op::Select(guard, wire, zero))
+            }
+            None => wire,
+        }
+    }
+
+    fn lower_branch(&mut self, cond: Wire, taken: bool, expr: Expr<A>) -> Wire {
+        self.path.push((cond, taken));
+        self.guards.push(None);
+        let wire = self.lower_expr(expr);
+        self.path.pop();
+        self.guards.pop();
+        wire
+    }
+
+    // the expression must be well typed and only refer to inputs
+    fn lower_expr(&mut self, expr: Expr<A>) -> Wire {
+        let name = self.names.get(&expr.source_loc()).cloned();
+        let wire = self.lower_node(expr);
+        let instruction = &mut self.instructions[wire.0];
+        // constants and inputs have no instruction of their own, a wire keeps its first name
+        if !matches!(instruction.op, Op::Const(_) | Op::Input(_)) && instruction.name.is_none() {
+            instruction.name = name;
+        }
+        wire
+    }
+
+    fn lower_node(&mut self, expr: Expr<A>) -> Wire {
+        match expr {
+            Expr::Literal { ann, value } => self.push(ann, value.ty(), Op::Const(value)),
+            Expr::Variable { value, .. } => match self.inputs.get(&value) {
+                Some(wire) => *wire,
+                None => panic!("Variable {} is not an input of the circuit", value),
+            },
+            Expr::UnaryOp { ann, op, expr } => {
+                let wire = self.lower_expr(*expr);
+                let ty = self.ty(wire);
+                self.push(ann, ty, Op::UnaryOp(op, wire))
+            }
+            Expr::BinOp { ann, lhs, op, rhs } => {
+                let mut lhs = self.lower_expr(*lhs);
+                let mut rhs = self.lower_expr(*rhs);
+                // checked integer arithmetic fails on overflow
+                if matches!(op, Opcode::Add | Opcode::Sub | Opcode::Mul)
+                    && self.ty(lhs).uint_bits().is_some()
+                {
+                    lhs = self.guarded(&ann, lhs);
+                    rhs = self.guarded(&ann, rhs);
+                }
+                let ty = match op {
+                    Opcode::Eq => Ty::Boolean,
+                    _ => self.ty(lhs),
+                };
+                self.push(ann, ty, Op::BinOp(op, lhs, rhs))
+            }
+            Expr::IfThenElse {
+                ann,
+                cond,
+                _then,
+                _else,
+            } => {
+                let cond = self.lower_expr(*cond);
+                let _then = self.lower_branch(cond, true, *_then);
+                let _else = self.lower_branch(cond, false, *_else);
+                let ty = self.ty(_then);
+                self.push(ann, ty, Op::Select(cond, _then, _else))
+            }
+            Expr::Ascription { expr, .. } => self.lower_expr(*expr),
+            Expr::Cast { ann, expr, _type } => {
+                let mut wire = self.lower_expr(*expr);
+                // casts to integers fail when the value does not fit
+                if _type.uint_bits().is_some() {
+                    wire = self.guarded(&ann, wire);
+                }
+                self.push(ann, _type, Op::Cast(wire))
+            }
+            Expr::Call {
+                ann,
+                func: Builtin::ToBits,
+                args,
+            } => {
+                let mut args = args.into_iter();
+                let wire = self.lower_expr(args.next().expect("Expected argument"));
+                let wire = self.guarded(&ann, wire);
+                let width = match args.next() {
+                    Some(Expr::Literal {
+                        value: Literal::Field(width),
+                        ..
+                    }) => width as usize,
+                    _ => unreachable!("to_bits expects a constant width"),
+                };
+                let ty = Ty::Array(Box::new(Ty::Boolean), width);
+                self.push(ann, ty, Op::ToBits(wire, width))
+            }
+            Expr::Call { ann, func, args } => {
+                let mut args: Vec<Wire> =
+                    args.into_iter().map(|arg| self.lower_expr(arg)).collect();
+                // the index of a merkle proof must fit in the depth of the path
+                if func == Builtin::MerkleVerify {
+                    args[2] = self.guarded(&ann, args[2]);
+                }
+                let ty = match func {
+                    Builtin::Poseidon => Ty::digest(),
+                    Builtin::MerkleVerify => Ty::Boolean,
+                    _ => self.ty(args[0]),
+                };
+                self.push(ann, ty, Op::Call(func, args))
+            }
+            Expr::Index { ann, expr, index } => {
+                let wire = self.lower_expr(*expr);
+                let ty = match self.ty(wire) {
+                    Ty::Array(elem_ty, _) => *elem_ty,
+                    ty => unreachable!("Expected an array, found {}", ty),
+                };
+                self.push(ann, ty, Op::Index(wire, index))
+            }
+        }
+    }
+}
+
+// lower typechecked expressions over the inputs into a circuit, the inputs are given the
+// first wires, public ones first. `names` are the `let` bindings by the location of their
+// expression, the instructions computing them are named after them.
+pub fn lower<A: Clone + HasSourceLoc>(
+    public_inputs: Vec<Binder<A>>,
+    private_inputs: Vec<Binder<A>>,
+    outputs: Vec<(Ident, Expr<A>)>,
+    names: HashMap<Span, Ident>,
+) -> Circuit<A> {
+    let mut lowering = Lowering {
+        instructions: Vec::new(),
+        inputs: HashMap::new(),
+        path: Vec::new(),
+        guards: Vec::new(),
+        names,
+    };
+    let mut lower_inputs = |binders: Vec<Binder<A>>| -> Vec<(Ident, Ty)> {
+        binders
+            .into_iter()
+            .map(|binder| {
+                // input variables are always declared with a type by the parser
+                let ty = binder.ty().unwrap_or(Ty::Field);
+                let ident = binder.var().clone();
+                let op = Op::Input(ident.clone());
+                let wire = lowering.push(binder.ann().clone(), ty.clone(), op);
+                lowering.inputs.insert(ident.clone(), wire);
+                (ident, ty)
+            })
+            .collect()
+    };
+    let public_inputs = lower_inputs(public_inputs);
+    let private_inputs = lower_inputs(private_inputs);
+    let outputs = outputs
+        .into_iter()
+        .map(|(ident, expr)| (ident, lowering.lower_expr(expr)))
+        .collect();
+    Circuit {
+        public_inputs,
+        private_inputs,
+        instructions: lowering.instructions,
+        outputs,
+    }
+}
+
+#[cfg(test)]
+mod ir_tests {
+    use crate::passes::PassManager;
+    use crate::{compiler, parser};
+
+    #[test]
+    fn lower_test() {
+        let input = "pub x: F; priv y: u8; out a = (x + 1) * x; out b = to_bits(y, 4)[3];";
+        let program = compiler::compile(parser::parse(input).unwrap()).unwrap();
+        assert_eq!(
+            program.ir.to_string(),
+            "pub x: Field\n\
+             priv y: U8\n\
+             %0: Field = input x\n\
+             %1: U8 = input y\n\
+             %2: Field = const 1\n\
+             %3: Field = add %0 %2\n\
+             %4: Field = mul %3 %0\n\
+             %5: [Boolean; 4] = to_bits %1 4\n\
+             %6: Boolean = index %5 3\n\
+             out a = %4\n\
+             out b = %6\n"
+        );
+    }
+
+    #[test]
+    fn guard_test() {
+        let input = "pub c: Bool; pub x: u8; if c then x + 1u8 else x *% 2u8";
+        let program = compiler::compile(parser::parse(input).unwrap()).unwrap();
+        // the checked addition only sees `x` when `c` holds, the wrapping product can't fail
+        assert_eq!(
+            program.ir.to_string(),
+            "pub c: Boolean\n\
+             pub x: U8\n\
+             %0: Boolean = input c\n\
+             %1: U8 = input x\n\
+             %2: U8 = const 1u8\n\
+             %3: U8 = const 0u8\n\
+             %4: U8 = select %0 %1 %3\n\
+             %5: U8 = select %0 %2 %3\n\
+             %6: U8 = add %4 %5\n\
+             %7: U8 = const 2u8\n\
+             %8: U8 = wrapping_mul %1 %7\n\
+             %9: U8 = select %0 %6 %8\n\
+             out result = %9\n"
+        );
+    }
+
+    #[test]
+    fn cse_dce_test() {
+        let input = "pub x: F; priv y: F; out a = (x + 1) * (x + 1);";
+        let passes = PassManager::from_names(&["inline".to_string()]).unwrap();
+        let program = compiler::compile_with(parser::parse(input).unwrap(), &passes).unwrap();
+        // `y` is unused but stays an input of the circuit
+        assert_eq!(
+            program.ir.cse().dce().to_string(),
+            "pub x: Field\n\
+             priv y: Field\n\
+             %0: Field = input x\n\
+             %1: Field = input y\n\
+             %2: Field = const 1\n\
+             %3: Field = add %0 %2\n\
+             %4: Field = mul %3 %3\n\
+             out a = %4\n"
+        );
+        // -1 and p - 1 are the same constant
+        let input = "pub x: F; out a = x * (0 - 1); out b = x * 18446744069414584320;";
+        let names = ["inline".to_string(), "fold".to_string()];
+        let passes = PassManager::from_names(&names).unwrap();
+        let program = compiler::compile_with(parser::parse(input).unwrap(), &passes).unwrap();
+        assert_eq!(
+            program.ir.cse().dce().to_string(),
+            "pub x: Field\n\
+             %0: Field = input x\n\
+             %1: Field = const -1\n\
+             %2: Field = mul %0 %1\n\
+             out a = %2\n\
+             out b = %2\n"
+        );
+    }
+}
@@ -1,90 +1,630 @@
 pub mod ast;
+pub mod backend;
 pub mod compiler;
+pub mod context;
+pub mod debugger;
+pub mod export;
+#[cfg(test)]
+mod fuzz;
 pub mod interpreter;
+pub mod ir;
 pub mod parser;
+pub mod passes;
 pub mod plonk;
+pub mod trace;
+pub mod witness;
 
-use anyhow::Result;
-use ast::{Ident, Literal};
-use clap::Parser;
+use anyhow::{anyhow, Result};
+use ast::{annotation::Span, Ident, Literal, Program, Ty};
+use backend::{Backend, ConstraintChecker};
+use clap::{Parser, Subcommand, ValueEnum};
+use compiler::CompiledProgram;
+use context::{load_context, parse_context, ContextFormat, RawContext};
+use debugger::Debugger;
+use err_derive::Error;
+use export::circom::{to_circom, to_circom_input};
+use export::r1cs::{build_r1cs, write_witness};
+use interpreter::{interpret_program, interpret_program_with, Value};
 use jemallocator::Jemalloc;
-use plonk::{prove, F};
+use passes::PassManager;
+use plonk::backend::SerializedProof;
+use plonk::prove::{load_proof, public_input_names};
+use plonk::{profile::profile, prove, stats::stats, HashConfig, Plonky2Backend, ProofConfig, F};
 use plonky2::field::types::Field;
 use std::collections::HashMap;
 use std::fs::File;
-use std::io::{self, Read};
+use std::io::{self, BufWriter, Read};
+use std::path::{Path, PathBuf};
+use trace::Trace;
+use witness::{check_witness, generate_witness};
 
 #[global_allocator]
 static GLOBAL: Jemalloc = Jemalloc;
 
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, subcommand_negates_reqs = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    // the same as `calc run`, `-` reads the program from stdin
     #[arg(short, long)]
-    input_file: String,
+    input_file: Option<String>,
+
+    #[command(flatten)]
+    run: RunOptions,
+
+    // set an input, on top of those in the context
+    #[arg(short = 'D', value_name = "NAME=VALUE", global = true)]
+    define: Vec<String>,
+
+    // optimization level, from 0 (inlining only) to 2
+    #[arg(short = 'O', default_value_t = 2, global = true)]
+    opt_level: u8,
+
+    // comma separated passes to run instead of those of the optimization level
+    #[arg(long, value_delimiter = ',', global = true)]
+    passes: Option<Vec<String>>,
+
+    // print the program after every pass
+    #[arg(long, global = true)]
+    dump_passes: bool,
 
+    // the proving system the circuit is built for
+    #[arg(long, value_enum, default_value_t = ProvingBackend::Plonky2, global = true)]
+    backend: ProvingBackend,
+
+    #[command(flatten)]
+    proof: ProofOptions,
+}
+
+// what to do with the program that is run, instead of proving it
+#[derive(clap::Args, Debug)]
+struct RunOptions {
+    // a json or toml file with the values of the inputs
     #[arg(short, long)]
     context: Option<String>,
 
     #[arg(short, long)]
     serialize: bool,
+
+    #[arg(long, value_enum)]
+    emit: Option<Emit>,
+
+    // write the plonky2 proof to a file, with the config it was made with
+    #[arg(long)]
+    save_proof: Option<String>,
+}
+
+// options of plonky2 proofs, those not given are taken from the standard recursion config
+#[derive(clap::Args, Debug)]
+struct ProofOptions {
+    // blind the proof so that it reveals nothing about the private inputs
+    #[arg(long, global = true)]
+    zk: bool,
+
+    // keccak makes proofs cheap to verify on the EVM, but not recursively
+    #[arg(long, value_enum, global = true)]
+    hash: Option<HashConfig>,
+
+    // log2 of the FRI blowup factor, larger rates prove slower but need fewer query rounds
+    #[arg(long, global = true)]
+    rate_bits: Option<usize>,
+
+    #[arg(long, global = true)]
+    query_rounds: Option<usize>,
+
+    #[arg(long, global = true)]
+    pow_bits: Option<u32>,
+
+    #[arg(long, global = true)]
+    num_wires: Option<usize>,
+
+    #[arg(long, global = true)]
+    num_routed_wires: Option<usize>,
+}
+
+impl ProofOptions {
+    fn config(&self) -> ProofConfig {
+        let default = ProofConfig::default();
+        ProofConfig {
+            zero_knowledge: self.zk,
+            hash: self.hash.unwrap_or(default.hash),
+            rate_bits: self.rate_bits.unwrap_or(default.rate_bits),
+            num_query_rounds: self.query_rounds.unwrap_or(default.num_query_rounds),
+            proof_of_work_bits: self.pow_bits.unwrap_or(default.proof_of_work_bits),
+            num_wires: self.num_wires.unwrap_or(default.num_wires),
+            num_routed_wires: self.num_routed_wires.unwrap_or(default.num_routed_wires),
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    // interpret the program and prove it, `-` reads the program from stdin
+    Run {
+        input_file: String,
+
+        #[command(flatten)]
+        options: RunOptions,
+    },
+    // interpret a program given on the command line, without proving it
+    Eval {
+        source: String,
+
+        #[arg(short, long)]
+        context: Option<String>,
+    },
+    // print every expression the interpreter evaluates with its value
+    Trace {
+        input_file: String,
+
+        #[arg(short, long)]
+        context: Option<String>,
+
+        // print json instead of an indented tree
+        #[arg(long)]
+        json: bool,
+    },
+    // step through the evaluation of the program, type `help` at the prompt for the commands
+    Debug {
+        input_file: String,
+
+        #[arg(short, long)]
+        context: Option<String>,
+
+        // stop at the first expression on the line, without breakpoints the debugger stops at
+        // the first expression of the program
+        #[arg(short, long = "break")]
+        breakpoints: Vec<u32>,
+    },
+    // check a proof saved by `calc run --save-proof`, the circuit is rebuilt from the program
+    // with the passes and the config saved with the proof
+    Verify {
+        input_file: String,
+
+        proof: String,
+    },
+    // report the size of the circuit and an estimate of the cost of proving it
+    Stats {
+        input_file: String,
+    },
+    // attribute the gates of the circuit to the source lines they come from
+    Profile {
+        input_file: String,
+
+        // print folded stacks for flamegraph tools instead of a table
+        #[arg(long)]
+        folded: bool,
+    },
+    // write the circuit in a format other proving stacks understand
+    Export {
+        input_file: String,
+
+        #[arg(long, value_enum)]
+        format: ExportFormat,
+
+        // a witness is written as well for the inputs in the context
+        #[arg(short, long)]
+        context: Option<String>,
+
+        // the path of the files without extension, defaults to that of the input file
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    // print the value of every binding and wire of the program as json, once they are
+    // checked against the witness plonky2 generates
+    Witness {
+        input_file: String,
+
+        #[arg(short, long)]
+        context: Option<String>,
+
+        // write to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+enum ExportFormat {
+    // iden3 .r1cs constraints and .wtns witness
+    R1cs,
+    // a circom template, and its input.json
+    Circom,
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+enum ProvingBackend {
+    Plonky2,
+    // evaluates the constraints without proving, for quick checks
+    Checker,
+}
+
+// intermediate stages of the compiler that can be printed instead of proving
+#[derive(ValueEnum, Clone, Debug)]
+enum Emit {
+    Ir,
 }
 
-fn read_context(file_path: &str) -> io::Result<HashMap<Ident, Literal>> {
-    let file = File::open(file_path)?;
-    let data = serde_json::from_reader(file)?;
-    Ok(data)
+// the inputs of the compiled program, public ones first
+fn compiled_inputs(program: &CompiledProgram<Span>) -> Vec<(Ident, Ty)> {
+    program
+        .public_vars
+        .iter()
+        .chain(&program.private_vars)
+        .cloned()
+        .collect()
+}
+
+// the inputs declared by the program, public ones first
+fn declared_inputs(program: &Program<Span>) -> Vec<(Ident, Ty)> {
+    program
+        .public_variable_decls()
+        .iter()
+        .chain(&program.private_variable_decls())
+        .map(|decl| {
+            let binder = decl.binder();
+            // input variables are always declared with a type by the parser
+            (binder.var().clone(), binder.ty().unwrap_or(Ty::Field))
+        })
+        .collect()
+}
+
+// the values of the inputs of the program, from the context file and the definitions
+fn read_context(
+    args: &Args,
+    file_path: Option<&str>,
+    inputs: &[(Ident, Ty)],
+) -> Result<HashMap<Ident, Literal>> {
+    let context = match file_path {
+        Some(path) => parse_context(&read_file(path)?, ContextFormat::from_path(Path::new(path)))?,
+        None => RawContext::new(),
+    };
+    load_context(context, &args.define, inputs)
+}
+
+#[derive(Debug, Error)]
+enum SourceError {
+    #[error(display = "No such file: {}", _0)]
+    NotFound(String),
+    #[error(
+        display = "No program to run, give one with --input-file or use calc run or calc eval"
+    )]
+    NoInput,
+}
+
+// the contents of the file, or of stdin for `-`
+fn read_file(file_path: &str) -> Result<String> {
+    let mut contents = String::new();
+    if file_path == "-" {
+        io::stdin().read_to_string(&mut contents)?;
+        return Ok(contents);
+    }
+    let mut file = File::open(file_path).map_err(|err| match err.kind() {
+        io::ErrorKind::NotFound => anyhow!(SourceError::NotFound(file_path.to_string())),
+        _ => anyhow!(err),
+    })?;
+    file.read_to_string(&mut contents)?;
+    Ok(contents)
+}
+
+fn compile_source(args: &Args, contents: &str) -> Result<CompiledProgram<Span>> {
+    compile_program(args, &parser::parse(contents)?)
+}
+
+// compile a program that was already parsed, for the commands that interpret it as well
+fn compile_program(args: &Args, program: &Program<Span>) -> Result<CompiledProgram<Span>> {
+    compiler::compile_with(program.clone(), &pass_manager(args)?)
+}
+
+fn pass_manager(args: &Args) -> Result<PassManager> {
+    let passes = match args.passes {
+        Some(ref names) => PassManager::from_names(names)?,
+        None => PassManager::from_level(args.opt_level)?,
+    };
+    Ok(passes.dump(args.dump_passes))
+}
+
+// the value of each of the outputs according to the interpreter, in the order they are given
+fn interpret_outputs(
+    source: &Program<Span>,
+    outputs: &[Ident],
+    inputs: &HashMap<Ident, Literal>,
+) -> Result<Vec<(Ident, Value)>> {
+    let bindings = interpret_program(source, inputs)?;
+    outputs
+        .iter()
+        .map(|name| {
+            let (_, value) = bindings
+                .iter()
+                .find(|(binding, _)| binding == name)
+                .ok_or_else(|| anyhow!("Output {} was not evaluated", name))?;
+            Ok((name.clone(), value.clone()))
+        })
+        .collect()
+}
+
+fn export_r1cs(
+    program: &CompiledProgram<Span>,
+    context: Option<&HashMap<Ident, Literal>>,
+    output: &Path,
+) -> Result<()> {
+    let (r1cs, witness) = build_r1cs(program, context)?;
+    let path = output.with_extension("r1cs");
+    r1cs.write(&mut BufWriter::new(File::create(&path)?))?;
+    println!(
+        "Wrote {} constraints over {} wires to {}",
+        r1cs.constraints.len(),
+        r1cs.num_wires,
+        path.display()
+    );
+    if context.is_some() {
+        r1cs.check(&witness)?;
+        let path = output.with_extension("wtns");
+        write_witness(&witness, &mut BufWriter::new(File::create(&path)?))?;
+        println!("Wrote the witness to {}", path.display());
+    }
+    Ok(())
+}
+
+fn export_circom(
+    program: &CompiledProgram<Span>,
+    context: Option<&HashMap<Ident, Literal>>,
+    output: &Path,
+) -> Result<()> {
+    let path = output.with_extension("circom");
+    std::fs::write(&path, to_circom(program)?)?;
+    println!("Wrote {}", path.display());
+    if let Some(context) = context {
+        let path = output.with_extension("input.json");
+        std::fs::write(&path, to_circom_input(context)?)?;
+        println!("Wrote the inputs to {}", path.display());
+    }
+    Ok(())
 }
 
 fn default_main() -> Result<()> {
     let args = Args::parse();
-    let mut file = File::open(args.input_file)?;
-    let mut contents = String::new();
-    file.read_to_string(&mut contents).unwrap();
-    let program = parser::parse(&contents)?;
-    let program = compiler::compile(program)?;
+    match args.command {
+        Some(Command::Stats { ref input_file }) => {
+            let program = compile_source(&args, &read_file(input_file)?)?;
+            Ok(print!("{}", stats(program, &args.proof.config())?))
+        }
+        Some(Command::Verify {
+            ref input_file,
+            ref proof,
+        }) => {
+            let serialized: SerializedProof = serde_json::from_str(&read_file(proof)?)?;
+            // the passes decide which circuit is built, so those of the proof replace -O and
+            // --passes
+            let passes = PassManager::from_names(&serialized.passes)?.dump(args.dump_passes);
+            let program = compiler::compile_with(parser::parse(&read_file(input_file)?)?, &passes)?;
+            // the proof exposes the public inputs followed by the outputs
+            let names: Vec<String> = public_input_names(&program.public_vars)
+                .into_iter()
+                .chain(program.outputs.iter().map(|(ident, _)| ident.to_string()))
+                .collect();
+            let proof = load_proof(program, serialized)?;
+            Plonky2Backend::verify(&proof)?;
+            for (name, value) in names.iter().zip(Plonky2Backend::public_values(&proof)) {
+                println!("{} = {}", name, value);
+            }
+            Ok(println!("Verified!"))
+        }
+        Some(Command::Profile {
+            ref input_file,
+            folded,
+        }) => {
+            let source = read_file(input_file)?;
+            let profile = profile(compile_source(&args, &source)?);
+            if folded {
+                return Ok(print!("{}", profile.format_folded()));
+            }
+            Ok(print!("{}", profile.format_text(&source)))
+        }
+        Some(Command::Export {
+            ref input_file,
+            ref format,
+            ref context,
+            ref output,
+        }) => {
+            let program = compile_source(&args, &read_file(input_file)?)?;
+            let output = match output {
+                Some(output) => PathBuf::from(output),
+                None => Path::new(input_file).with_extension(""),
+            };
+            let context = match context.is_some() || !args.define.is_empty() {
+                true => Some(read_context(
+                    &args,
+                    context.as_deref(),
+                    &compiled_inputs(&program),
+                )?),
+                false => None,
+            };
+            match format {
+                ExportFormat::R1cs => export_r1cs(&program, context.as_ref(), &output),
+                ExportFormat::Circom => export_circom(&program, context.as_ref(), &output),
+            }
+        }
+        Some(Command::Witness {
+            ref input_file,
+            ref context,
+            ref output,
+        }) => {
+            let contents = read_file(input_file)?;
+            let source = parser::parse(&contents)?;
+            let program = compile_program(&args, &source)?;
+            let inputs = read_context(&args, context.as_deref(), &compiled_inputs(&program))?;
+            let witness = generate_witness(&source, &program, &inputs)?;
+            check_witness(&witness, program, &inputs)?;
+            let json = serde_json::to_string_pretty(&witness)?;
+            match output {
+                Some(path) => Ok(std::fs::write(path, json)?),
+                None => Ok(println!("{}", json)),
+            }
+        }
+        Some(Command::Run {
+            ref input_file,
+            ref options,
+        }) => run(&args, input_file, options),
+        Some(Command::Eval {
+            ref source,
+            ref context,
+        }) => {
+            // only the interpreter is needed, the program is not lowered to a circuit
+            let source = parser::parse(source)?;
+            let outputs: Vec<Ident> = source
+                .typecheck()?
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect();
+            let initial_context =
+                read_context(&args, context.as_deref(), &declared_inputs(&source))?;
+            for (name, value) in interpret_outputs(&source, &outputs, &initial_context)? {
+                println!("{} = {:?}", name, value);
+            }
+            Ok(())
+        }
+        Some(Command::Trace {
+            ref input_file,
+            ref context,
+            json,
+        }) => {
+            let contents = read_file(input_file)?;
+            let source = parser::parse(&contents)?;
+            source.typecheck()?;
+            let inputs = read_context(&args, context.as_deref(), &declared_inputs(&source))?;
+            let (trace, result) = Trace::of_program(&source, &inputs);
+            match json {
+                true => println!("{}", serde_json::to_string_pretty(&trace)?),
+                false => print!("{}", trace),
+            }
+            result
+        }
+        Some(Command::Debug {
+            ref input_file,
+            ref context,
+            ref breakpoints,
+        }) => {
+            let contents = read_file(input_file)?;
+            let source = parser::parse(&contents)?;
+            source.typecheck()?;
+            let inputs = read_context(&args, context.as_deref(), &declared_inputs(&source))?;
+            let stdin = io::stdin();
+            let mut debugger =
+                Debugger::new(&contents, &inputs, breakpoints, stdin.lock(), io::stdout());
+            for (name, value) in interpret_program_with(&source, &inputs, &mut debugger)? {
+                println!("{} = {:?}", name, value);
+            }
+            Ok(())
+        }
+        None => match args.input_file {
+            Some(ref input_file) => run(&args, input_file, &args.run),
+            None => Err(anyhow!(SourceError::NoInput)),
+        },
+    }
+}
+
+fn run(args: &Args, input_file: &str, options: &RunOptions) -> Result<()> {
+    let source = parser::parse(&read_file(input_file)?)?;
+    let program = compile_program(args, &source)?;
 
-    if args.serialize {
+    if let Some(Emit::Ir) = options.emit {
+        Ok(print!("{}", program.ir))
+    } else if options.serialize {
         let serialized = serde_json::to_string(&program)?;
         Ok(println!("{}", serialized))
     } else {
-        let initial_context = match args.context {
-            None => HashMap::new(),
-            Some(ref file_path) => read_context(&file_path).unwrap(),
-        };
+        let initial_context =
+            read_context(args, options.context.as_deref(), &compiled_inputs(&program))?;
 
-        let interpreter_result = {
-            let mut context = interpreter::Context::from(initial_context.clone());
-            interpreter::interpret(&mut context, &program.expr)
-        };
+        // the proof exposes the outputs in the order they were compiled
+        let outputs: Vec<Ident> = program
+            .outputs
+            .iter()
+            .map(|(name, _)| name.clone())
+            .collect();
+        let mut interpreter_results = Vec::new();
+        for (name, value) in interpret_outputs(&source, &outputs, &initial_context)? {
+            println!("According to the interpreter, {} is: {:?}", name, value);
+            interpreter_results.push(value.to_field());
+        }
 
-        println!(
-            "According to the interpreter, your result is: {:?}",
-            interpreter_result.unwrap()
-        );
+        match args.backend {
+            ProvingBackend::Plonky2 => {
+                let proof = prove_and_verify(
+                    Plonky2Backend::new(args.proof.config())?,
+                    initial_context,
+                    program,
+                    interpreter_results,
+                )?;
+                if let Some(ref path) = options.save_proof {
+                    let serialized = proof.serialize(pass_manager(args)?.names());
+                    std::fs::write(path, serde_json::to_string(&serialized)?)?;
+                    println!("Wrote the proof to {}", path);
+                }
+                Ok(())
+            }
+            ProvingBackend::Checker if options.save_proof.is_some() => {
+                Err(anyhow!("Only plonky2 proofs can be saved"))
+            }
+            ProvingBackend::Checker => prove_and_verify(
+                ConstraintChecker::default(),
+                initial_context,
+                program,
+                interpreter_results,
+            )
+            .map(|_| ()),
+        }
+    }
+}
 
-        let program_expr = program.expr.clone();
+// prove the program and check the outputs of the proof against those of the interpreter
+fn prove_and_verify<B: Backend>(
+    backend: B,
+    initial_context: HashMap<Ident, Literal>,
+    program: CompiledProgram<Span>,
+    interpreter_results: Vec<F>,
+) -> Result<B::Proof> {
+    let outputs = program.outputs.clone();
 
-        let proving_data = prove(initial_context, program).unwrap();
-        let proof = proving_data.data.prove(proving_data.pw).unwrap();
-        let formatted_input: String = proving_data
-            .inputs
-            .into_iter()
-            .zip(proof.public_inputs.clone().into_iter())
-            .map(|(a, b)| format!("{}={}", a, b))
-            .collect::<Vec<String>>()
-            .join(", ");
+    let proving_data = prove(backend, initial_context, program)?;
+    let public_values = B::public_values(&proving_data.proof);
+    let (public_inputs, public_outputs) = public_values.split_at(proving_data.inputs.len());
+    let formatted_input: String = proving_data
+        .inputs
+        .iter()
+        .zip(public_inputs.iter())
+        .map(|(a, b)| format!("{}={}", a, b))
+        .collect::<Vec<String>>()
+        .join(", ");
 
+    for (((name, expr), value), expected) in outputs
+        .iter()
+        .zip(public_outputs.iter())
+        .zip(interpreter_results)
+    {
+        if *value != expected {
+            return Err(anyhow!(
+                "Output {} is {} in the proof but {} according to the interpreter",
+                name,
+                value,
+                expected
+            ));
+        }
         println!(
-            "Proof for equation {} = {:?} (mod {:?}),  where {}",
-            program_expr.format(),
-            proof.public_inputs.last().unwrap(),
+            "Proof for equation {} = {} = {:?} (mod {:?}),  where {}",
+            name,
+            expr.format(),
+            value,
             F::order(),
             formatted_input
         );
-        proving_data.data.verify(proof).unwrap();
-        Ok(println!("Verified!"))
     }
+    B::verify(&proving_data.proof)?;
+    println!("Verified!");
+    Ok(proving_data.proof)
 }
 
 fn main() {
@@ -2,10 +2,10 @@ use pest_derive::Parser;
 
 use crate::ast::annotation::{from_pest_span, Span};
 use crate::ast::typechecker::Ty;
-use crate::ast::{Binder, Declaration, Expr, Ident, Literal, Opcode, Program, UOpcode};
+use crate::ast::{Binder, Builtin, Declaration, Expr, Ident, Literal, Opcode, Program, UOpcode};
 use anyhow::Result;
 use lazy_static::lazy_static;
-use pest::error::Error;
+use pest::error::{Error, ErrorVariant};
 use pest::iterators::{Pair, Pairs};
 use pest::pratt_parser::PrattParser;
 use pest::Parser;
@@ -20,8 +20,13 @@ lazy_static! {
         use Rule::*;
 
         PrattParser::new()
-            .op(Op::infix(add, Left) | Op::infix(sub, Left) | Op::infix(or, Left))
-            .op(Op::infix(mul, Left) | Op::infix(and, Left))
+            .op(Op::infix(add, Left)
+                | Op::infix(sub, Left)
+                | Op::infix(wrapping_add, Left)
+                | Op::infix(wrapping_sub, Left)
+                | Op::infix(or, Left))
+            .op(Op::infix(mul, Left) | Op::infix(wrapping_mul, Left) | Op::infix(and, Left))
+            .op(Op::postfix(cast) | Op::postfix(index))
             .op(Op::prefix(unary_minus))
             .op(Op::infix(pow, Right))
             .op(Op::infix(eq, Left))
@@ -37,6 +42,9 @@ fn infix_rule(lhs: Expr<Span>, pair: Pair<Rule>, rhs: Expr<Span>) -> Expr<Span>
         Rule::and => Opcode::And,
         Rule::or => Opcode::Or,
         Rule::eq => Opcode::Eq,
+        Rule::wrapping_add => Opcode::WrappingAdd,
+        Rule::wrapping_sub => Opcode::WrappingSub,
+        Rule::wrapping_mul => Opcode::WrappingMul,
         rule => unreachable!("Expr::parse expected infix operation, found {:?}", rule),
     };
     let ann = from_pest_span(pair.as_span());
@@ -51,13 +59,25 @@ fn infix_rule(lhs: Expr<Span>, pair: Pair<Rule>, rhs: Expr<Span>) -> Expr<Span>
 fn primary_rule(pair: Pair<Rule>) -> Expr<Span> {
     let ann = from_pest_span(pair.as_span());
     match pair.as_rule() {
+        // the literals are in range, `check_tokens` reports the others before the
+        // expressions are built
         Rule::integer => Expr::Literal {
             ann,
-            value: Literal::Field(pair.as_str().parse::<i32>().unwrap()),
+            value: pair
+                .as_str()
+                .parse::<i128>()
+                .ok()
+                .and_then(Literal::field)
+                .unwrap_or_else(|| unreachable!("Literal {} is out of range", pair.as_str())),
+        },
+        Rule::uint => Expr::Literal {
+            ann,
+            value: parse_uint(pair.as_str())
+                .unwrap_or_else(|| unreachable!("Literal {} is out of range", pair.as_str())),
         },
         Rule::bool => Expr::Literal {
             ann,
-            value: Literal::Boolean(pair.as_str().parse::<bool>().unwrap()),
+            value: Literal::Boolean(pair.as_str() == "true"),
         },
         Rule::identifier => Expr::Variable {
             ann,
@@ -75,11 +95,53 @@ fn primary_rule(pair: Pair<Rule>) -> Expr<Span> {
                 _else: Box::new(_else),
             }
         }
-        Rule::expression => parse_expr(pair.into_inner()),
+        Rule::parenthesized => {
+            let mut pairs = pair.into_inner();
+            let expr = parse_expr(pairs.next().expect("Expected expression").into_inner());
+            match pairs.peek() {
+                Some(_) => Expr::Ascription {
+                    ann,
+                    expr: Box::new(expr),
+                    _type: parse_type(pairs),
+                },
+                None => expr,
+            }
+        }
+        Rule::call => {
+            let mut pairs = pair.into_inner();
+            let name = pairs.next().expect("Expected builtin").as_str();
+            let func = Builtin::from_name(name)
+                .unwrap_or_else(|| unreachable!("Expr::parse expected builtin, found {}", name));
+            let args = pairs.map(|pair| parse_expr(pair.into_inner())).collect();
+            Expr::Call { ann, func, args }
+        }
         rule => unreachable!("Expr::parse expected atom, found {:?}", rule),
     }
 }
 
+fn postfix_rule(expr: Expr<Span>, pair: Pair<Rule>) -> Expr<Span> {
+    let ann = from_pest_span(pair.as_span());
+    match pair.as_rule() {
+        Rule::cast => Expr::Cast {
+            ann,
+            expr: Box::new(expr),
+            _type: parse_type(pair.into_inner()),
+        },
+        Rule::index => Expr::Index {
+            ann,
+            expr: Box::new(expr),
+            index: pair
+                .into_inner()
+                .next()
+                .expect("Expected index")
+                .as_str()
+                .parse::<usize>()
+                .expect("Index out of range"),
+        },
+        rule => unreachable!("Expr::parse expected postfix operation, found {:?}", rule),
+    }
+}
+
 fn prefix_rule(pair: Pair<Rule>, expr: Expr<Span>) -> Expr<Span> {
     let ann = from_pest_span(pair.as_span());
     match pair.as_rule() {
@@ -97,20 +159,27 @@ pub fn parse_expr(pairs: Pairs<Rule>) -> Expr<Span> {
         .map_primary(primary_rule)
         .map_infix(infix_rule)
         .map_prefix(prefix_rule)
+        .map_postfix(postfix_rule)
         .parse(pairs)
 }
 
 fn parse_assignment(pairs: Pair<Rule>) -> Declaration<Span> {
     match pairs.as_rule() {
-        Rule::assignment => {
+        rule @ (Rule::assignment | Rule::output) => {
             let mut pairs = pairs.into_inner();
             let name_pair = pairs.next().expect("Expected identifier");
-            let binder = Binder::VarBinder {
-                ann: from_pest_span(name_pair.as_span()),
-                var: Ident::new(name_pair.as_str()),
+            let binder = match name_pair.as_rule() {
+                Rule::typed_identifier => parse_typed_binder(name_pair),
+                _ => Binder::VarBinder {
+                    ann: from_pest_span(name_pair.as_span()),
+                    var: Ident::new(name_pair.as_str()),
+                },
             };
             let expr = parse_expr(pairs.next().expect("Expected expression").into_inner());
-            Declaration::VarAssignment { binder, expr }
+            match rule {
+                Rule::output => Declaration::Output { binder, expr },
+                _ => Declaration::VarAssignment { binder, expr },
+            }
         }
         rule => unreachable!("Declaration::parse expected assignment, found {:?}", rule),
     }
@@ -130,13 +199,28 @@ fn parse_public_var(pairs: Pair<Rule>) -> Declaration<Span> {
     }
 }
 
+fn parse_private_var(pairs: Pair<Rule>) -> Declaration<Span> {
+    match pairs.as_rule() {
+        Rule::private_var => {
+            let pairs = pairs
+                .into_inner()
+                .next()
+                .expect("expected typed_identifier");
+            let binder = parse_typed_binder(pairs);
+            Declaration::PrivateVar { binder }
+        }
+        rule => unreachable!("Declaration::parse expected private var, found {:?}", rule),
+    }
+}
+
 fn parse_typed_binder(pairs: Pair<Rule>) -> Binder<Span> {
     match pairs.as_rule() {
         Rule::typed_identifier => {
+            // the binder covers the whole `x: T` so that type errors can point at the annotation
+            let ann = from_pest_span(pairs.as_span());
             let mut pairs = pairs.into_inner();
             let name_pair = pairs.next().expect("Expected identifier");
             let name = Ident::new(name_pair.as_str());
-            let ann = from_pest_span(name_pair.as_span());
             let _type = parse_type(pairs);
             Binder::TypedBinder {
                 ann,
@@ -151,10 +235,23 @@ fn parse_typed_binder(pairs: Pair<Rule>) -> Binder<Span> {
 fn parse_type(pairs: Pairs<Rule>) -> Ty {
     let mut pairs = pairs;
     let pair = pairs.next().expect("Expected type");
-    match pair.as_str() {
-        "F" => Ty::Field,
-        "Bool" => Ty::Boolean,
-        a => panic!("Expected type, got {}", a),
+    let pair = pair.into_inner().next().expect("Expected type");
+    match pair.as_rule() {
+        Rule::array_ty => {
+            let mut pairs = pair.into_inner();
+            let elem_ty = parse_type(pairs.clone());
+            pairs.next();
+            let len = pairs
+                .next()
+                .expect("Expected array length")
+                .as_str()
+                .parse::<usize>()
+                .expect("Array length out of range");
+            Ty::Array(Box::new(elem_ty), len)
+        }
+        // `check_tokens` reports the unknown types before the types are built
+        _ => scalar_type(pair.as_str())
+            .unwrap_or_else(|| unreachable!("Unknown type {}", pair.as_str())),
     }
 }
 
@@ -166,7 +263,11 @@ fn parse_decls(pairs: &mut Pairs<Rule>) -> Vec<Declaration<Span>> {
                 declarations.push(parse_public_var(pair));
                 pairs.next();
             }
-            Rule::assignment => {
+            Rule::private_var => {
+                declarations.push(parse_private_var(pair));
+                pairs.next();
+            }
+            Rule::assignment | Rule::output => {
                 declarations.push(parse_assignment(pair));
                 pairs.next();
             }
@@ -176,17 +277,77 @@ fn parse_decls(pairs: &mut Pairs<Rule>) -> Vec<Declaration<Span>> {
     declarations
 }
 
+fn scalar_type(name: &str) -> Option<Ty> {
+    match name {
+        "F" => Some(Ty::Field),
+        "Bool" => Some(Ty::Boolean),
+        "u8" => Some(Ty::U8),
+        "u32" => Some(Ty::U32),
+        "u64" => Some(Ty::U64),
+        _ => None,
+    }
+}
+
+// an unsigned integer literal with its type suffix, `None` if it does not fit in its type
+fn parse_uint(s: &str) -> Option<Literal> {
+    let (n, suffix) = s.split_at(s.find('u')?);
+    match suffix {
+        "u8" => n.parse::<u8>().ok().map(Literal::U8),
+        "u32" => n.parse::<u32>().ok().map(Literal::U32),
+        "u64" => n.parse::<u64>().ok().map(Literal::U64),
+        _ => None,
+    }
+}
+
+// the grammar accepts literals of any length and any name as a type, the literals that don't
+// fit in their type and the unknown types are reported before the expressions are built
+#[allow(clippy::result_large_err)]
+fn check_tokens(pairs: &Pairs<Rule>) -> Result<(), Error<Rule>> {
+    for pair in pairs.clone().flatten() {
+        let message = match pair.as_rule() {
+            Rule::integer
+                if pair
+                    .as_str()
+                    .parse::<i128>()
+                    .ok()
+                    .and_then(Literal::field)
+                    .is_none() =>
+            {
+                format!("Literal {} is out of range", pair.as_str())
+            }
+            Rule::uint if parse_uint(pair.as_str()).is_none() => {
+                format!("Literal {} is out of range", pair.as_str())
+            }
+            Rule::scalar_ty if scalar_type(pair.as_str()).is_none() => {
+                format!("Unknown type {}", pair.as_str())
+            }
+            _ => continue,
+        };
+        return Err(Error::new_from_span(
+            ErrorVariant::CustomError { message },
+            pair.as_span(),
+        ));
+    }
+    Ok(())
+}
+
 pub fn parse(input: &str) -> Result<Program<Span>> {
     let mut pairs = CalcParser::parse(Rule::program, input)?;
+    check_tokens(&pairs)?;
     let decls_pair = pairs.next().unwrap();
     let decls = parse_decls(&mut decls_pair.into_inner());
-    let expr_pair = pairs.next().unwrap();
-    let expr = parse_expr(expr_pair.into_inner());
+    // the final expression is optional when the program declares outputs
+    let expr = pairs
+        .next()
+        .filter(|pair| pair.as_rule() == Rule::expression)
+        .map(|pair| parse_expr(pair.into_inner()));
     Program::new(decls, expr)
 }
 
+#[allow(clippy::result_large_err)]
 pub fn parse_single_expression(input: &str) -> Result<Expr<Span>, Error<Rule>> {
     let mut pairs = CalcParser::parse(Rule::expression, input)?;
+    check_tokens(&pairs)?;
     let pair = pairs.next().unwrap();
     Ok(parse_expr(pair.into_inner()))
 }
@@ -247,6 +408,174 @@ mod parser_tests {
         );
     }
 
+    #[test]
+    fn ascription_test() {
+        let input = "(x == 1 : Bool) && true";
+        let expr = parse_single_expression(input).unwrap().clear_annotations();
+        assert_eq!(
+            expr,
+            Expr::binary_op_default(
+                Expr::Ascription {
+                    ann: (),
+                    expr: Box::new(Expr::binary_op_default(
+                        Expr::variable_default(Ident::new("x")),
+                        Opcode::Eq,
+                        Expr::field_default(1)
+                    )),
+                    _type: Ty::Boolean,
+                },
+                Opcode::And,
+                Expr::bool_default(true)
+            )
+        );
+    }
+
+    #[test]
+    fn nested_parens_test() {
+        // each level is parsed once, trying an ascription first used to double the work
+        let input = format!("{}1{}", "(".repeat(100), ")".repeat(100));
+        let expr = parse_single_expression(&input).unwrap().clear_annotations();
+        assert_eq!(expr, Expr::field_default(1));
+    }
+
+    #[test]
+    fn uint_test() {
+        let input = "200u8 +% x as u8";
+        let expr = parse_single_expression(input).unwrap().clear_annotations();
+        assert_eq!(
+            expr,
+            Expr::binary_op_default(
+                Expr::Literal {
+                    ann: (),
+                    value: Literal::U8(200)
+                },
+                Opcode::WrappingAdd,
+                Expr::Cast {
+                    ann: (),
+                    expr: Box::new(Expr::variable_default(Ident::new("x"))),
+                    _type: Ty::U8,
+                }
+            )
+        );
+    }
+
+    #[test]
+    fn out_of_range_test() {
+        for input in [
+            "1 + 300u8",
+            "4294967296u32",
+            "18446744073709551616u64",
+            "18446744069414584321",
+        ] {
+            match parse_single_expression(input) {
+                Err(err) => assert!(
+                    err.to_string().contains("is out of range"),
+                    "{} for {}",
+                    err,
+                    input
+                ),
+                Ok(_) => panic!("Expected {} to be out of range", input),
+            }
+        }
+        let err = parse("pub x: u8;\nx + 256u8").unwrap_err().to_string();
+        assert!(err.contains("2:5"), "{}", err);
+        assert!(parse_single_expression("255u8 + 4294967295u32").is_ok());
+        assert!(parse_single_expression("18446744069414584320").is_ok());
+    }
+
+    #[test]
+    fn unknown_type_test() {
+        let err = parse("pub x: Foo;\nx").unwrap_err().to_string();
+        assert!(err.contains("Unknown type Foo"), "{}", err);
+        assert!(err.contains("1:8"), "{}", err);
+        for input in ["(x : [Bar; 2])", "x as u16"] {
+            match parse_single_expression(input) {
+                Err(err) => assert!(err.to_string().contains("Unknown type"), "{}", err),
+                Ok(_) => panic!("Expected an unknown type in {}", input),
+            }
+        }
+    }
+
+    #[test]
+    fn call_test() {
+        let input = "to_bits(x, 8)[3]";
+        let expr = parse_single_expression(input).unwrap().clear_annotations();
+        assert_eq!(
+            expr,
+            Expr::Index {
+                ann: (),
+                expr: Box::new(Expr::call_default(
+                    Builtin::ToBits,
+                    vec![
+                        Expr::variable_default(Ident::new("x")),
+                        Expr::field_default(8)
+                    ]
+                )),
+                index: 3,
+            }
+        );
+    }
+
+    #[test]
+    fn typed_assignment_test() {
+        let input = r#"
+            pub x: F;
+            let a: F = x + 1;
+            let b = (a : F);
+            b
+          "#;
+        let program = parse(input)
+            .expect("Expected end of program")
+            .clear_annotations();
+        assert_eq!(
+            program.decls[1],
+            Declaration::VarAssignment {
+                binder: Binder::default(Ident::new("a"), Some(Ty::Field)),
+                expr: Expr::binary_op_default(
+                    Expr::variable_default(Ident::new("x")),
+                    Opcode::Add,
+                    Expr::field_default(1)
+                ),
+            }
+        );
+    }
+
+    #[test]
+    fn private_var_test() {
+        let input = "pub root: F; priv path: [F; 3]; path[0]";
+        let program = parse(input).unwrap().clear_annotations();
+        assert_eq!(
+            program.decls,
+            vec![
+                Declaration::PublicVar {
+                    binder: Binder::default(Ident::new("root"), Some(Ty::Field)),
+                },
+                Declaration::PrivateVar {
+                    binder: Binder::default(
+                        Ident::new("path"),
+                        Some(Ty::Array(Box::new(Ty::Field), 3))
+                    ),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn output_test() {
+        let input = "pub x: F; out ok: Bool = x == 1; out total = x + 1;";
+        let program = parse(input).unwrap().clear_annotations();
+        assert_eq!(program.outputs, vec![Ident::new("ok"), Ident::new("total")]);
+        assert_eq!(program.expr, None);
+        assert!(program.decls.contains(&Declaration::Output {
+            binder: Binder::default(Ident::new("ok"), Some(Ty::Boolean)),
+            expr: Expr::binary_op_default(
+                Expr::variable_default(Ident::new("x")),
+                Opcode::Eq,
+                Expr::field_default(1),
+            ),
+        }));
+    }
+
     #[test]
     fn program_test() {
         let input = r#"
@@ -288,7 +617,7 @@ mod parser_tests {
                     ),
                 },
             ],
-            Expr::binary_op_default(
+            Some(Expr::binary_op_default(
                 Expr::binary_op_default(
                     Expr::variable_default(Ident::new("a")),
                     Opcode::Mul,
@@ -296,7 +625,7 @@ mod parser_tests {
                 ),
                 Opcode::Sub,
                 Expr::field_default(2),
-            ),
+            )),
         )
         .unwrap();
 
@@ -0,0 +1,230 @@
+use crate::ast::annotation::HasSourceLoc;
+use crate::ast::{fold_constants, inline, simplify, Expr, Ident, Program, TypeContext};
+use crate::ir::Circuit;
+use anyhow::{anyhow, Result};
+use err_derive::Error;
+
+#[derive(Debug, Error)]
+pub enum PassError {
+    #[error(display = "Unknown pass {}", _0)]
+    UnknownPass(String),
+    #[error(display = "Unknown optimization level {}", _0)]
+    UnknownLevel(u8),
+    #[error(display = "inline must be the first pass and run exactly once")]
+    MisplacedInline,
+    #[error(
+        display = "Pass {} runs on expressions and can't come after {}",
+        _0,
+        _1
+    )]
+    OutOfOrder(Pass, Pass),
+}
+
+// a transformation of the program. the expression passes run on the inlined outputs,
+// the circuit passes on the IR they are lowered to.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Pass {
+    Inline,
+    Fold,
+    Simplify,
+    Cse,
+    Dce,
+}
+
+impl Pass {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "inline" => Some(Pass::Inline),
+            "fold" => Some(Pass::Fold),
+            "simplify" => Some(Pass::Simplify),
+            "cse" => Some(Pass::Cse),
+            "dce" => Some(Pass::Dce),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Pass::Inline => "inline",
+            Pass::Fold => "fold",
+            Pass::Simplify => "simplify",
+            Pass::Cse => "cse",
+            Pass::Dce => "dce",
+        }
+    }
+
+    fn on_circuit(&self) -> bool {
+        matches!(self, Pass::Cse | Pass::Dce)
+    }
+}
+
+impl std::fmt::Display for Pass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+pub struct PassManager {
+    passes: Vec<Pass>,
+    // print the program after every pass, to bisect miscompilations
+    dump: bool,
+}
+
+impl Default for PassManager {
+    fn default() -> Self {
+        PassManager::from_level(2).unwrap()
+    }
+}
+
+impl PassManager {
+    // inlining is required to lower the outputs, so it always comes first
+    pub fn new(passes: Vec<Pass>) -> Result<Self> {
+        if passes.iter().position(|pass| *pass == Pass::Inline) != Some(0)
+            || passes.iter().filter(|pass| **pass == Pass::Inline).count() > 1
+        {
+            return Err(anyhow!(PassError::MisplacedInline));
+        }
+        for window in passes.windows(2) {
+            if window[0].on_circuit() && !window[1].on_circuit() {
+                return Err(anyhow!(PassError::OutOfOrder(window[1], window[0])));
+            }
+        }
+        Ok(PassManager {
+            passes,
+            dump: false,
+        })
+    }
+
+    pub fn from_level(level: u8) -> Result<Self> {
+        let passes = match level {
+            0 => vec![Pass::Inline],
+            1 => vec![Pass::Inline, Pass::Fold, Pass::Simplify],
+            2 => vec![
+                Pass::Inline,
+                Pass::Fold,
+                Pass::Simplify,
+                Pass::Cse,
+                Pass::Dce,
+            ],
+            level => return Err(anyhow!(PassError::UnknownLevel(level))),
+        };
+        PassManager::new(passes)
+    }
+
+    pub fn from_names(names: &[String]) -> Result<Self> {
+        let passes = names
+            .iter()
+            .map(|name| {
+                Pass::from_name(name).ok_or_else(|| anyhow!(PassError::UnknownPass(name.clone())))
+            })
+            .collect::<Result<Vec<Pass>>>()?;
+        PassManager::new(passes)
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.passes
+            .iter()
+            .map(|pass| pass.name().to_string())
+            .collect()
+    }
+
+    pub fn dump(self, dump: bool) -> Self {
+        PassManager { dump, ..self }
+    }
+
+    // run the expression passes, `context` holds the types of the inputs
+    pub fn run_on_program<A: Clone + HasSourceLoc>(
+        &self,
+        program: Program<A>,
+        context: &TypeContext,
+    ) -> Vec<(Ident, Expr<A>)> {
+        let mut outputs = inline(program);
+        self.dump_outputs(Pass::Inline, &outputs);
+        for pass in self.passes.iter().skip(1).filter(|pass| !pass.on_circuit()) {
+            outputs = outputs
+                .into_iter()
+                .map(|(name, expr)| {
+                    let expr = match pass {
+                        Pass::Fold => fold_constants(expr),
+                        Pass::Simplify => simplify(expr, context),
+                        pass => unreachable!("{} is not an expression pass", pass),
+                    };
+                    (name, expr)
+                })
+                .collect();
+            self.dump_outputs(*pass, &outputs);
+        }
+        outputs
+    }
+
+    pub fn run_on_circuit<A>(&self, mut circuit: Circuit<A>) -> Circuit<A> {
+        for pass in self.passes.iter().filter(|pass| pass.on_circuit()) {
+            circuit = match pass {
+                Pass::Cse => circuit.cse(),
+                Pass::Dce => circuit.dce(),
+                pass => unreachable!("{} is not a circuit pass", pass),
+            };
+            if self.dump {
+                eprint!("; after {}\n{}", pass, circuit);
+            }
+        }
+        circuit
+    }
+
+    fn dump_outputs<A>(&self, pass: Pass, outputs: &[(Ident, Expr<A>)]) {
+        if self.dump {
+            eprintln!("; after {}", pass);
+            for (name, expr) in outputs {
+                eprintln!("{} = {}", name, expr.format());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod passes_tests {
+    use super::*;
+    use crate::{compiler, parser};
+
+    #[test]
+    fn pass_order_test() {
+        let names =
+            |names: &[&str]| -> Vec<String> { names.iter().map(|name| name.to_string()).collect() };
+        assert!(PassManager::from_names(&names(&["inline", "fold", "cse"])).is_ok());
+        for passes in [&["fold", "inline"][..], &["inline", "fold", "inline"]] {
+            match PassManager::from_names(&names(passes)) {
+                Err(err) => match err.downcast_ref() {
+                    Some(PassError::MisplacedInline) => (),
+                    _ => panic!("Expected MisplacedInline error"),
+                },
+                _ => panic!("Expected MisplacedInline error"),
+            };
+        }
+        match PassManager::from_names(&names(&["inline", "dce", "simplify"])) {
+            Err(err) => match err.downcast_ref() {
+                Some(PassError::OutOfOrder(Pass::Simplify, Pass::Dce)) => (),
+                _ => panic!("Expected OutOfOrder error"),
+            },
+            _ => panic!("Expected OutOfOrder error"),
+        };
+        match PassManager::from_names(&names(&["inline", "unroll"])) {
+            Err(err) => match err.downcast_ref() {
+                Some(PassError::UnknownPass(_)) => (),
+                _ => panic!("Expected UnknownPass error"),
+            },
+            _ => panic!("Expected UnknownPass error"),
+        };
+    }
+
+    #[test]
+    fn levels_test() {
+        let input = "pub x: F; let y = (x + 1) * (x + 1); out a = y * 1; out b = y + 0;";
+        let compile = |level| {
+            let passes = PassManager::from_level(level).unwrap();
+            compiler::compile_with(parser::parse(input).unwrap(), &passes).unwrap()
+        };
+        // O0 computes `y` once per use, O2 shares it between the outputs
+        assert_eq!(compile(0).ir.instructions.len(), 15);
+        assert_eq!(compile(2).ir.instructions.len(), 4);
+    }
+}
@@ -0,0 +1,331 @@
+use crate::backend::Backend;
+use crate::plonk::config::{HashConfig, ProofConfig};
+use crate::plonk::parameters::*;
+use anyhow::{anyhow, Result};
+use plonky2::hash::poseidon::PoseidonHash;
+use plonky2::iop::target::{BoolTarget, Target};
+use plonky2::iop::witness::{PartialWitness, WitnessWrite};
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use plonky2::plonk::circuit_data::CircuitData;
+use plonky2::plonk::config::{GenericConfig, KeccakGoldilocksConfig, PoseidonGoldilocksConfig};
+use plonky2::plonk::proof::ProofWithPublicInputs;
+use serde::{Deserialize, Serialize};
+
+// booleans are kept as plain targets by the circuit builder, they are constrained to 0 or 1
+// wherever they are created
+fn bool_target(target: Target) -> BoolTarget {
+    BoolTarget::new_unsafe(target)
+}
+
+pub struct Plonky2Backend {
+    pub builder: CircuitBuilder<F, D>,
+    pub config: ProofConfig,
+}
+
+impl Plonky2Backend {
+    pub fn new(config: ProofConfig) -> Result<Self> {
+        Ok(Plonky2Backend {
+            builder: CircuitBuilder::new(config.circuit_config()?),
+            config,
+        })
+    }
+}
+
+impl Default for Plonky2Backend {
+    fn default() -> Self {
+        Plonky2Backend::new(ProofConfig::default()).unwrap()
+    }
+}
+
+pub struct ProofData<C: GenericConfig<D, F = F>> {
+    pub data: CircuitData<F, C, D>,
+    pub proof: ProofWithPublicInputs<F, C, D>,
+}
+
+impl<C: GenericConfig<D, F = F>> ProofData<C> {
+    fn prove(builder: CircuitBuilder<F, D>, inputs: Vec<(Target, F)>) -> Result<Self> {
+        let data = builder.build::<C>();
+        let mut pw = PartialWitness::new();
+        for (target, value) in inputs {
+            pw.set_target(target, value);
+        }
+        let proof = data.prove(pw)?;
+        Ok(ProofData { data, proof })
+    }
+
+    // the proof of the circuit being built, as written by `to_bytes`
+    fn load(builder: CircuitBuilder<F, D>, bytes: Vec<u8>) -> Result<Self> {
+        let data = builder.build::<C>();
+        let proof = ProofWithPublicInputs::from_bytes(bytes, &data.common)?;
+        Ok(ProofData { data, proof })
+    }
+
+    fn verify(&self) -> Result<()> {
+        self.data.verify(self.proof.clone())
+    }
+}
+
+// the hash of the config is a type parameter of plonky2, so there is one variant per hash
+pub enum HashedProof {
+    Poseidon(ProofData<PoseidonGoldilocksConfig>),
+    Keccak(ProofData<KeccakGoldilocksConfig>),
+}
+
+// a proof and the config it was made with, which is the one it is verified with
+pub struct Plonky2Proof {
+    pub config: ProofConfig,
+    pub proof: HashedProof,
+}
+
+// a proof as it is written to a file. The circuit data is not saved, it is rebuilt from the
+// program with the passes and the config the proof was made with.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SerializedProof {
+    pub passes: Vec<String>,
+    pub config: ProofConfig,
+    pub proof: Vec<u8>,
+}
+
+impl Plonky2Proof {
+    // `passes` are those the program was compiled with, they shape the circuit as well
+    pub fn serialize(&self, passes: Vec<String>) -> SerializedProof {
+        let proof = match &self.proof {
+            HashedProof::Poseidon(data) => data.proof.to_bytes(),
+            HashedProof::Keccak(data) => data.proof.to_bytes(),
+        };
+        SerializedProof {
+            passes,
+            config: self.config.clone(),
+            proof,
+        }
+    }
+}
+
+impl Plonky2Backend {
+    // read back a proof of the circuit built by the backend, which must have been created
+    // with the config of the proof
+    pub fn load(self, proof: Vec<u8>) -> Result<Plonky2Proof> {
+        let proof = match self.config.hash {
+            HashConfig::Poseidon => HashedProof::Poseidon(ProofData::load(self.builder, proof)?),
+            HashConfig::Keccak => HashedProof::Keccak(ProofData::load(self.builder, proof)?),
+        };
+        Ok(Plonky2Proof {
+            config: self.config,
+            proof,
+        })
+    }
+}
+
+impl Backend for Plonky2Backend {
+    type Target = Target;
+    type Proof = Plonky2Proof;
+
+    fn constant(&mut self, value: F) -> Target {
+        self.builder.constant(value)
+    }
+
+    fn input(&mut self) -> Target {
+        self.builder.add_virtual_target()
+    }
+
+    fn add(&mut self, a: Target, b: Target) -> Target {
+        self.builder.add(a, b)
+    }
+
+    fn sub(&mut self, a: Target, b: Target) -> Target {
+        self.builder.sub(a, b)
+    }
+
+    fn mul(&mut self, a: Target, b: Target) -> Target {
+        self.builder.mul(a, b)
+    }
+
+    fn select(&mut self, cond: Target, a: Target, b: Target) -> Target {
+        self.builder._if(bool_target(cond), a, b)
+    }
+
+    fn is_equal(&mut self, a: Target, b: Target) -> Target {
+        self.builder.is_equal(a, b).target
+    }
+
+    fn assert_equal(&mut self, a: Target, b: Target) {
+        self.builder.connect(a, b)
+    }
+
+    fn split_le(&mut self, a: Target, num_bits: usize) -> Vec<Target> {
+        let bits = self.builder.split_le(a, num_bits);
+        bits.into_iter().map(|bit| bit.target).collect()
+    }
+
+    fn hash(&mut self, inputs: Vec<Target>) -> [Target; 4] {
+        self.builder
+            .hash_n_to_hash_no_pad::<PoseidonHash>(inputs)
+            .elements
+    }
+
+    fn public_output(&mut self, a: Target) {
+        self.builder.register_public_input(a)
+    }
+
+    fn size(&self) -> usize {
+        self.builder.num_gates()
+    }
+
+    fn prove(self, inputs: Vec<(Target, F)>) -> Result<Plonky2Proof> {
+        let proof = match self.config.hash {
+            HashConfig::Poseidon => HashedProof::Poseidon(ProofData::prove(self.builder, inputs)?),
+            HashConfig::Keccak => HashedProof::Keccak(ProofData::prove(self.builder, inputs)?),
+        };
+        Ok(Plonky2Proof {
+            config: self.config,
+            proof,
+        })
+    }
+
+    fn verify(proof: &Plonky2Proof) -> Result<()> {
+        match (&proof.proof, proof.config.hash) {
+            (HashedProof::Poseidon(data), HashConfig::Poseidon) => data.verify(),
+            (HashedProof::Keccak(data), HashConfig::Keccak) => data.verify(),
+            (_, hash) => Err(anyhow!("Proof was not made with the {:?} hash", hash)),
+        }
+    }
+
+    fn public_values(proof: &Plonky2Proof) -> Vec<F> {
+        match &proof.proof {
+            HashedProof::Poseidon(data) => data.proof.public_inputs.clone(),
+            HashedProof::Keccak(data) => data.proof.public_inputs.clone(),
+        }
+    }
+
+    fn zero(&mut self) -> Target {
+        self.builder.zero()
+    }
+
+    fn one(&mut self) -> Target {
+        self.builder.one()
+    }
+
+    fn add_const(&mut self, a: Target, c: F) -> Target {
+        self.builder.add_const(a, c)
+    }
+
+    fn mul_const(&mut self, c: F, a: Target) -> Target {
+        self.builder.mul_const(c, a)
+    }
+
+    fn mul_const_add(&mut self, c: F, a: Target, b: Target) -> Target {
+        self.builder.mul_const_add(c, a, b)
+    }
+
+    fn add_many(&mut self, terms: Vec<Target>) -> Target {
+        self.builder.add_many(terms)
+    }
+
+    fn assert_zero(&mut self, a: Target) {
+        self.builder.assert_zero(a)
+    }
+
+    fn assert_one(&mut self, a: Target) {
+        self.builder.assert_one(a)
+    }
+
+    fn assert_bool(&mut self, a: Target) {
+        self.builder.assert_bool(bool_target(a))
+    }
+
+    fn and(&mut self, a: Target, b: Target) -> Target {
+        self.builder.and(bool_target(a), bool_target(b)).target
+    }
+
+    fn or(&mut self, a: Target, b: Target) -> Target {
+        self.builder.or(bool_target(a), bool_target(b)).target
+    }
+
+    fn not(&mut self, a: Target) -> Target {
+        self.builder.not(bool_target(a)).target
+    }
+
+    fn le_sum(&mut self, bits: &[Target]) -> Target {
+        self.builder
+            .le_sum(bits.iter().map(|bit| bool_target(*bit)))
+    }
+
+    fn range_check(&mut self, a: Target, num_bits: usize) {
+        self.builder.range_check(a, num_bits)
+    }
+
+    fn split_low_high(&mut self, a: Target, n: usize, num_bits: usize) -> (Target, Target) {
+        self.builder.split_low_high(a, n, num_bits)
+    }
+
+    fn exp_u64(&mut self, base: Target, exponent: u64) -> Target {
+        self.builder.exp_u64(base, exponent)
+    }
+
+    fn exp_from_bits(&mut self, base: Target, bits: &[Target]) -> Target {
+        self.builder
+            .exp_from_bits(base, bits.iter().map(|bit| bool_target(*bit)))
+    }
+}
+
+#[cfg(test)]
+mod backend_tests {
+    use super::*;
+    use plonky2::field::types::Field;
+
+    // the backend with a circuit proving the square of its input
+    fn square(config: ProofConfig) -> (Plonky2Backend, Target) {
+        let mut backend = Plonky2Backend::new(config).unwrap();
+        let x = backend.input();
+        let square = backend.mul(x, x);
+        backend.public_output(square);
+        (backend, x)
+    }
+
+    fn prove_square(config: ProofConfig) -> Plonky2Proof {
+        let (backend, x) = square(config);
+        backend.prove(vec![(x, F::from_canonical_u32(3))]).unwrap()
+    }
+
+    #[test]
+    fn config_test() {
+        let config = ProofConfig {
+            zero_knowledge: true,
+            hash: HashConfig::Keccak,
+            ..ProofConfig::default()
+        };
+        let proof = prove_square(config.clone());
+        assert!(matches!(proof.proof, HashedProof::Keccak(_)));
+        Plonky2Backend::verify(&proof).unwrap();
+        assert_eq!(
+            Plonky2Backend::public_values(&proof),
+            vec![F::from_canonical_u32(9)]
+        );
+    }
+
+    #[test]
+    fn serialize_test() {
+        let config = ProofConfig {
+            rate_bits: 1,
+            num_query_rounds: 84,
+            ..ProofConfig::default()
+        };
+        let serialized = prove_square(config).serialize(vec!["inline".to_string()]);
+        let serialized: SerializedProof =
+            serde_json::from_str(&serde_json::to_string(&serialized).unwrap()).unwrap();
+        // the circuit is rebuilt with the config saved with the proof
+        let (backend, _) = square(serialized.config.clone());
+        let proof = backend.load(serialized.proof.clone()).unwrap();
+        Plonky2Backend::verify(&proof).unwrap();
+        assert_eq!(
+            Plonky2Backend::public_values(&proof),
+            vec![F::from_canonical_u32(9)]
+        );
+        // and the proof does not hold for the circuit of another config
+        let (backend, _) = square(ProofConfig::default());
+        let loaded = backend.load(serialized.proof);
+        assert!(loaded
+            .and_then(|proof| Plonky2Backend::verify(&proof))
+            .is_err());
+    }
+}
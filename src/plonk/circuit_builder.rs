@@ -1,83 +1,356 @@
-use crate::ast::{Expr, Ident, Literal, Opcode, UOpcode};
+use crate::ast::{Builtin, Ident, Literal, Opcode, Ty, UOpcode};
+use crate::backend::Backend;
 use crate::compiler::CompiledProgram;
+use crate::ir::{Circuit, Instruction, Op, Wire};
 use crate::plonk::parameters::*;
+use crate::plonk::uint::UIntTarget;
 use plonky2::field::types::Field;
-use plonky2::iop::target::{BoolTarget, Target};
-use plonky2::plonk::circuit_builder::CircuitBuilder;
-use plonky2::plonk::circuit_data::CircuitConfig;
 use std::collections::HashMap;
+use std::fmt::Debug;
 
-fn interpret_literal_as_target(builder: &mut CircuitBuilder<F, D>, lit: Literal) -> Target {
-    builder.constant(from_literal(lit))
-}
-
-fn interpret_as_target<A>(
-    context: &mut HashMap<Ident, Target>,
-    builder: &mut CircuitBuilder<F, D>,
-    expr: Expr<A>,
-) -> Target {
-    match expr {
-        Expr::Literal { value, .. } => interpret_literal_as_target(builder, value),
-        Expr::Variable { value: ident, .. } => match context.get(&ident) {
-            Some(target) => *target,
-            None => {
-                let x = builder.add_virtual_target();
-                context.insert(ident, x);
-                x
+// the circuit representation of a value of each type
+#[derive(Debug, Clone)]
+pub enum CircuitValue<T> {
+    Field(T),
+    Boolean(T),
+    UInt(UIntTarget<T>),
+    Array(Vec<CircuitValue<T>>),
+}
+
+impl<T: Copy + Debug> CircuitValue<T> {
+    // a single field element standing for the value, e.g. to register it as a public input
+    pub fn to_field(&self, builder: &mut impl Backend<Target = T>) -> T {
+        match self {
+            CircuitValue::Field(target) => *target,
+            CircuitValue::Boolean(target) => *target,
+            CircuitValue::UInt(uint) => uint.to_field(builder),
+            CircuitValue::Array(_) => unreachable!("Arrays are not a single field element"),
+        }
+    }
+
+    // the targets holding the value, limb by limb for integers and element by element for arrays
+    pub fn targets(&self) -> Vec<T> {
+        match self {
+            CircuitValue::Field(target) | CircuitValue::Boolean(target) => vec![*target],
+            CircuitValue::UInt(uint) => uint.limbs.clone(),
+            CircuitValue::Array(values) => {
+                values.iter().flat_map(|value| value.targets()).collect()
             }
-        },
-        Expr::UnaryOp { op, expr, .. } => {
-            let expr = interpret_as_target(context, builder, *expr);
-            match op {
-                UOpcode::Neg => builder.mul_const(F::NEG_ONE, expr),
+        }
+    }
+
+    fn field(self) -> T {
+        match self {
+            CircuitValue::Field(target) => target,
+            value => unreachable!("Expected a field value, found {:?}", value),
+        }
+    }
+
+    fn boolean(self) -> T {
+        match self {
+            CircuitValue::Boolean(target) => target,
+            value => unreachable!("Expected a boolean value, found {:?}", value),
+        }
+    }
+
+    fn uint(self) -> UIntTarget<T> {
+        match self {
+            CircuitValue::UInt(uint) => uint,
+            value => unreachable!("Expected an unsigned integer value, found {:?}", value),
+        }
+    }
+}
+
+fn interpret_literal_as_target<B: Backend>(
+    builder: &mut B,
+    lit: Literal,
+) -> CircuitValue<B::Target> {
+    match lit {
+        Literal::Field(_) => CircuitValue::Field(builder.constant(from_literal(lit))),
+        Literal::Boolean(b) => CircuitValue::Boolean(builder.constant(from_bool(b))),
+        Literal::U8(n) => CircuitValue::UInt(UIntTarget::constant(builder, Ty::U8, n as u64)),
+        Literal::U32(n) => CircuitValue::UInt(UIntTarget::constant(builder, Ty::U32, n as u64)),
+        Literal::U64(n) => CircuitValue::UInt(UIntTarget::constant(builder, Ty::U64, n)),
+        Literal::Array(elems) => CircuitValue::Array(
+            elems
+                .into_iter()
+                .map(|elem| interpret_literal_as_target(builder, elem))
+                .collect(),
+        ),
+    }
+}
+
+// create the targets for an input of the given type, together with its value in the circuit.
+// arrays get one target per element.
+fn input_as_target<B: Backend>(
+    builder: &mut B,
+    ty: Ty,
+) -> (Vec<B::Target>, CircuitValue<B::Target>) {
+    if let Ty::Array(elem_ty, len) = ty {
+        let mut targets = Vec::new();
+        let mut values = Vec::with_capacity(len);
+        for _ in 0..len {
+            let (elem_targets, value) = input_as_target(builder, (*elem_ty).clone());
+            targets.extend(elem_targets);
+            values.push(value);
+        }
+        return (targets, CircuitValue::Array(values));
+    }
+    let target = builder.input();
+    let value = match ty {
+        Ty::Field => CircuitValue::Field(target),
+        Ty::Boolean => {
+            builder.assert_bool(target);
+            CircuitValue::Boolean(target)
+        }
+        ty => CircuitValue::UInt(UIntTarget::from_field(builder, ty, target)),
+    };
+    (vec![target], value)
+}
+
+fn cast_as_target<B: Backend>(
+    builder: &mut B,
+    value: CircuitValue<B::Target>,
+    ty: Ty,
+) -> CircuitValue<B::Target> {
+    match (value, ty) {
+        (CircuitValue::Field(target), Ty::Field) => CircuitValue::Field(target),
+        (CircuitValue::Field(target), ty) => {
+            CircuitValue::UInt(UIntTarget::from_field(builder, ty, target))
+        }
+        (CircuitValue::UInt(uint), Ty::Field) => CircuitValue::Field(uint.to_field(builder)),
+        (CircuitValue::UInt(uint), ty) => CircuitValue::UInt(uint.resize(builder, ty)),
+        (value, ty) => unreachable!("Cannot cast {:?} to {}", value, ty),
+    }
+}
+
+// select between two values of the same type, element-wise for arrays
+fn select_as_target<B: Backend>(
+    builder: &mut B,
+    cond: B::Target,
+    _then: CircuitValue<B::Target>,
+    _else: CircuitValue<B::Target>,
+) -> CircuitValue<B::Target> {
+    match (_then, _else) {
+        (CircuitValue::Field(_then), CircuitValue::Field(_else)) => {
+            CircuitValue::Field(builder.select(cond, _then, _else))
+        }
+        (CircuitValue::Boolean(_then), CircuitValue::Boolean(_else)) => {
+            CircuitValue::Boolean(builder.select(cond, _then, _else))
+        }
+        (CircuitValue::UInt(_then), CircuitValue::UInt(_else)) => {
+            CircuitValue::UInt(_then.select(builder, cond, &_else))
+        }
+        (CircuitValue::Array(_then), CircuitValue::Array(_else)) => CircuitValue::Array(
+            _then
+                .into_iter()
+                .zip(_else)
+                .map(|(_then, _else)| select_as_target(builder, cond, _then, _else))
+                .collect(),
+        ),
+        (_then, _else) => unreachable!("Branches {:?} and {:?} differ", _then, _else),
+    }
+}
+
+// decompose a number into `width` little-endian bits, the circuit is unsatisfiable if
+// the number does not fit.
+fn bits_as_target<B: Backend>(
+    builder: &mut B,
+    value: CircuitValue<B::Target>,
+    width: usize,
+) -> Vec<B::Target> {
+    match value {
+        // below 64 bits the decomposition can't wrap around the field order
+        CircuitValue::Field(target) if width < 64 => builder.split_le(target, width),
+        CircuitValue::Field(target) => {
+            UIntTarget::from_field(builder, Ty::U64, target).to_bits(builder)
+        }
+        CircuitValue::UInt(uint) => {
+            let mut bits = uint.to_bits(builder);
+            for bit in bits.iter().skip(width) {
+                builder.assert_zero(*bit);
             }
+            let zero = builder.zero();
+            bits.resize(width, zero);
+            bits
+        }
+        value => unreachable!("Cannot decompose {:?} into bits", value),
+    }
+}
+
+// a constant exponent is unrolled by square-and-multiply, an integer one is raised bit by bit.
+// the compiler rejects any other exponent.
+fn pow_as_target<B: Backend>(
+    builder: &mut B,
+    base: B::Target,
+    exponent: CircuitValue<B::Target>,
+    constant: &Option<Literal>,
+) -> B::Target {
+    match (constant, exponent) {
+        (Some(Literal::Field(n)), _) => builder.exp_u64(base, *n as u64),
+        (Some(Literal::U8(n)), _) => builder.exp_u64(base, *n as u64),
+        (Some(Literal::U32(n)), _) => builder.exp_u64(base, *n as u64),
+        (Some(Literal::U64(n)), _) => builder.exp_u64(base, *n),
+        (_, CircuitValue::UInt(uint)) => {
+            let bits = uint.to_bits(builder);
+            builder.exp_from_bits(base, &bits)
         }
-        Expr::BinOp { lhs, op, rhs, .. } => {
-            let lhs = interpret_as_target(context, builder, *lhs);
-            let rhs = interpret_as_target(context, builder, *rhs);
+        (_, exponent) => unreachable!("Exponent {:?} is not a constant", exponent),
+    }
+}
+
+// the nodes of the tree are digests, given by the targets of their elements
+fn merkle_verify_as_target<B: Backend>(
+    builder: &mut B,
+    root: Vec<B::Target>,
+    leaf: Vec<B::Target>,
+    index: CircuitValue<B::Target>,
+    path: Vec<Vec<B::Target>>,
+) -> B::Target {
+    let bits = bits_as_target(builder, index, path.len());
+    let mut node = leaf;
+    for (bit, sibling) in bits.into_iter().zip(path) {
+        // the index bit is set when `node` is the right child
+        let left: Vec<B::Target> = (0..node.len())
+            .map(|i| builder.select(bit, sibling[i], node[i]))
+            .collect();
+        let right: Vec<B::Target> = (0..node.len())
+            .map(|i| builder.select(bit, node[i], sibling[i]))
+            .collect();
+        node = builder.hash([left, right].concat()).to_vec();
+    }
+    let equal: Vec<B::Target> = node
+        .into_iter()
+        .zip(root)
+        .map(|(a, b)| builder.is_equal(a, b))
+        .collect();
+    equal[1..]
+        .iter()
+        .fold(equal[0], |acc, eq| builder.and(acc, *eq))
+}
+
+// the value of an instruction given the values of all the wires before it
+fn instruction_as_target<B: Backend>(
+    values: &[CircuitValue<B::Target>],
+    constants: &[Option<Literal>],
+    builder: &mut B,
+    ty: &Ty,
+    op: Op,
+) -> CircuitValue<B::Target> {
+    let value = |wire: Wire| values[wire.0].clone();
+    match op {
+        Op::BinOp(Opcode::Pow, lhs, rhs) => {
+            let base = value(lhs).field();
+            CircuitValue::Field(pow_as_target(builder, base, value(rhs), &constants[rhs.0]))
+        }
+        Op::Const(value) => interpret_literal_as_target(builder, value),
+        Op::Input(ident) => unreachable!("Input {} is created by build_circuit", ident),
+        Op::UnaryOp(op, wire) => {
+            let expr = value(wire).field();
             match op {
-                Opcode::Add => builder.add(lhs, rhs),
-                Opcode::Sub => builder.sub(lhs, rhs),
-                Opcode::Mul => builder.mul(lhs, rhs),
-                Opcode::Pow => builder.exp(lhs, rhs, 10),
-                Opcode::And => {
-                    builder
-                        .and(BoolTarget::new_unsafe(lhs), BoolTarget::new_unsafe(rhs))
-                        .target
-                }
-                Opcode::Or => {
-                    builder
-                        .or(BoolTarget::new_unsafe(lhs), BoolTarget::new_unsafe(rhs))
-                        .target
+                UOpcode::Neg => CircuitValue::Field(builder.mul_const(F::NEG_ONE, expr)),
+            }
+        }
+        Op::BinOp(op, lhs, rhs) => match (value(lhs), value(rhs)) {
+            (CircuitValue::UInt(lhs), CircuitValue::UInt(rhs)) => match op {
+                Opcode::Add => CircuitValue::UInt(lhs.add(builder, &rhs, true)),
+                Opcode::Sub => CircuitValue::UInt(lhs.sub(builder, &rhs, true)),
+                Opcode::Mul => CircuitValue::UInt(lhs.mul(builder, &rhs, true)),
+                Opcode::WrappingAdd => CircuitValue::UInt(lhs.add(builder, &rhs, false)),
+                Opcode::WrappingSub => CircuitValue::UInt(lhs.sub(builder, &rhs, false)),
+                Opcode::WrappingMul => CircuitValue::UInt(lhs.mul(builder, &rhs, false)),
+                Opcode::Eq => CircuitValue::Boolean(lhs.is_equal(builder, &rhs)),
+                op => unreachable!("{:?} is not defined on unsigned integers", op),
+            },
+            (CircuitValue::Boolean(lhs), CircuitValue::Boolean(rhs)) => match op {
+                Opcode::And => CircuitValue::Boolean(builder.and(lhs, rhs)),
+                Opcode::Or => CircuitValue::Boolean(builder.or(lhs, rhs)),
+                op => unreachable!("{:?} is not defined on booleans", op),
+            },
+            (lhs, rhs) => {
+                let lhs = lhs.field();
+                let rhs = rhs.field();
+                match op {
+                    Opcode::Add => CircuitValue::Field(builder.add(lhs, rhs)),
+                    Opcode::Sub => CircuitValue::Field(builder.sub(lhs, rhs)),
+                    Opcode::Mul => CircuitValue::Field(builder.mul(lhs, rhs)),
+                    Opcode::Eq => CircuitValue::Boolean(builder.is_equal(lhs, rhs)),
+                    op => unreachable!("{:?} is not defined on fields", op),
                 }
-                Opcode::Eq => builder.is_equal(lhs, rhs).target,
             }
+        },
+        Op::Select(cond, _then, _else) => {
+            let cond = value(cond).boolean();
+            select_as_target(builder, cond, value(_then), value(_else))
         }
-        Expr::IfThenElse {
-            cond, _then, _else, ..
-        } => {
-            let cond = BoolTarget::new_unsafe(interpret_as_target(context, builder, *cond));
-            let _then = interpret_as_target(context, builder, *_then);
-            let _else = interpret_as_target(context, builder, *_else);
-            builder._if(cond, _then, _else)
+        Op::Cast(wire) => cast_as_target(builder, value(wire), ty.clone()),
+        Op::ToBits(wire, width) => {
+            let bits = bits_as_target(builder, value(wire), width);
+            CircuitValue::Array(bits.into_iter().map(CircuitValue::Boolean).collect())
         }
+        // arrays of fields are hashed element by element, like in the interpreter
+        Op::Call(Builtin::Poseidon, args) => {
+            let inputs = args
+                .into_iter()
+                .flat_map(|arg| value(arg).targets())
+                .collect();
+            let digest = builder.hash(inputs);
+            CircuitValue::Array(digest.into_iter().map(CircuitValue::Field).collect())
+        }
+        Op::Call(Builtin::MerkleVerify, args) => {
+            let [root, leaf, index, path]: [Wire; 4] =
+                args.try_into().expect("merkle_verify expects 4 arguments");
+            let path = match value(path) {
+                CircuitValue::Array(path) => path.iter().map(|node| node.targets()).collect(),
+                value => unreachable!("Expected an array, found {:?}", value),
+            };
+            let root = value(root).targets();
+            let leaf = value(leaf).targets();
+            let index = value(index);
+            CircuitValue::Boolean(merkle_verify_as_target(builder, root, leaf, index, path))
+        }
+        Op::Call(func, args) => {
+            let lhs = value(args[0]).uint();
+            let rhs = value(args[1]).uint();
+            CircuitValue::UInt(match func {
+                Builtin::Shl => lhs.shift(builder, &rhs, true),
+                Builtin::Shr => lhs.shift(builder, &rhs, false),
+                func => lhs.bitwise(builder, &rhs, func),
+            })
+        }
+        Op::Index(wire, index) => match value(wire) {
+            CircuitValue::Array(mut values) => values.swap_remove(index),
+            value => unreachable!("Expected an array, found {:?}", value),
+        },
     }
 }
 
 pub fn from_literal(lit: Literal) -> F {
     match lit {
-        Literal::Field(n) => from_i32(n),
+        Literal::Field(n) => from_i128(n),
         Literal::Boolean(b) => from_bool(b),
+        Literal::U8(n) => F::from_canonical_u8(n),
+        Literal::U32(n) => F::from_canonical_u32(n),
+        Literal::U64(n) => F::from_noncanonical_u64(n),
+        Literal::Array(_) => unreachable!("Arrays are not a single field element"),
     }
 }
 
-fn from_i32(n: i32) -> F {
+// the field elements making up an input, in the order of the targets from `input_as_target`
+pub fn flatten_literal(lit: Literal) -> Vec<F> {
+    match lit {
+        Literal::Array(elems) => elems.into_iter().flat_map(flatten_literal).collect(),
+        lit => vec![from_literal(lit)],
+    }
+}
+
+// field literals are strictly between minus the order and the order
+fn from_i128(n: i128) -> F {
     let sign = if n < 0 { F::NEG_ONE } else { F::ONE };
-    let n = n.abs() as u32;
-    sign * F::from_canonical_u32(n)
+    sign * F::from_canonical_u64(n.unsigned_abs() as u64)
 }
 
-fn from_bool(b: bool) -> F {
+pub fn from_bool(b: bool) -> F {
     if b {
         F::ONE
     } else {
@@ -85,26 +358,264 @@ fn from_bool(b: bool) -> F {
     }
 }
 
-pub struct ProvableCircuit {
-    pub public_inputs: HashMap<Ident, Target>,
-    pub output: Target,
-    pub builder: CircuitBuilder<F, D>,
+pub struct ProvableCircuit<B: Backend> {
+    pub public_inputs: HashMap<Ident, Vec<B::Target>>,
+    pub private_inputs: HashMap<Ident, Vec<B::Target>>,
+    // the targets of the outputs, registered as public inputs after the inputs
+    pub outputs: Vec<(Ident, B::Target)>,
+    // the size added by each instruction of the IR, in the unit of the backend
+    pub instruction_rows: Vec<usize>,
+    // the value of every wire of the IR in the circuit
+    pub wires: Vec<CircuitValue<B::Target>>,
+    pub backend: B,
 }
 
-pub fn build_circuit<A>(program: CompiledProgram<A>) -> ProvableCircuit {
-    let config = CircuitConfig::standard_recursion_config();
-    let mut builder: CircuitBuilder<F, D> = CircuitBuilder::new(config);
+pub fn build_circuit<A, B: Backend>(
+    program: CompiledProgram<A>,
+    mut backend: B,
+) -> ProvableCircuit<B> {
     let mut public_inputs = HashMap::new();
-    let output = interpret_as_target(&mut public_inputs, &mut builder, program.expr);
+    let mut private_inputs = HashMap::new();
+    let Circuit {
+        public_inputs: public_vars,
+        instructions,
+        outputs,
+        ..
+    } = program.ir;
+    let mut values: Vec<CircuitValue<B::Target>> = Vec::with_capacity(instructions.len());
+    let mut instruction_rows = Vec::with_capacity(instructions.len());
+    // the literals behind the wires, for the operations that unroll on a constant
+    let constants: Vec<Option<Literal>> = instructions
+        .iter()
+        .map(|instruction| match &instruction.op {
+            Op::Const(lit) => Some(lit.clone()),
+            _ => None,
+        })
+        .collect();
+    for Instruction { ty, op, .. } in instructions {
+        let size = backend.size();
+        let value = match op {
+            Op::Input(ident) => {
+                let (targets, value) = input_as_target(&mut backend, ty);
+                if public_vars.iter().any(|(var, _)| var == &ident) {
+                    public_inputs.insert(ident, targets);
+                } else {
+                    private_inputs.insert(ident, targets);
+                }
+                value
+            }
+            op => instruction_as_target(&values, &constants, &mut backend, &ty, op),
+        };
+        values.push(value);
+        instruction_rows.push(backend.size() - size);
+    }
+    let outputs: Vec<(Ident, B::Target)> = outputs
+        .into_iter()
+        .map(|(name, wire)| {
+            // exposing an integer packs its limbs, which is charged to the instruction
+            let size = backend.size();
+            let target = values[wire.0].to_field(&mut backend);
+            instruction_rows[wire.0] += backend.size() - size;
+            (name, target)
+        })
+        .collect();
 
-    for ident in program.public_vars {
-        let target = public_inputs.get(&ident).unwrap().clone();
-        builder.register_public_input(target);
+    // array inputs are registered element by element
+    for (ident, _) in public_vars {
+        for target in &public_inputs[&ident] {
+            backend.public_output(*target);
+        }
+    }
+    for (_, target) in &outputs {
+        backend.public_output(*target);
     }
-    builder.register_public_input(output);
     ProvableCircuit {
         public_inputs,
-        output,
-        builder,
+        private_inputs,
+        outputs,
+        instruction_rows,
+        wires: values,
+        backend,
+    }
+}
+
+#[cfg(test)]
+mod circuit_builder_tests {
+    use super::*;
+    use crate::backend::ConstraintChecker;
+    use crate::interpreter::{interpret, Context};
+    use crate::passes::PassManager;
+    use crate::plonk::backend::Plonky2Backend;
+    use crate::plonk::prove;
+    use crate::{compiler, parser};
+    use plonky2::hash::poseidon::PoseidonHash;
+
+    // the public values of a proof of the program, and the outputs computed by the interpreter
+    fn prove_with<B: Backend>(
+        backend: B,
+        input: &str,
+        context: &HashMap<Ident, Literal>,
+    ) -> (Vec<F>, Vec<F>) {
+        let program = compiler::compile(parser::parse(input).unwrap()).unwrap();
+        let expected: Vec<F> = program
+            .outputs
+            .iter()
+            .map(|(_, expr)| {
+                interpret(&mut Context::from(context.clone()), expr)
+                    .unwrap()
+                    .to_field()
+            })
+            .collect();
+        let proving_data = prove(backend, context.clone(), program).unwrap();
+        B::verify(&proving_data.proof).unwrap();
+        (B::public_values(&proving_data.proof), expected)
+    }
+
+    // prove the program with every backend and check that the outputs agree with the
+    // interpreter, returning the value of the last one
+    fn check_against_interpreter(input: &str, inputs: Vec<(&str, Literal)>) -> F {
+        let context: HashMap<Ident, Literal> = inputs
+            .into_iter()
+            .map(|(name, value)| (Ident::new(name), value))
+            .collect();
+        let (public_values, expected) = prove_with(Plonky2Backend::default(), input, &context);
+        let num_inputs = public_values.len() - expected.len();
+        assert_eq!(public_values[num_inputs..], expected);
+        let (checked_values, _) = prove_with(ConstraintChecker::default(), input, &context);
+        assert_eq!(checked_values, public_values);
+        *expected.last().unwrap()
+    }
+
+    #[test]
+    fn outputs_test() {
+        let inputs = vec![("x", Literal::Field(3)), ("y", Literal::U8(200))];
+        let program = "pub x: F; pub y: u8; out sum = x + y as F; out wrapped: u8 = y +% 100u8; \
+                       out parity = to_bits(y, 8)[0];";
+        assert_eq!(check_against_interpreter(program, inputs.clone()), F::ZERO);
+        let program = "pub x: F; pub y: u8; out double = x * 2; double + 1";
+        assert_eq!(
+            check_against_interpreter(program, inputs),
+            F::from_canonical_u32(7)
+        );
+    }
+
+    #[test]
+    fn poseidon_test() {
+        let inputs = vec![("x", Literal::Field(3)), ("y", Literal::Field(-4))];
+        let program = "pub x: F; pub y: F; let h = poseidon(x, y); \
+                       out h0 = h[0]; out h1 = h[1]; out h2 = h[2]; out h3 = h[3];";
+        check_against_interpreter(program, inputs.clone());
+        check_against_interpreter("pub x: F; pub y: F; poseidon(x)[0] * y", inputs.clone());
+        check_against_interpreter(
+            "pub x: F; pub y: F; poseidon(poseidon(x, y), 1)[3] == (poseidon(x)[3])",
+            inputs,
+        );
+    }
+
+    #[test]
+    fn merkle_verify_test() {
+        use plonky2::field::types::PrimeField64;
+        use plonky2::plonk::config::Hasher;
+        let hash = |inputs: &[F]| PoseidonHash::hash_no_pad(inputs).elements;
+        let digest = |n: u32| hash(&[F::from_canonical_u32(n)]);
+        // the leaf sits at index 2 = 0b10, so it is a left child and then a right child
+        let leaf = digest(5);
+        let path = [digest(7), digest(11)];
+        let root = hash(&[path[1], hash(&[leaf, path[0]].concat())].concat());
+        let program = "pub root: [F; 4]; priv leaf: F; priv index: u8; priv path: [[F; 4]; 2]; \
+                       merkle_verify(root, poseidon(leaf), index, path)";
+        let literal = |digest: [F; 4]| {
+            Literal::Array(
+                digest
+                    .iter()
+                    .map(|n| Literal::Field(n.to_canonical_u64() as i128))
+                    .collect(),
+            )
+        };
+        let inputs = |index| {
+            vec![
+                ("root", literal(root)),
+                ("leaf", Literal::Field(5)),
+                ("index", Literal::U8(index)),
+                ("path", Literal::Array(path.map(literal).to_vec())),
+            ]
+        };
+        assert_eq!(check_against_interpreter(program, inputs(2)), F::ONE);
+        assert_eq!(check_against_interpreter(program, inputs(1)), F::ZERO);
+    }
+
+    #[test]
+    fn untaken_branch_test() {
+        // the overflow in the branch that is not taken doesn't make the circuit unsatisfiable
+        let program = "pub c: Bool; pub x: u8; if c then x + 200u8 else 0u8";
+        let inputs = |c| vec![("c", Literal::Boolean(c)), ("x", Literal::U8(100))];
+        assert_eq!(check_against_interpreter(program, inputs(false)), F::ZERO);
+        let nested = "pub c: Bool; pub x: u8; \
+                      if c then 0u8 else (if x == 100u8 then x as u8 else (x * 3u8) as u8)";
+        assert_eq!(
+            check_against_interpreter(nested, inputs(false)),
+            F::from_canonical_u32(100)
+        );
+        let cast = "pub c: Bool; pub x: u8; priv y: F; \
+                    if c then to_bits(y, 4)[0] else y as u8 == x";
+        let cast_inputs = vec![
+            ("c", Literal::Boolean(false)),
+            ("x", Literal::U8(100)),
+            ("y", Literal::Field(100)),
+        ];
+        assert_eq!(check_against_interpreter(cast, cast_inputs), F::ONE);
+        // both sides still reject the taken branch
+        let context: HashMap<Ident, Literal> = inputs(true)
+            .into_iter()
+            .map(|(name, value)| (Ident::new(name), value))
+            .collect();
+        let compiled = compiler::compile(parser::parse(program).unwrap()).unwrap();
+        let (_, expr) = &compiled.outputs[0];
+        assert!(interpret(&mut Context::from(context.clone()), expr).is_err());
+        assert!(prove(ConstraintChecker::default(), context, compiled).is_err());
+    }
+
+    #[test]
+    fn unknown_input_test() {
+        let program = compiler::compile(parser::parse("pub x: F; x + 1").unwrap()).unwrap();
+        let context = HashMap::from([
+            (Ident::new("x"), Literal::Field(1)),
+            (Ident::new("y"), Literal::Field(2)),
+        ]);
+        match prove(ConstraintChecker::default(), context, program) {
+            Err(err) => assert_eq!(err.to_string(), "y is not an input of the program"),
+            Ok(_) => panic!("Expected an error for the unknown input"),
+        }
+    }
+
+    #[test]
+    fn pow_test() {
+        let inputs = vec![("x", Literal::Field(3)), ("n", Literal::U32(1500))];
+        // exponents past 10 bits used to be truncated
+        let expected = F::from_canonical_u32(3).exp_u64(1500);
+        assert_eq!(
+            check_against_interpreter("pub x: F; pub n: u32; x ^ 1500", inputs.clone()),
+            expected
+        );
+        assert_eq!(
+            check_against_interpreter("pub x: F; pub n: u32; x ^ n", inputs.clone()),
+            expected
+        );
+        check_against_interpreter("pub x: F; pub n: u32; x ^ 0 + x ^ 7u8", inputs);
+        // constant exponents are folded whichever passes run
+        for names in [&["inline"][..], &["inline", "cse"]] {
+            let names: Vec<String> = names.iter().map(|name| name.to_string()).collect();
+            let passes = PassManager::from_names(&names).unwrap();
+            let program = parser::parse("pub x: F; x ^ (1 + 1)").unwrap();
+            assert!(compiler::compile_with(program, &passes).is_ok());
+        }
+        let program = parser::parse("pub x: F; pub y: F; x ^ y").unwrap();
+        match compiler::compile(program) {
+            Err(err) => match err.downcast_ref() {
+                Some(compiler::CompilerError::InvalidExponent(_)) => (),
+                _ => panic!("Expected InvalidExponent error"),
+            },
+            _ => panic!("Expected InvalidExponent error"),
+        };
     }
 }
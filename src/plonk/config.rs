@@ -0,0 +1,136 @@
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use err_derive::Error;
+use plonky2::plonk::circuit_data::CircuitConfig;
+use serde::{Deserialize, Serialize};
+
+// the security the standard configs target, in bits
+const SECURITY_BITS: usize = 100;
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error(
+        display = "FRI parameters give {} bits of security, at least {} are required",
+        _0,
+        _1
+    )]
+    InsufficientSecurity(usize, usize),
+    #[error(display = "{} routed wires do not fit in {} wires", _0, _1)]
+    TooManyRoutedWires(usize, usize),
+    #[error(display = "The rate must be at least 1 bit")]
+    InvalidRate,
+}
+
+// the hash used for the Merkle trees and the Fiat-Shamir transcript of a proof
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+pub enum HashConfig {
+    // cheap to verify in another plonky2 circuit
+    Poseidon,
+    // cheap to verify on the EVM, but the proof can't be verified recursively
+    Keccak,
+}
+
+// the options a plonky2 proof is made with. They are kept with the proof so that it is
+// verified with the same ones.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProofConfig {
+    // blind the witness polynomials so that the proof reveals nothing about private inputs
+    pub zero_knowledge: bool,
+    pub hash: HashConfig,
+    // the FRI blowup factor is 2^rate_bits
+    pub rate_bits: usize,
+    pub num_query_rounds: usize,
+    pub proof_of_work_bits: u32,
+    pub num_wires: usize,
+    pub num_routed_wires: usize,
+}
+
+impl Default for ProofConfig {
+    fn default() -> Self {
+        let config = CircuitConfig::standard_recursion_config();
+        ProofConfig {
+            zero_knowledge: config.zero_knowledge,
+            hash: HashConfig::Poseidon,
+            rate_bits: config.fri_config.rate_bits,
+            num_query_rounds: config.fri_config.num_query_rounds,
+            proof_of_work_bits: config.fri_config.proof_of_work_bits,
+            num_wires: config.num_wires,
+            num_routed_wires: config.num_routed_wires,
+        }
+    }
+}
+
+impl ProofConfig {
+    // the conjectured security of FRI with these parameters
+    pub fn security_bits(&self) -> usize {
+        self.rate_bits * self.num_query_rounds + self.proof_of_work_bits as usize
+    }
+
+    pub fn circuit_config(&self) -> Result<CircuitConfig> {
+        if self.rate_bits == 0 {
+            return Err(anyhow!(ConfigError::InvalidRate));
+        }
+        if self.security_bits() < SECURITY_BITS {
+            return Err(anyhow!(ConfigError::InsufficientSecurity(
+                self.security_bits(),
+                SECURITY_BITS
+            )));
+        }
+        if self.num_routed_wires > self.num_wires {
+            return Err(anyhow!(ConfigError::TooManyRoutedWires(
+                self.num_routed_wires,
+                self.num_wires
+            )));
+        }
+        let mut config = match self.zero_knowledge {
+            true => CircuitConfig::standard_recursion_zk_config(),
+            false => CircuitConfig::standard_recursion_config(),
+        };
+        config.num_wires = self.num_wires;
+        config.num_routed_wires = self.num_routed_wires;
+        config.fri_config.rate_bits = self.rate_bits;
+        config.fri_config.num_query_rounds = self.num_query_rounds;
+        config.fri_config.proof_of_work_bits = self.proof_of_work_bits;
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod config_tests {
+    use super::*;
+
+    #[test]
+    fn circuit_config_test() {
+        let config = ProofConfig::default();
+        assert_eq!(
+            config.circuit_config().unwrap(),
+            CircuitConfig::standard_recursion_config()
+        );
+        let zk = ProofConfig {
+            zero_knowledge: true,
+            ..ProofConfig::default()
+        };
+        assert_eq!(
+            zk.circuit_config().unwrap(),
+            CircuitConfig::standard_recursion_zk_config()
+        );
+        // a faster prover with a larger proof keeps the same security
+        let fast = ProofConfig {
+            rate_bits: 1,
+            num_query_rounds: 84,
+            ..ProofConfig::default()
+        };
+        assert_eq!(fast.circuit_config().unwrap().fri_config.rate_bits, 1);
+        let weak = ProofConfig {
+            num_query_rounds: 10,
+            ..ProofConfig::default()
+        };
+        match weak.circuit_config() {
+            Err(err) => match err.downcast_ref() {
+                Some(ConfigError::InsufficientSecurity(46, 100)) => (),
+                _ => panic!("Expected InsufficientSecurity error"),
+            },
+            _ => panic!("Expected InsufficientSecurity error"),
+        };
+    }
+}
@@ -1,7 +1,14 @@
+pub mod backend;
 pub mod circuit_builder;
+pub mod config;
 pub mod parameters;
+pub mod profile;
 pub mod prove;
+pub mod stats;
+pub mod uint;
 
+pub use backend::Plonky2Backend;
 pub use circuit_builder::{build_circuit, ProvableCircuit};
+pub use config::{HashConfig, ProofConfig};
 pub use parameters::{C, D, F};
 pub use prove::prove;
@@ -0,0 +1,132 @@
+use crate::ast::annotation::HasSourceLoc;
+use crate::backend::Backend;
+use crate::compiler::CompiledProgram;
+use crate::ir::Op;
+use crate::plonk::backend::Plonky2Backend;
+use crate::plonk::circuit_builder::build_circuit;
+use std::collections::{BTreeMap, HashMap};
+
+// the gates of a circuit attributed to the source lines they come from
+pub struct Profile {
+    // gates per source line, in line order
+    pub lines: BTreeMap<u32, usize>,
+    // gates per `output;line;operation` stack, in the folded format of flamegraph tools
+    pub stacks: BTreeMap<String, usize>,
+    // gates added when building the circuit (constants, public inputs), not tied to a line
+    pub unattributed: usize,
+    pub total: usize,
+}
+
+pub fn profile<A: Clone + HasSourceLoc>(program: CompiledProgram<A>) -> Profile {
+    let ir = program.ir.clone();
+    // charge every instruction to the first output that depends on it
+    let mut roots: Vec<Option<String>> = vec![None; ir.instructions.len()];
+    for (name, wire) in &ir.outputs {
+        let mut stack = vec![*wire];
+        while let Some(wire) = stack.pop() {
+            if roots[wire.0].is_none() {
+                roots[wire.0] = Some(name.to_string());
+                stack.extend(ir.instructions[wire.0].op.operands());
+            }
+        }
+    }
+    let circuit = build_circuit(program, Plonky2Backend::default());
+    let mut lines = BTreeMap::new();
+    let mut stacks = BTreeMap::new();
+    for ((instruction, root), rows) in ir
+        .instructions
+        .iter()
+        .zip(roots)
+        .zip(circuit.instruction_rows)
+    {
+        if rows == 0 {
+            continue;
+        }
+        let line = instruction.ann.source_loc().line();
+        let root = match (&instruction.op, root) {
+            (Op::Input(_), _) => "inputs".to_string(),
+            (_, Some(root)) => root,
+            (_, None) => "unused".to_string(),
+        };
+        *lines.entry(line).or_insert(0) += rows;
+        let stack = format!("{};line {};{}", root, line, instruction.op.name());
+        *stacks.entry(stack).or_insert(0) += rows;
+    }
+    let total = circuit.backend.size();
+    let attributed: usize = lines.values().sum();
+    Profile {
+        lines,
+        stacks,
+        unattributed: total - attributed,
+        total,
+    }
+}
+
+impl Profile {
+    // one row per source line with its gates, followed by the line itself
+    pub fn format_text(&self, source: &str) -> String {
+        let source: HashMap<u32, &str> = source
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i as u32 + 1, line.trim()))
+            .collect();
+        let percent = |rows: usize| 100.0 * rows as f64 / self.total.max(1) as f64;
+        let mut text = format!("{:>6} {:>8} {:>6}  source\n", "line", "gates", "%");
+        for (line, rows) in &self.lines {
+            let code = source.get(line).copied().unwrap_or("");
+            text += &format!(
+                "{:>6} {:>8} {:>5.1}%  {}\n",
+                line,
+                rows,
+                percent(*rows),
+                code
+            );
+        }
+        text += &format!(
+            "{:>6} {:>8} {:>5.1}%  (constants and public inputs)\n",
+            "-",
+            self.unattributed,
+            percent(self.unattributed)
+        );
+        text += &format!("{:>6} {:>8}\n", "total", self.total);
+        text
+    }
+
+    pub fn format_folded(&self) -> String {
+        let mut folded = String::new();
+        for (stack, rows) in &self.stacks {
+            folded += &format!("{} {}\n", stack, rows);
+        }
+        if self.unattributed > 0 {
+            folded += &format!("builder {}\n", self.unattributed);
+        }
+        folded
+    }
+}
+
+#[cfg(test)]
+mod profile_tests {
+    use super::*;
+    use crate::{compiler, parser};
+
+    #[test]
+    fn profile_test() {
+        let input = "pub x: F;\n\
+                     let y = x * x;\n\
+                     out a = poseidon(y, y)[0];\n\
+                     out b = y + 1;";
+        let program = compiler::compile(parser::parse(input).unwrap()).unwrap();
+        let profile = profile(program);
+        assert_eq!(
+            profile.lines.values().sum::<usize>() + profile.unattributed,
+            profile.total
+        );
+        // the square is shared between the outputs, and charged to the first
+        assert!(profile.stacks.contains_key("a;line 2;mul"));
+        assert!(profile.stacks.contains_key("a;line 3;poseidon"));
+        assert!(profile.stacks.contains_key("b;line 4;add"));
+        let text = profile.format_text(input);
+        assert!(text.contains("out a = poseidon(y, y)[0];"));
+        assert!(profile.format_folded().contains("a;line 3;poseidon "));
+    }
+}
@@ -1,46 +1,108 @@
 use std::collections::HashMap;
 
-use crate::ast::{Ident, Literal};
+use crate::ast::{Ident, Literal, Ty};
+use crate::backend::Backend;
 use crate::compiler::CompiledProgram;
+use crate::plonk::backend::{Plonky2Backend, Plonky2Proof, SerializedProof};
 use crate::plonk::circuit_builder::ProvableCircuit;
-use crate::plonk::circuit_builder::{build_circuit, from_literal};
+use crate::plonk::circuit_builder::{build_circuit, flatten_literal};
 use crate::plonk::parameters::*;
-use anyhow::Result;
-use plonky2::iop::witness::{PartialWitness, WitnessWrite};
-use plonky2::plonk::circuit_data::CircuitData;
+use anyhow::{anyhow, Result};
+use plonky2::field::types::Field64;
 
-pub fn prove<A>(
+pub fn prove<A, B: Backend>(
+    backend: B,
     initital_context: HashMap<Ident, Literal>,
     program: CompiledProgram<A>,
-) -> Result<ProvingData> {
-    let mut circuit = build_circuit(program);
-    let (pw, inputs) = set_public_inputs(&mut circuit, &initital_context);
-    let data = circuit.builder.build::<C>();
-    Ok(ProvingData { data, pw, inputs })
+) -> Result<ProvingData<B>> {
+    let inputs = public_input_names(&program.public_vars);
+    let circuit = build_circuit(program, backend);
+    let witness = set_inputs(&circuit, &initital_context)?;
+    let outputs = circuit
+        .outputs
+        .iter()
+        .map(|(ident, _)| ident.clone())
+        .collect();
+    let proof = circuit.backend.prove(witness)?;
+    Ok(ProvingData {
+        proof,
+        inputs,
+        outputs,
+    })
 }
 
-pub struct ProvingData {
-    pub data: CircuitData<F, C, 2>,
-    pub pw: PartialWitness<F>,
-    pub inputs: Vec<Ident>,
+// read back a plonky2 proof of the program, the circuit is rebuilt with the config the proof
+// was made with
+pub fn load_proof<A>(
+    program: CompiledProgram<A>,
+    serialized: SerializedProof,
+) -> Result<Plonky2Proof> {
+    let backend = Plonky2Backend::new(serialized.config)?;
+    build_circuit(program, backend)
+        .backend
+        .load(serialized.proof)
+}
+
+// the names of the public values standing for the public inputs. They are registered in
+// declaration order, followed by the outputs, and arrays are registered element by element.
+pub fn public_input_names(public_vars: &[(Ident, Ty)]) -> Vec<String> {
+    public_vars
+        .iter()
+        .flat_map(|(ident, ty)| match ty {
+            Ty::Array(_, _) => (0..ty.num_elements())
+                .map(|i| format!("{}[{}]", ident, i))
+                .collect(),
+            _ => vec![ident.to_string()],
+        })
+        .collect()
+}
+
+pub struct ProvingData<B: Backend> {
+    pub proof: B::Proof,
+    pub inputs: Vec<String>,
+    pub outputs: Vec<Ident>,
+}
+
+// the circuit can only receive field elements, so larger integers can't be inputs
+pub fn fits_in_field(value: &Literal) -> bool {
+    match value {
+        Literal::U64(n) => *n < F::ORDER,
+        Literal::Array(elems) => elems.iter().all(fits_in_field),
+        _ => true,
+    }
 }
 
 // We need to guarantee that the variables delclared initial context are the same as what
-// we declared as public inputs in the circuit.
-fn set_public_inputs(
-    circuit: &mut ProvableCircuit,
+// we declared as inputs in the circuit.
+pub fn set_inputs<B: Backend>(
+    circuit: &ProvableCircuit<B>,
     initital_context: &HashMap<Ident, Literal>,
-) -> (PartialWitness<F>, Vec<Ident>) {
-    let mut pw = PartialWitness::<F>::new();
-    let mut inputs = Vec::new();
+) -> Result<Vec<(B::Target, F)>> {
+    let mut witness = Vec::new();
     for (ident, value) in initital_context {
-        let target = match circuit.public_inputs.get(ident) {
-            Some(target) => *target,
-            None => panic!("Public input {} not found in circuit", ident),
+        if !fits_in_field(value) {
+            return Err(anyhow!(
+                "Input {} = {} does not fit in the field",
+                ident,
+                value
+            ));
+        }
+        let targets = match circuit.public_inputs.get(ident) {
+            Some(targets) => targets.clone(),
+            None => match circuit.private_inputs.get(ident) {
+                Some(targets) => targets.clone(),
+                None => return Err(anyhow!("{} is not an input of the program", ident)),
+            },
         };
-        let val = from_literal(*value);
-        pw.set_target(target, val);
-        inputs.push(ident.clone());
+        let values = flatten_literal(value.clone());
+        if values.len() != targets.len() {
+            return Err(anyhow!(
+                "Input {} = {} does not match its declared type",
+                ident,
+                value
+            ));
+        }
+        witness.extend(targets.into_iter().zip(values));
     }
-    (pw, inputs)
+    Ok(witness)
 }
@@ -0,0 +1,189 @@
+use crate::compiler::CompiledProgram;
+use crate::plonk::backend::Plonky2Backend;
+use crate::plonk::circuit_builder::build_circuit;
+use crate::plonk::config::{HashConfig, ProofConfig};
+use crate::plonk::parameters::*;
+use anyhow::Result;
+use plonky2::field::types::PrimeField64;
+use plonky2::plonk::circuit_data::CircuitData;
+use plonky2::plonk::config::{GenericConfig, KeccakGoldilocksConfig, PoseidonGoldilocksConfig};
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::time::Duration;
+
+// rough costs of proving on a laptop, the estimates
+// are only meant to tell a circuit that proves in milliseconds from one that takes minutes
+const NANOS_PER_CELL: u64 = 300;
+// the LDEs of the wires, plus about as much again for the partial products and quotient
+const LDE_COPIES: usize = 2;
+
+// padding rows are filled with this gate
+const NOOP_GATE: &str = "NoopGate";
+
+pub struct GateType {
+    pub id: String,
+    pub degree: usize,
+    pub num_constraints: usize,
+    // the rows using the gate, after padding
+    pub instances: usize,
+}
+
+pub struct CircuitStats {
+    // the gates added by each kind of IR operation, most expensive first
+    pub rows_by_op: Vec<(&'static str, usize)>,
+    pub gate_types: Vec<GateType>,
+    pub zero_knowledge: bool,
+    // the gates in the circuit before padding, including those added when building
+    pub rows: usize,
+    pub degree_bits: usize,
+    pub num_wires: usize,
+    pub num_public_inputs: usize,
+    pub estimated_time: Duration,
+    pub estimated_memory: usize,
+}
+
+// each row has one gate, the selector polynomials hold the index of the gate of the row in
+// `common.gates`, or a value past the gates in the rows of the other selector groups
+fn gate_instances<C: GenericConfig<D, F = F>>(data: &CircuitData<F, C, D>) -> Vec<usize> {
+    let mut instances = vec![0; data.common.gates.len()];
+    let num_selectors = data.common.selectors_info.num_selectors();
+    let selectors = &data.prover_only.constants_sigmas_commitment.polynomials[..num_selectors];
+    for selector in selectors {
+        for value in selector.clone().fft().values {
+            if let Some(count) = instances.get_mut(value.to_canonical_u64() as usize) {
+                *count += 1;
+            }
+        }
+    }
+    instances
+}
+
+pub fn stats<A>(program: CompiledProgram<A>, config: &ProofConfig) -> Result<CircuitStats> {
+    let ops: Vec<&'static str> = program
+        .ir
+        .instructions
+        .iter()
+        .map(|instruction| instruction.op.name())
+        .collect();
+    let circuit = build_circuit(program, Plonky2Backend::new(config.clone())?);
+    let mut rows_by_op: HashMap<&'static str, usize> = HashMap::new();
+    for (op, rows) in ops.into_iter().zip(circuit.instruction_rows) {
+        *rows_by_op.entry(op).or_insert(0) += rows;
+    }
+    let mut rows_by_op: Vec<(&'static str, usize)> = rows_by_op.into_iter().collect();
+    rows_by_op.sort_by(|(op1, rows1), (op2, rows2)| rows2.cmp(rows1).then(op1.cmp(op2)));
+    let builder = circuit.backend.builder;
+    // the hash of the config is a type parameter of plonky2
+    Ok(match config.hash {
+        HashConfig::Poseidon => {
+            circuit_stats(rows_by_op, builder.build::<PoseidonGoldilocksConfig>())
+        }
+        HashConfig::Keccak => circuit_stats(rows_by_op, builder.build::<KeccakGoldilocksConfig>()),
+    })
+}
+
+fn circuit_stats<C: GenericConfig<D, F = F>>(
+    rows_by_op: Vec<(&'static str, usize)>,
+    data: CircuitData<F, C, D>,
+) -> CircuitStats {
+    let common = &data.common;
+    let gate_types: Vec<GateType> = common
+        .gates
+        .iter()
+        .zip(gate_instances(&data))
+        .map(|(gate, instances)| GateType {
+            id: gate.0.id(),
+            degree: gate.0.degree(),
+            num_constraints: gate.0.num_constraints(),
+            instances,
+        })
+        .collect();
+    let rows = gate_types
+        .iter()
+        .filter(|gate| gate.id != NOOP_GATE)
+        .map(|gate| gate.instances)
+        .sum();
+    let num_wires = common.config.num_wires;
+    let cells = (common.degree() * num_wires) as u64;
+    CircuitStats {
+        rows_by_op,
+        gate_types,
+        rows,
+        degree_bits: common.degree_bits(),
+        num_wires,
+        num_public_inputs: common.num_public_inputs,
+        estimated_time: Duration::from_nanos(cells * NANOS_PER_CELL),
+        estimated_memory: common.lde_size() * num_wires * std::mem::size_of::<F>() * LDE_COPIES,
+        zero_knowledge: common.config.zero_knowledge,
+    }
+}
+
+impl Display for CircuitStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "rows: {}", self.rows)?;
+        writeln!(
+            f,
+            "degree bits: {} ({} rows after padding)",
+            self.degree_bits,
+            1usize << self.degree_bits
+        )?;
+        writeln!(f, "wires: {}", self.num_wires)?;
+        writeln!(f, "zero knowledge: {}", self.zero_knowledge)?;
+        writeln!(f, "public inputs: {}", self.num_public_inputs)?;
+        writeln!(f, "rows by operation:")?;
+        for (op, rows) in &self.rows_by_op {
+            writeln!(f, "  {}: {}", op, rows)?;
+        }
+        writeln!(f, "gate types:")?;
+        for gate in &self.gate_types {
+            writeln!(
+                f,
+                "  {}: {} (degree {}, {} constraints)",
+                gate.id, gate.instances, gate.degree, gate.num_constraints
+            )?;
+        }
+        writeln!(f, "estimated proving time: {:?}", self.estimated_time)?;
+        writeln!(
+            f,
+            "estimated proving memory: {:.1} MiB",
+            self.estimated_memory as f64 / (1024.0 * 1024.0)
+        )
+    }
+}
+
+#[cfg(test)]
+mod stats_tests {
+    use super::*;
+    use crate::{compiler, parser};
+
+    #[test]
+    fn stats_test() {
+        let program = "pub x: F; pub y: F; out a = x * y + 1; out h = poseidon(x, y)[0];";
+        let program = compiler::compile(parser::parse(program).unwrap()).unwrap();
+        let stats = stats(program, &ProofConfig::default()).unwrap();
+        assert_eq!(stats.num_public_inputs, 4);
+        let total: usize = stats.rows_by_op.iter().map(|(_, rows)| rows).sum();
+        assert!(total <= stats.rows);
+        assert!(stats.rows <= 1 << stats.degree_bits);
+        assert!(stats.rows_by_op.iter().any(|(op, _)| *op == "poseidon"));
+        let instances: usize = stats.gate_types.iter().map(|gate| gate.instances).sum();
+        assert_eq!(instances, 1 << stats.degree_bits);
+        assert!(stats.gate_types.iter().any(|gate| gate.instances > 0));
+        assert!(!stats.zero_knowledge);
+    }
+
+    #[test]
+    fn config_test() {
+        let program = "pub x: F; pub y: F; x * y";
+        let program = compiler::compile(parser::parse(program).unwrap()).unwrap();
+        let config = ProofConfig {
+            zero_knowledge: true,
+            hash: HashConfig::Keccak,
+            num_wires: 160,
+            ..ProofConfig::default()
+        };
+        let stats = stats(program, &config).unwrap();
+        assert_eq!(stats.num_wires, 160);
+        assert!(stats.zero_knowledge);
+    }
+}
@@ -0,0 +1,444 @@
+use crate::ast::{Builtin, Ty};
+use crate::backend::Backend;
+use crate::plonk::parameters::*;
+use plonky2::field::types::Field;
+
+// Unsigned integers are kept in the circuit as little-endian limbs of at most `LIMB_BITS`
+// bits. Sums and products of limbs are then far below the field order, so limb arithmetic
+// never wraps around and carries can be recovered exactly with `split_low_high`.
+pub const LIMB_BITS: usize = 16;
+
+#[derive(Debug, Clone)]
+pub struct UIntTarget<T> {
+    pub ty: Ty,
+    pub limbs: Vec<T>,
+}
+
+fn limb_bits(ty: &Ty) -> usize {
+    ty.uint_bits()
+        .expect("Expected an unsigned integer type")
+        .min(LIMB_BITS)
+}
+
+fn num_limbs(ty: &Ty) -> usize {
+    ty.uint_bits().expect("Expected an unsigned integer type") / limb_bits(ty)
+}
+
+// the number of extra bits needed to hold a sum of `n` limb products
+fn log2_ceil(n: usize) -> usize {
+    n.next_power_of_two().trailing_zeros() as usize
+}
+
+// the values of the limbs of `n` as an integer of type `ty`
+pub fn limb_values(ty: &Ty, n: u64) -> Vec<F> {
+    let bits = limb_bits(ty);
+    let mask = (1u64 << bits) - 1;
+    (0..num_limbs(ty))
+        .map(|i| F::from_canonical_u64((n >> (i * bits)) & mask))
+        .collect()
+}
+
+impl<T: Copy> UIntTarget<T> {
+    pub fn constant(builder: &mut impl Backend<Target = T>, ty: Ty, n: u64) -> Self {
+        let limbs = limb_values(&ty, n)
+            .into_iter()
+            .map(|limb| builder.constant(limb))
+            .collect();
+        UIntTarget { ty, limbs }
+    }
+
+    // decompose a field element into limbs, the circuit is unsatisfiable if the
+    // (canonical) value of `x` does not fit in `ty`.
+    pub fn from_field(builder: &mut impl Backend<Target = T>, ty: Ty, x: T) -> Self {
+        let bits = limb_bits(&ty);
+        let n = num_limbs(&ty);
+        let mut limbs = Vec::with_capacity(n);
+        let mut rest = x;
+        for i in 0..n - 1 {
+            let (low, high) = builder.split_low_high(rest, bits, bits * (n - i));
+            limbs.push(low);
+            rest = high;
+        }
+        if n == 1 {
+            builder.range_check(rest, bits);
+        }
+        limbs.push(rest);
+        // 64 bit limbs can also spell out `x + p`, so we insist on the canonical
+        // decomposition: if the high 32 bits are all set the low 32 bits must be zero.
+        if ty.uint_bits() == Some(64) {
+            let shift = F::from_canonical_u64(1 << LIMB_BITS);
+            let low = builder.mul_const_add(shift, limbs[1], limbs[0]);
+            let high = builder.mul_const_add(shift, limbs[3], limbs[2]);
+            let max = builder.constant(F::from_canonical_u32(u32::MAX));
+            let high_is_max = builder.is_equal(high, max);
+            let overflow = builder.mul(high_is_max, low);
+            builder.assert_zero(overflow);
+        }
+        UIntTarget { ty, limbs }
+    }
+
+    // recombine the limbs into a single field element. For `u64` values above the field
+    // order this reduces the value modulo the order.
+    pub fn to_field(&self, builder: &mut impl Backend<Target = T>) -> T {
+        let shift = F::from_canonical_u64(1 << limb_bits(&self.ty));
+        let mut limbs = self.limbs.iter().rev();
+        let top = *limbs.next().expect("Expected at least one limb");
+        limbs.fold(top, |acc, limb| builder.mul_const_add(shift, acc, *limb))
+    }
+
+    pub fn add(&self, builder: &mut impl Backend<Target = T>, rhs: &Self, checked: bool) -> Self {
+        let bits = limb_bits(&self.ty);
+        let mut limbs = Vec::with_capacity(self.limbs.len());
+        let mut carry: Option<T> = None;
+        for (a, b) in self.limbs.iter().zip(rhs.limbs.iter()) {
+            let sum = builder.add(*a, *b);
+            let sum = match carry {
+                Some(carry) => builder.add(sum, carry),
+                None => sum,
+            };
+            let (low, high) = builder.split_low_high(sum, bits, bits + 1);
+            limbs.push(low);
+            carry = Some(high);
+        }
+        if checked {
+            builder.assert_zero(carry.expect("Expected at least one limb"));
+        }
+        UIntTarget {
+            ty: self.ty.clone(),
+            limbs,
+        }
+    }
+
+    pub fn sub(&self, builder: &mut impl Backend<Target = T>, rhs: &Self, checked: bool) -> Self {
+        let bits = limb_bits(&self.ty);
+        let base = F::from_canonical_u64(1 << bits);
+        let mut limbs = Vec::with_capacity(self.limbs.len());
+        // `no_borrow` is 1 when the previous limb did not need to borrow from this one
+        let mut no_borrow: Option<T> = None;
+        for (a, b) in self.limbs.iter().zip(rhs.limbs.iter()) {
+            let diff = match no_borrow {
+                Some(no_borrow) => {
+                    let diff = builder.add_const(*a, base - F::ONE);
+                    builder.add(diff, no_borrow)
+                }
+                None => builder.add_const(*a, base),
+            };
+            let diff = builder.sub(diff, *b);
+            let (low, high) = builder.split_low_high(diff, bits, bits + 1);
+            limbs.push(low);
+            no_borrow = Some(high);
+        }
+        if checked {
+            builder.assert_one(no_borrow.expect("Expected at least one limb"));
+        }
+        UIntTarget {
+            ty: self.ty.clone(),
+            limbs,
+        }
+    }
+
+    // schoolbook multiplication, column by column
+    pub fn mul(&self, builder: &mut impl Backend<Target = T>, rhs: &Self, checked: bool) -> Self {
+        let bits = limb_bits(&self.ty);
+        let n = self.limbs.len();
+        let column_bits = 2 * bits + log2_ceil(n) + 1;
+        let mut limbs = Vec::with_capacity(n);
+        let mut carry: Option<T> = None;
+        for column in 0..n {
+            let mut terms: Vec<T> = (0..=column)
+                .map(|i| builder.mul(self.limbs[i], rhs.limbs[column - i]))
+                .collect();
+            terms.extend(carry);
+            let sum = builder.add_many(terms);
+            let (low, high) = builder.split_low_high(sum, bits, column_bits);
+            limbs.push(low);
+            carry = Some(high);
+        }
+        if checked {
+            // every term feeding the high half of the product is non-negative and small,
+            // so the high half is zero exactly when their sum is.
+            let mut overflow: Vec<T> = carry.into_iter().collect();
+            for i in 1..n {
+                for j in (n - i)..n {
+                    overflow.push(builder.mul(self.limbs[i], rhs.limbs[j]));
+                }
+            }
+            let overflow = builder.add_many(overflow);
+            builder.assert_zero(overflow);
+        }
+        UIntTarget {
+            ty: self.ty.clone(),
+            limbs,
+        }
+    }
+
+    pub fn is_equal(&self, builder: &mut impl Backend<Target = T>, rhs: &Self) -> T {
+        let mut result = builder.one();
+        for (a, b) in self.limbs.iter().zip(rhs.limbs.iter()) {
+            let eq = builder.is_equal(*a, *b);
+            result = builder.and(result, eq);
+        }
+        result
+    }
+
+    pub fn select(&self, builder: &mut impl Backend<Target = T>, cond: T, rhs: &Self) -> Self {
+        let limbs = self
+            .limbs
+            .iter()
+            .zip(rhs.limbs.iter())
+            .map(|(a, b)| builder.select(cond, *a, *b))
+            .collect();
+        UIntTarget {
+            ty: self.ty.clone(),
+            limbs,
+        }
+    }
+
+    // the little-endian bits of the value. The limbs are range checked, so the
+    // decomposition is unique.
+    pub fn to_bits(&self, builder: &mut impl Backend<Target = T>) -> Vec<T> {
+        let bits = limb_bits(&self.ty);
+        self.limbs
+            .iter()
+            .flat_map(|limb| builder.split_le(*limb, bits))
+            .collect()
+    }
+
+    pub fn from_bits(builder: &mut impl Backend<Target = T>, ty: Ty, bits: &[T]) -> Self {
+        let limbs = bits
+            .chunks(limb_bits(&ty))
+            .map(|chunk| builder.le_sum(chunk))
+            .collect();
+        UIntTarget { ty, limbs }
+    }
+
+    pub fn bitwise(
+        &self,
+        builder: &mut impl Backend<Target = T>,
+        rhs: &Self,
+        func: Builtin,
+    ) -> Self {
+        let lhs_bits = self.to_bits(builder);
+        let rhs_bits = rhs.to_bits(builder);
+        let bits: Vec<T> = lhs_bits
+            .into_iter()
+            .zip(rhs_bits)
+            .map(|(a, b)| match func {
+                Builtin::BitAnd => builder.and(a, b),
+                Builtin::BitOr => builder.or(a, b),
+                // a ^ b = a + b - 2ab
+                Builtin::BitXor => {
+                    let sum = builder.add(a, b);
+                    let prod = builder.mul(a, b);
+                    builder.mul_const_add(-F::TWO, prod, sum)
+                }
+                func => unreachable!("{} is not a bitwise operation", func),
+            })
+            .collect();
+        Self::from_bits(builder, self.ty.clone(), &bits)
+    }
+
+    // logical shift by a variable amount, done as a barrel shifter over the bits of
+    // `amount`. Shifting by the width or more gives zero.
+    pub fn shift(&self, builder: &mut impl Backend<Target = T>, amount: &Self, left: bool) -> Self {
+        let mut bits = self.to_bits(builder);
+        let width = bits.len();
+        let amount_bits = amount.to_bits(builder);
+        let stages = width.trailing_zeros() as usize;
+        let zero = builder.zero();
+        for (stage, cond) in amount_bits.iter().take(stages).enumerate() {
+            let shift = 1 << stage;
+            let shifted: Vec<T> = (0..width)
+                .map(|i| {
+                    let from = if left {
+                        i.checked_sub(shift)
+                    } else {
+                        Some(i + shift).filter(|j| *j < width)
+                    };
+                    from.map_or(zero, |j| bits[j])
+                })
+                .collect();
+            bits = shifted
+                .into_iter()
+                .zip(bits.iter())
+                .map(|(s, b)| builder.select(*cond, s, *b))
+                .collect();
+        }
+        // any of the remaining bits of the amount shifts everything out
+        let overflow = amount_bits
+            .iter()
+            .skip(stages)
+            .fold(zero, |acc, bit| builder.or(acc, *bit));
+        let keep = builder.not(overflow);
+        let bits: Vec<T> = bits.into_iter().map(|b| builder.and(b, keep)).collect();
+        Self::from_bits(builder, self.ty.clone(), &bits)
+    }
+
+    // convert to another unsigned integer type, the circuit is unsatisfiable if the
+    // value does not fit.
+    pub fn resize(&self, builder: &mut impl Backend<Target = T>, ty: Ty) -> Self {
+        let n = num_limbs(&ty);
+        let mut limbs: Vec<T> = self.limbs.iter().take(n).cloned().collect();
+        for limb in self.limbs.iter().skip(n) {
+            builder.assert_zero(*limb);
+        }
+        if limb_bits(&ty) < limb_bits(&self.ty) {
+            builder.range_check(limbs[0], limb_bits(&ty));
+        }
+        while limbs.len() < n {
+            limbs.push(builder.zero());
+        }
+        UIntTarget { ty, limbs }
+    }
+}
+
+#[cfg(test)]
+mod uint_tests {
+    use super::*;
+    use crate::backend::ConstraintChecker;
+    use crate::plonk::backend::Plonky2Backend;
+    use plonky2::field::types::Field64;
+
+    // build a circuit applying `op` to two inputs of type `ty` and check whether it can be
+    // proven with the output `expected` (or not at all when `expected` is `None`)
+    fn check_binop<B: Backend>(
+        mut builder: B,
+        ty: Ty,
+        lhs: u64,
+        rhs: u64,
+        expected: Option<u64>,
+        op: impl Fn(&mut B, &UIntTarget<B::Target>, &UIntTarget<B::Target>) -> UIntTarget<B::Target>,
+    ) {
+        let x = builder.input();
+        let y = builder.input();
+        let a = UIntTarget::from_field(&mut builder, ty.clone(), x);
+        let b = UIntTarget::from_field(&mut builder, ty.clone(), y);
+        let out = op(&mut builder, &a, &b).to_field(&mut builder);
+        builder.public_output(out);
+        let inputs = vec![
+            (x, F::from_noncanonical_u64(lhs)),
+            (y, F::from_noncanonical_u64(rhs)),
+        ];
+        match (builder.prove(inputs), expected) {
+            (Ok(proof), Some(expected)) => {
+                assert_eq!(
+                    B::public_values(&proof)[0],
+                    F::from_noncanonical_u64(expected)
+                )
+            }
+            (Err(_), None) => (),
+            (Ok(_), None) => panic!("Expected {} to overflow", ty),
+            (Err(e), Some(_)) => panic!("Expected a proof, got {}", e),
+        }
+    }
+
+    // check the operation with plonky2 and with the constraint checker
+    macro_rules! check_binop {
+        ($ty:expr, $lhs:expr, $rhs:expr, $expected:expr, $op:expr) => {
+            check_binop(Plonky2Backend::default(), $ty, $lhs, $rhs, $expected, $op);
+            check_binop(
+                ConstraintChecker::default(),
+                $ty,
+                $lhs,
+                $rhs,
+                $expected,
+                $op,
+            );
+        };
+    }
+
+    // the operations under test, generic so that both backends can run them
+    type Target<B> = UIntTarget<<B as Backend>::Target>;
+
+    fn and<B: Backend>(b: &mut B, x: &Target<B>, y: &Target<B>) -> Target<B> {
+        x.bitwise(b, y, Builtin::BitAnd)
+    }
+
+    fn or<B: Backend>(b: &mut B, x: &Target<B>, y: &Target<B>) -> Target<B> {
+        x.bitwise(b, y, Builtin::BitOr)
+    }
+
+    fn xor<B: Backend>(b: &mut B, x: &Target<B>, y: &Target<B>) -> Target<B> {
+        x.bitwise(b, y, Builtin::BitXor)
+    }
+
+    fn shl<B: Backend>(b: &mut B, x: &Target<B>, y: &Target<B>) -> Target<B> {
+        x.shift(b, y, true)
+    }
+
+    fn shr<B: Backend>(b: &mut B, x: &Target<B>, y: &Target<B>) -> Target<B> {
+        x.shift(b, y, false)
+    }
+
+    #[test]
+    fn u8_boundary_test() {
+        check_binop!(Ty::U8, 200, 55, Some(255), |b, x, y| x.add(b, y, true));
+        check_binop!(Ty::U8, 200, 56, None, |b, x, y| x.add(b, y, true));
+        check_binop!(Ty::U8, 200, 56, Some(0), |b, x, y| x.add(b, y, false));
+        check_binop!(Ty::U8, 3, 4, None, |b, x, y| x.sub(b, y, true));
+        check_binop!(Ty::U8, 3, 4, Some(255), |b, x, y| x.sub(b, y, false));
+        check_binop!(Ty::U8, 16, 16, None, |b, x, y| x.mul(b, y, true));
+        check_binop!(Ty::U8, 15, 17, Some(255), |b, x, y| x.mul(b, y, true));
+        check_binop!(Ty::U8, 256, 0, None, |b, x, y| x.add(b, y, false));
+    }
+
+    #[test]
+    fn u32_boundary_test() {
+        let max = u32::MAX as u64;
+        check_binop!(Ty::U32, max, 0, Some(max), |b, x, y| x.add(b, y, true));
+        check_binop!(Ty::U32, max, 1, None, |b, x, y| x.add(b, y, true));
+        check_binop!(Ty::U32, max, 1, Some(0), |b, x, y| x.add(b, y, false));
+        check_binop!(Ty::U32, 0x1_0000, 0xffff, Some(max - 0xffff), |b, x, y| {
+            x.mul(b, y, true)
+        });
+        check_binop!(Ty::U32, 0x1_0000, 0x1_0000, None, |b, x, y| {
+            x.mul(b, y, true)
+        });
+        check_binop!(Ty::U32, 0x1_0000, 0x1_0000, Some(0), |b, x, y| {
+            x.mul(b, y, false)
+        });
+        check_binop!(Ty::U32, 0, 1, Some(max), |b, x, y| x.sub(b, y, false));
+    }
+
+    #[test]
+    fn u64_boundary_test() {
+        let order = F::ORDER;
+        let max = order - 1;
+        check_binop!(Ty::U64, max, 0, Some(max), |b, x, y| x.add(b, y, true));
+        check_binop!(Ty::U64, 1 << 32, 1 << 31, Some(1 << 63), |b, x, y| {
+            x.mul(b, y, true)
+        });
+        check_binop!(Ty::U64, 1 << 32, 1 << 32, None, |b, x, y| x.mul(b, y, true));
+        check_binop!(Ty::U64, 1 << 32, 1 << 32, Some(0), |b, x, y| {
+            x.mul(b, y, false)
+        });
+        check_binop!(Ty::U64, 0, 1, Some(u64::MAX), |b, x, y| x.sub(b, y, false));
+        check_binop!(Ty::U64, 0, 1, None, |b, x, y| x.sub(b, y, true));
+    }
+
+    #[test]
+    fn bitwise_test() {
+        let max = u64::MAX >> 1;
+        check_binop!(Ty::U8, 0b1100, 0b1010, Some(0b1000), and);
+        check_binop!(Ty::U8, 0b1100, 0b1010, Some(0b1110), or);
+        check_binop!(Ty::U8, 0xff, 0x0f, Some(0xf0), xor);
+        check_binop!(Ty::U32, 0xffff_0000, 0x00ff_ff00, Some(0x00ff_0000), and);
+        check_binop!(Ty::U32, 0xffff_0000, 0x0000_ffff, Some(0xffff_ffff), or);
+        check_binop!(Ty::U64, max, 1 << 62, Some(1 << 62), and);
+        check_binop!(Ty::U64, max, max, Some(0), xor);
+    }
+
+    #[test]
+    fn shift_test() {
+        check_binop!(Ty::U8, 0b1011, 4, Some(0b1011_0000), shl);
+        check_binop!(Ty::U8, 0xff, 7, Some(0x80), shl);
+        check_binop!(Ty::U8, 0xff, 8, Some(0), shl);
+        check_binop!(Ty::U8, 0xff, 200, Some(0), shr);
+        check_binop!(Ty::U32, 1, 31, Some(1 << 31), shl);
+        check_binop!(Ty::U32, 1 << 31, 31, Some(1), shr);
+        check_binop!(Ty::U32, 1, 32, Some(0), shl);
+        check_binop!(Ty::U64, 1, 62, Some(1 << 62), shl);
+        check_binop!(Ty::U64, 1 << 62, 62, Some(1), shr);
+        check_binop!(Ty::U64, 1 << 62, 64, Some(0), shr);
+    }
+}
@@ -0,0 +1,161 @@
+use crate::ast::annotation::{HasSourceLoc, Span};
+use crate::ast::program::RESULT;
+use crate::ast::{Expr, Ident, Literal, Program};
+use crate::interpreter::{interpret_program_with, Observer, Value};
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+
+// an evaluated expression with the evaluations of its subexpressions
+#[derive(Debug, Serialize)]
+pub struct TraceNode {
+    pub span: Span,
+    pub expr: String,
+    // none when the evaluation failed
+    pub value: Option<Value>,
+    pub children: Vec<TraceNode>,
+}
+
+// every expression evaluated while interpreting a program
+#[derive(Debug, Default, Serialize)]
+pub struct Trace {
+    // the evaluation of each binding in the order they were evaluated, the final expression
+    // comes last
+    pub bindings: Vec<(Ident, TraceNode)>,
+    // the expressions being evaluated, innermost last
+    #[serde(skip)]
+    stack: Vec<TraceNode>,
+    #[serde(skip)]
+    binding: Option<Ident>,
+}
+
+impl Observer for Trace {
+    fn binding(&mut self, name: &Ident) {
+        self.binding = Some(name.clone());
+    }
+
+    fn enter<A: Clone + HasSourceLoc>(&mut self, expr: &Expr<A>) -> Result<()> {
+        self.stack.push(TraceNode {
+            span: expr.source_loc(),
+            expr: expr.format(),
+            value: None,
+            children: Vec::new(),
+        });
+        Ok(())
+    }
+
+    fn exit<A: Clone + HasSourceLoc>(&mut self, _expr: &Expr<A>, result: &Result<Value>) {
+        let mut node = self.stack.pop().unwrap();
+        node.value = result.as_ref().ok().cloned();
+        match self.stack.last_mut() {
+            Some(parent) => parent.children.push(node),
+            None => {
+                let name = self.binding.clone().unwrap_or_else(|| Ident::new(RESULT));
+                self.bindings.push((name, node));
+            }
+        }
+    }
+}
+
+impl Trace {
+    // trace the program on the inputs, the trace is returned even when the interpreter fails
+    // and ends with the expression that failed
+    pub fn of_program<A: Clone + Default + HasSourceLoc>(
+        program: &Program<A>,
+        inputs: &HashMap<Ident, Literal>,
+    ) -> (Trace, Result<()>) {
+        let mut trace = Trace::default();
+        let result = interpret_program_with(program, inputs, &mut trace).map(|_| ());
+        (trace, result)
+    }
+}
+
+impl TraceNode {
+    fn fmt_indented(&self, f: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+        let value = match &self.value {
+            Some(value) => format!("{:?}", value),
+            None => "failed".to_string(),
+        };
+        writeln!(
+            f,
+            "{:indent$}{} => {}    at {}",
+            "",
+            self.expr,
+            value,
+            self.span,
+            indent = 2 * depth
+        )?;
+        for child in &self.children {
+            child.fmt_indented(f, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+// an indented evaluation tree, one line per expression
+impl Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, node) in &self.bindings {
+            writeln!(f, "{}:", name)?;
+            node.fmt_indented(f, 1)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod trace_tests {
+    use super::*;
+    use crate::parser;
+    use crate::plonk::F;
+    use plonky2::field::types::Field;
+
+    fn trace(input: &str, inputs: Vec<(&str, Literal)>) -> (Trace, Result<()>) {
+        let inputs: HashMap<Ident, Literal> = inputs
+            .into_iter()
+            .map(|(name, value)| (Ident::new(name), value))
+            .collect();
+        let program: Program<Span> = parser::parse(input).unwrap();
+        Trace::of_program(&program, &inputs)
+    }
+
+    #[test]
+    fn tree_test() {
+        let (trace, result) = trace(
+            "pub x: F;\nlet a = x * x;\na + 1",
+            vec![("x", Literal::Field(3))],
+        );
+        result.unwrap();
+        assert_eq!(
+            trace.to_string(),
+            "a:\n  \
+               (x * x) => Field(9)    at line 2, columns 11-12\n    \
+                 x => Field(3)    at line 2, columns 9-10\n    \
+                 x => Field(3)    at line 2, columns 13-14\n\
+             result:\n  \
+               (a + 1) => Field(10)    at line 3, columns 3-4\n    \
+                 a => Field(9)    at line 3, columns 1-2\n    \
+                 1 => Field(1)    at line 3, columns 5-6\n"
+        );
+        let (_, node) = &trace.bindings[1];
+        assert_eq!(node.value, Some(Value::Field(F::from_canonical_u32(10))));
+        assert_eq!(node.span.line(), 3);
+    }
+
+    #[test]
+    fn error_test() {
+        let program = "pub x: u8;\nlet a = x *% 2u8;\nlet b = a + 200u8;\nb";
+        let (trace, result) = trace(program, vec![("x", Literal::U8(50))]);
+        assert!(result.is_err());
+        // the failing binding is traced up to the overflow, and again where it is used
+        assert_eq!(trace.bindings.len(), 3);
+        let (name, node) = &trace.bindings[1];
+        assert_eq!(name, &Ident::new("b"));
+        assert_eq!(node.value, None);
+        assert_eq!(node.children[0].value, Some(Value::U8(100)));
+        let (name, node) = &trace.bindings[2];
+        assert_eq!(name, &Ident::new(RESULT));
+        assert_eq!(node.value, None);
+    }
+}
@@ -0,0 +1,172 @@
+use crate::ast::annotation::{HasSourceLoc, Span};
+use crate::ast::{Ident, Literal, Program, Ty};
+use crate::compiler::CompiledProgram;
+use crate::interpreter::{interpret_circuit, interpret_program, Value};
+use crate::ir::Wire;
+use crate::plonk::prove::set_inputs;
+use crate::plonk::uint::limb_values;
+use crate::plonk::{build_circuit, Plonky2Backend, C, F};
+use anyhow::{anyhow, Result};
+use err_derive::Error;
+use plonky2::iop::generator::generate_partial_witness;
+use plonky2::iop::witness::{PartialWitness, Witness as _, WitnessWrite};
+use serde::Serialize;
+use std::collections::HashMap;
+
+#[derive(Debug, Error)]
+pub enum WitnessError {
+    #[error(
+        display = "Output {} is {} in the interpreter but {} in the IR",
+        _0,
+        _1,
+        _2
+    )]
+    OutputMismatch(Ident, String, String),
+    #[error(
+        display = "Wire {} ({}) at {} is {} in the interpreter but {} in the circuit",
+        _1,
+        _2,
+        _0,
+        _3,
+        _4
+    )]
+    Divergence(Span, Wire, String, String, String),
+    #[error(
+        display = "Wire {} ({}) at {} is not set by the witness generators",
+        _1,
+        _2,
+        _0
+    )]
+    Unsolved(Span, Wire, String),
+}
+
+// the values of a program on some inputs
+#[derive(Debug, Serialize)]
+pub struct Witness {
+    // the let-bindings and outputs of the source in the order they were evaluated, the final
+    // expression comes last
+    pub bindings: Vec<(Ident, Value)>,
+    // the value of every wire of the IR
+    pub wires: Vec<Value>,
+}
+
+// evaluate every binding of the source once, see `interpret_program`, and every wire of the
+// compiled program
+pub fn generate_witness<A: Clone + Default + HasSourceLoc>(
+    source: &Program<A>,
+    program: &CompiledProgram<A>,
+    inputs: &HashMap<Ident, Literal>,
+) -> Result<Witness> {
+    let bindings = interpret_program(source, inputs)?;
+    let wires = interpret_circuit(&program.ir, inputs)?;
+    for (name, wire) in &program.ir.outputs {
+        if let Some((_, value)) = bindings.iter().find(|(binding, _)| binding == name) {
+            if *value != wires[wire.0] {
+                return Err(anyhow!(WitnessError::OutputMismatch(
+                    name.clone(),
+                    format!("{:?}", value),
+                    format!("{:?}", wires[wire.0])
+                )));
+            }
+        }
+    }
+    Ok(Witness { bindings, wires })
+}
+
+// the field elements holding a value in the circuit, see `CircuitValue::targets`
+fn flatten_value(value: &Value) -> Vec<F> {
+    match value {
+        Value::U8(n) => limb_values(&Ty::U8, *n as u64),
+        Value::U32(n) => limb_values(&Ty::U32, *n as u64),
+        Value::U64(n) => limb_values(&Ty::U64, *n),
+        Value::Array(values) => values.iter().flat_map(flatten_value).collect(),
+        value => vec![value.to_field()],
+    }
+}
+
+// run the plonky2 witness generators on the inputs and check that every wire of the circuit
+// holds the value the interpreter computed for it
+pub fn check_witness<A: HasSourceLoc>(
+    witness: &Witness,
+    program: CompiledProgram<A>,
+    inputs: &HashMap<Ident, Literal>,
+) -> Result<()> {
+    let locations: Vec<(Span, String)> = program
+        .ir
+        .instructions
+        .iter()
+        .map(|instruction| (instruction.ann.source_loc(), instruction.op.to_string()))
+        .collect();
+    let circuit = build_circuit(program, Plonky2Backend::default());
+    let mut pw = PartialWitness::new();
+    for (target, value) in set_inputs(&circuit, inputs)? {
+        pw.set_target(target, value);
+    }
+    let data = circuit.backend.builder.build::<C>();
+    let generated = generate_partial_witness(pw, &data.prover_only, &data.common);
+    for (wire, (value, targets)) in witness.wires.iter().zip(&circuit.wires).enumerate() {
+        let (span, op) = locations[wire].clone();
+        let actual = targets
+            .targets()
+            .into_iter()
+            .map(|target| generated.try_get_target(target))
+            .collect::<Option<Vec<F>>>()
+            .ok_or_else(|| anyhow!(WitnessError::Unsolved(span, Wire(wire), op.clone())))?;
+        if actual != flatten_value(value) {
+            return Err(anyhow!(WitnessError::Divergence(
+                span,
+                Wire(wire),
+                op,
+                format!("{:?}", value),
+                format!("{:?}", actual)
+            )));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod witness_tests {
+    use super::*;
+    use crate::{compiler, parser};
+    use plonky2::field::types::Field;
+
+    fn witness_for(input: &str, inputs: Vec<(&str, Literal)>) -> Result<Witness> {
+        let inputs: HashMap<Ident, Literal> = inputs
+            .into_iter()
+            .map(|(name, value)| (Ident::new(name), value))
+            .collect();
+        let source: Program<Span> = parser::parse(input).unwrap();
+        let program = compiler::compile(source.clone()).unwrap();
+        let witness = generate_witness(&source, &program, &inputs)?;
+        check_witness(&witness, program, &inputs)?;
+        Ok(witness)
+    }
+
+    #[test]
+    fn bindings_test() {
+        let program = "pub x: F; priv y: u8; let a = x * x; let b: u8 = y *% y; \
+                       out c = a + b as F; to_bits(b, 8)[0] && c == 109";
+        let witness = witness_for(
+            program,
+            vec![("x", Literal::Field(3)), ("y", Literal::U8(10))],
+        )
+        .unwrap();
+        let bindings: HashMap<String, Value> = witness
+            .bindings
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value))
+            .collect();
+        assert_eq!(bindings.len(), 4);
+        assert_eq!(bindings["a"], Value::Field(F::from_canonical_u32(9)));
+        assert_eq!(bindings["b"], Value::U8(100));
+        assert_eq!(bindings["c"], Value::Field(F::from_canonical_u32(109)));
+        assert_eq!(bindings["result"], Value::Boolean(false));
+    }
+
+    #[test]
+    fn runtime_error_test() {
+        let program = "pub x: u8; let y = x + 200u8; y";
+        assert!(witness_for(program, vec![("x", Literal::U8(100))]).is_err());
+    }
+}
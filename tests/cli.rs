@@ -0,0 +1,106 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+// what calc prints with the arguments and the input, and whether it succeeded
+fn calc(args: &[&str], stdin: &str) -> (String, bool) {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_calc"))
+        .args(args)
+        .env_remove("RUST_BACKTRACE")
+        .env_remove("RUST_LIB_BACKTRACE")
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    (
+        String::from_utf8(output.stdout).unwrap(),
+        output.status.success(),
+    )
+}
+
+#[test]
+fn stdin_test() {
+    let source = std::fs::read_to_string("examples/poly.calc").unwrap();
+    let expected = std::fs::read_to_string("examples/poly.expected").unwrap();
+    let args = ["run", "-", "--context", "examples/poly.json"];
+    assert_eq!(calc(&args, &source), (expected, true));
+}
+
+#[test]
+fn eval_test() {
+    let args = ["eval", "pub x: F; x^2 + 1", "-D", "x=3"];
+    assert_eq!(calc(&args, ""), ("result = Field(10)\n".to_string(), true));
+    let args = [
+        "eval",
+        "pub x: u8; out y = x *% 3u8; y == 9u8",
+        "-D",
+        "x=0x3",
+    ];
+    let expected = "y = U8(9)\nresult = Boolean(true)\n".to_string();
+    assert_eq!(calc(&args, ""), (expected, true));
+}
+
+#[test]
+fn missing_file_test() {
+    let expected = "Error: No such file: missing.calc\n".to_string();
+    assert_eq!(
+        calc(&["run", "missing.calc"], ""),
+        (expected.clone(), false)
+    );
+    assert_eq!(calc(&["-i", "missing.calc"], ""), (expected, false));
+    let (output, success) = calc(&["-i", "examples/poly.calc", "-c", "missing.json"], "");
+    assert_eq!(output, "Error: No such file: missing.json\n");
+    assert!(!success);
+}
+
+#[test]
+fn trace_test() {
+    let args = [
+        "trace",
+        "examples/simple_add.calc",
+        "-c",
+        "examples/simple_add.json",
+    ];
+    let (output, success) = calc(&args, "");
+    assert!(success);
+    assert!(output.starts_with("x:\n  4 => Field(4)    at line 2, columns 9-10\n"));
+    let args = [
+        "debug",
+        "examples/poly.calc",
+        "-c",
+        "examples/poly.json",
+        "-b",
+        "4",
+    ];
+    let (output, success) = calc(&args, "p y\nc\n");
+    assert!(success);
+    assert!(output.starts_with("Stopped at line 4, columns 15-16: ((y * x) + 2)\n"));
+    assert!(output.ends_with("w = Field(17)\nresult = Field(0)\n"));
+}
+
+#[test]
+fn verify_test() {
+    let path = std::env::temp_dir().join("calc_verify_test.proof");
+    let path = path.to_str().unwrap();
+    let args = [
+        "run",
+        "examples/poly.calc",
+        "-c",
+        "examples/poly.json",
+        "--save-proof",
+        path,
+    ];
+    assert!(calc(&args, "").1);
+    // the circuit is rebuilt with the passes of the proof, not those given to verify
+    let (output, success) = calc(&["verify", "examples/poly.calc", path, "-O0"], "");
+    assert!(success);
+    assert!(output.ends_with("Verified!\n"));
+    std::fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,91 @@
+// runs calc on every program in `examples` with the `.json` or `.toml` context next to it and
+// compares what it prints with the `.expected` file next to the program. The programs in
+// `examples/errors` are expected to fail.
+//
+// Set CALC_BLESS=1 to write the `.expected` files when the output is meant to change.
+
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+fn sources(dir: &Path) -> Vec<PathBuf> {
+    let mut sources = Vec::new();
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            sources.extend(self::sources(&path));
+        } else if path.extension() == Some(OsStr::new("calc")) {
+            sources.push(path);
+        }
+    }
+    sources.sort();
+    sources
+}
+
+// what calc prints for the program, and whether it succeeded
+fn run(source: &Path) -> (String, bool) {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_calc"));
+    command.arg("--input-file").arg(source);
+    let contexts = ["json", "toml"].map(|extension| source.with_extension(extension));
+    if let Some(context) = contexts.into_iter().find(|context| context.exists()) {
+        command.arg("--context").arg(context);
+    }
+    // errors would come with a backtrace
+    let output = command
+        .env_remove("RUST_BACKTRACE")
+        .env_remove("RUST_LIB_BACKTRACE")
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .output()
+        .unwrap();
+    assert!(
+        output.stderr.is_empty(),
+        "{} printed to stderr: {}",
+        source.display(),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    (
+        String::from_utf8(output.stdout).unwrap(),
+        output.status.success(),
+    )
+}
+
+#[test]
+fn examples_test() {
+    let examples = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+    let sources = sources(&examples);
+    assert!(!sources.is_empty());
+    let bless = std::env::var_os("CALC_BLESS").is_some();
+    let mut failures = Vec::new();
+    for source in sources {
+        let name = source
+            .strip_prefix(&examples)
+            .unwrap()
+            .display()
+            .to_string();
+        let (output, success) = run(source.strip_prefix(env!("CARGO_MANIFEST_DIR")).unwrap());
+        let should_fail = name.starts_with("errors");
+        if success == should_fail {
+            failures.push(format!(
+                "{} was expected to {}:\n{}",
+                name,
+                if should_fail { "fail" } else { "succeed" },
+                output
+            ));
+        }
+        let path = source.with_extension("expected");
+        if bless {
+            fs::write(&path, &output).unwrap();
+            continue;
+        }
+        match fs::read_to_string(&path) {
+            Ok(expected) if expected == output => (),
+            Ok(expected) => failures.push(format!(
+                "{} does not match its expected output.\nExpected:\n{}\nActual:\n{}",
+                name, expected, output
+            )),
+            Err(_) => failures.push(format!("{} has no .expected file", name)),
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}